@@ -1,84 +1,81 @@
 use super::types::*;
 use crate::{core::Clerk, Runtime, Router};
-use std::collections::HashMap;
-use std::any::{TypeId, Any};
-use std::cell::UnsafeCell;
+use std::collections::{HashMap, HashSet};
+use std::any::TypeId;
+use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 use std::fmt::Debug;
 use super::processor::{Processor, SystemInput, SystemOutput};
+use super::router::RoutingErr;
+use super::control::main_ports;
+#[cfg(feature = "serde")]
+use super::snapshot::StateCodec;
 
 pub struct Builder<E: Clone + Copy + Debug + 'static>{
-    components: Vec<(TypeId, &'static str, StoredComponent<E>)>,
+    instances: Vec<Instance<E>>,
     next_component_id: usize,
     buffer_size: usize,
-    states: Vec<Box<UnsafeCell<dyn Any + Send + 'static>>>,
+    sample_rate: f32,
+    crossfade_length: usize,
+    seed: u64,
+    registry_ids: HashMap<&'static str, String>,
+    states: Vec<BoxedState>,
+    lifecycles: Vec<Lifecycle<E>>,
+    #[cfg(feature = "serde")]
+    state_slots: Vec<StateSlot>,
 }
 
 impl<E: Clone + Copy + Debug> Builder<E> {
     pub fn new() -> Self {
         Self {
-            components: Vec::new(),
-            next_component_id: 0,
+            instances: Vec::new(),
+            // ComponentId(0) and ComponentId(1) belong to the system input and output
+            next_component_id: 2,
             buffer_size: 512,
+            sample_rate: 48_000.0,
+            crossfade_length: 0,
+            seed: 0,
+            registry_ids: HashMap::new(),
             states: Vec::new(),
+            lifecycles: Vec::new(),
+            #[cfg(feature = "serde")]
+            state_slots: Vec::new(),
         }
     }
     
     pub fn add_processor<P: Processor>(
-        mut self,
+        self,
         processor: P,
         instance_name: &'static str,
     ) -> Self {
-        let component_id = ComponentId(self.next_component_id);
-        self.next_component_id += 1;
-        
-        let handle = ContextHandle {
-            component_id,
-            buffer_ids_start: BufferIdx(0), // Will be set during build
-            slot_ids_start: self.states.len()
-        };
-
-        // Create a wrapper function that calls the processor's call method
-        let component_fn = |runtime: &Runtime<E>, handle: ContextHandle| {
-            P::call(runtime, handle)
-        };
+        self.place(Instance::new(processor, instance_name))
+    }
 
-        let stored = UserComponent {
-            component: component_fn,
-            context_handle: handle,
-            field_count: P::buffers_count(),
-            instance_name,
-            processor_type: TypeId::of::<P>(),
-        };
+    // Adds a processor named after its type, so a graph can only hold one
+    // instance of each type added this way. A second Delay added with add
+    // makes build panic, and try_build return DuplicateInstance; give each
+    // its own name with add_processor instead
+    pub fn add<P: Processor>(self, processor: P) -> Self {
+        // the type name doubles as the instance name
+        self.place(Instance::new(processor, std::any::type_name::<P>()))
+    }
 
-        self.components.push((TypeId::of::<P>(), instance_name, StoredComponent::User(stored)));
+    // Remembers the Registry id an instance was added under, for patches
+    pub(crate) fn registry_id(mut self, instance_name: &'static str, id: &str) -> Self {
+        self.registry_ids.insert(instance_name, id.to_string());
         self
     }
 
-    pub fn add<P: Processor>(mut self, processor: P) -> Self {
-        let component_id = ComponentId(self.next_component_id);
+    fn place(mut self, mut instance: Instance<E>) -> Self {
+        let states = (instance.create_states)();
+        instance.place(ComponentId(self.next_component_id), self.states.len(), states.len());
         self.next_component_id += 1;
-        
-        let handle = ContextHandle {
-            component_id,
-            buffer_ids_start: BufferIdx(0), // Set during build
-            slot_ids_start: self.states.len(),
-        };
 
-        self.states.extend(P::create_states());
-
-        // use type name for unique, hashable identifier
-        let instance_name = std::any::type_name::<P>();
-        
-        let stored = UserComponent {
-            component: P::call,
-            context_handle: handle,
-            field_count: P::buffers_count(),
-            instance_name: instance_name,
-            processor_type: TypeId::of::<P>(),
-        };
-        
-        self.components.push((TypeId::of::<P>(), instance_name, StoredComponent::User(stored)));
+        self.states.extend(states);
+        self.lifecycles.push(instance.lifecycle());
+        #[cfg(feature = "serde")]
+        self.state_slots.extend(instance.state_slots());
+        self.instances.push(instance);
         self
     }
 
@@ -86,8 +83,47 @@ impl<E: Clone + Copy + Debug> Builder<E> {
         self.buffer_size = length;
         self
     }
+
+    // Crossfades from the old graph to the new one over this many samples
+    // whenever routing changes, see Router::set_crossfade. Off unless set
+    pub fn crossfade(mut self, samples: usize) -> Self {
+        self.crossfade_length = samples;
+        self
+    }
+
+    // The graph's seed, which every instance's Runtime::instance_seed is
+    // worked out from. 0 unless set
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // The rate processors are prepared at when the runtime is built,
+    // 48kHz unless set. Runtime::prepare changes it later
+    pub fn sample_rate(mut self, sample_rate: f32) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
     
+    // Panics if two processors share an instance name, see try_build
     pub fn build(self) -> (Runtime<E>, Router<E>) {
+        match self.try_build() {
+            Ok(built) => built,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    // Instance names identify processors in patches, presets and errors,
+    // so each must be unique
+    pub fn try_build(self) -> Result<(Runtime<E>, Router<E>), RoutingErr> {
+        let mut names = HashSet::from(["__system_input__", "__system_output__"]);
+        for instance in &self.instances {
+            let name = instance.component.instance_name;
+            if !names.insert(name) {
+                return Err(RoutingErr::DuplicateInstance { instance: name.to_string() });
+            }
+        }
+
         let (update_tx, update_rx) = lockfree::channel::spsc::create();
         let (event_tx, event_rx) = lockfree::channel::spsc::create();
         
@@ -107,19 +143,197 @@ impl<E: Clone + Copy + Debug> Builder<E> {
         });
         components.insert((TypeId::of::<SystemOutput>(), "__system_output__"), output_component);
         
-        for (type_id, name, stored) in self.components {
-            components.insert((type_id, name), stored);
+        for instance in &self.instances {
+            let component = instance.component;
+            components.insert((component.processor_type, component.instance_name), StoredComponent::User(component));
         }
         
-        let clerk = Arc::new(Mutex::new(Clerk::new(components, self.buffer_size, update_tx, event_tx)));
-        
+        let mut runtime = Runtime::new(
+            update_rx,
+            event_rx,
+            self.states,
+            self.lifecycles,
+            self.next_component_id,
+            self.buffer_size,
+            self.sample_rate,
+        );
+        runtime.crossfade_length = self.crossfade_length;
+        runtime.seed = self.seed;
+        // still on the building thread, before the first tick
+        runtime.prepare(self.sample_rate);
+
+        let clerk = Clerk::new(components, self.buffer_size, update_tx, event_tx, Arc::clone(&runtime.shared))
+            .with_crossfade(self.crossfade_length)
+            .with_registry_ids(self.registry_ids)
+            .with_instances(self.instances, self.seed);
+        #[cfg(feature = "serde")]
+        let clerk = clerk.with_state_slots(self.state_slots.clone());
+        #[cfg(feature = "serde")]
+        let runtime = runtime.with_state_slots(self.state_slots);
+
         let router = Router {
-            clerk: Arc::clone(&clerk),
+            clerk: Arc::new(Mutex::new(clerk)),
         };
         
-        let runtime = Runtime::new(update_rx, event_rx, self.states, self.buffer_size);
-        
-        (runtime, router)
+        Ok((runtime, router))
     }
 
-}
\ No newline at end of file
+}
+
+// Everything needed to put one processor instance into a graph, while
+// building or later through Router::add_processor. It keeps the processor
+// value, so an instance that was removed can be added back with fresh states
+#[derive(Clone)]
+pub(crate) struct Instance<E: Clone + Copy + 'static> {
+    // its context handle is filled in when it's placed
+    pub(crate) component: UserComponent<E>,
+    pub(crate) create_states: Rc<dyn Fn() -> Vec<BoxedState>>,
+    // how many states it has where it was placed
+    pub(crate) state_count: usize,
+    prepare: fn(&Runtime<E>, ContextHandle, AudioConfig),
+    reset: fn(&Runtime<E>, ContextHandle),
+    release: fn(&Runtime<E>, ContextHandle),
+    #[cfg(feature = "serde")]
+    state_codecs: Vec<Option<StateCodec>>,
+}
+
+impl<E: Clone + Copy + 'static> Instance<E> {
+    pub(crate) fn new<P: Processor>(processor: P, instance_name: &'static str) -> Self {
+        check_ports::<P>();
+        let (main_input, main_output) = main_ports(P::ports());
+        let component = UserComponent {
+            component: P::call,
+            context_handle: ContextHandle {
+                component_id: ComponentId(0),
+                buffer_ids_start: BufferIdx(0), // set during build
+                slot_ids_start: 0,
+            },
+            field_count: P::buffers_count(),
+            instance_name,
+            processor_type: TypeId::of::<P>(),
+            ports: P::ports(),
+            main_input,
+            main_output,
+            latency: processor.latency(),
+        };
+
+        Self {
+            component,
+            create_states: Rc::new(move || processor.create_states()),
+            state_count: 0,
+            prepare: P::prepare,
+            reset: P::reset,
+            release: P::release,
+            #[cfg(feature = "serde")]
+            state_codecs: P::state_codecs(),
+        }
+    }
+
+    // Gives the instance its id, and the index in Runtime::states its
+    // state_count states start at
+    pub(crate) fn place(&mut self, component_id: ComponentId, slot_ids_start: usize, state_count: usize) {
+        self.component.context_handle.component_id = component_id;
+        self.component.context_handle.slot_ids_start = slot_ids_start;
+        self.state_count = state_count;
+    }
+
+    pub(crate) fn lifecycle(&self) -> Lifecycle<E> {
+        Lifecycle {
+            context_handle: self.component.context_handle,
+            instance_name: self.component.instance_name,
+            prepare: self.prepare,
+            reset: self.reset,
+            release: self.release,
+        }
+    }
+
+    // which instance each of its states belongs to, for snapshots
+    #[cfg(feature = "serde")]
+    pub(crate) fn state_slots(&self) -> Vec<StateSlot> {
+        let mut codecs = self.state_codecs.iter().copied();
+        (0..self.state_count)
+            .map(|slot| StateSlot {
+                instance_name: self.component.instance_name,
+                slot,
+                codec: codecs.next().flatten(),
+            })
+            .collect()
+    }
+}
+
+// Every buffer is a port, and the ledger works out which buffer a port
+// uses from where it sits in ports(), so the two counts must agree
+pub(crate) fn check_ports<P: Processor>() {
+    assert_eq!(
+        P::ports().len(),
+        P::buffers_count(),
+        "{} describes {} ports but has {} buffers",
+        std::any::type_name::<P>(),
+        P::ports().len(),
+        P::buffers_count(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::processor::*;
+    use crate::core::test_processors::*;
+    use std::any::Any;
+    use std::cell::UnsafeCell;
+
+    #[test]
+    fn test_state_built_from_processor_value() {
+        let (mut runtime, router) = Builder::<TestEvent>::new()
+            .add(Source)
+            .add_processor(Delay::new(3), "short")
+            .add_processor(Delay::new(5), "long")
+            .buffer_length(2)
+            .build();
+
+        router.route(source_out(), named_port::<Delay, Input>("short", 0)).unwrap();
+        router.route(named_port::<Delay, Output>("short", 1), named_port::<Delay, Input>("long", 0)).unwrap();
+        router.route(named_port::<Delay, Output>("long", 1), output()).unwrap();
+
+        // the source reaches the output after 3 + 5 samples
+        let mut out = [0.0; 2];
+        let mut played = Vec::new();
+        for _ in 0..5 {
+            runtime.process(None, &mut out);
+            played.extend_from_slice(&out);
+        }
+        assert_eq!(played, [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_add_names_by_type() {
+        let err = Builder::<TestEvent>::new()
+            .add(Delay::new(3))
+            .add(Delay::new(5))
+            .try_build()
+            .err()
+            .unwrap();
+        assert_eq!(err, RoutingErr::DuplicateInstance { instance: std::any::type_name::<Delay>().to_string() });
+    }
+
+    struct Miscounted;
+
+    impl Processor for Miscounted {
+        type Handle = TestHandle;
+        fn buffers_count() -> usize { 2 }
+        fn slot_count() -> usize { 0 }
+        fn ports() -> &'static [PortDescriptor] {
+            const PORTS: &[PortDescriptor] = &[PortDescriptor::output("out")];
+            PORTS
+        }
+        fn call<E: Clone + Copy>(_runtime: &Runtime<E>, _handle: ContextHandle) {}
+        fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> { Vec::new() }
+        fn get_handle() -> TestHandle { TestHandle }
+    }
+
+    #[test]
+    #[should_panic(expected = "describes 1 ports but has 2 buffers")]
+    fn test_ports_match_buffers() {
+        let _ = Builder::<TestEvent>::new().add(Miscounted);
+    }
+}
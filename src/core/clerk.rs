@@ -4,10 +4,33 @@ use std::any::TypeId;
 use std::collections::HashSet;
 use std::cell::UnsafeCell;
 use std::fmt::Debug;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, SyncSender, TryRecvError};
 use crate::Runtime;
 use lockfree::channel::spsc::{Sender, Receiver};
 use super::router::{RoutingErr, PortHandle};
-use super::processor::{Port, PortType, SystemInput, SystemOutput, input, output};
+use super::processor::{Processor, Port, PortType, SystemInput, SystemOutput, input, output};
+use super::graph::{ProcessorInfo, PortInfo, PortRef, Connection, GraphDescription};
+use super::patch::PatchParameter;
+use super::history::{History, DEFAULT_HISTORY_LIMIT};
+use super::latency::Compensation;
+use super::crossfade::{Blend, BlendSide};
+use super::runtime::{PendingSchedule, Shared, Removal};
+use super::builder::Instance;
+#[cfg(feature = "serde")]
+use super::snapshot::{PendingSnapshot, StateSnapshot, SnapshotErr, StateCopy, copy_targets, decode_states};
+#[cfg(feature = "serde")]
+use std::sync::mpsc::TrySendError;
+
+// Everything the runtime needs to swap in a new schedule
+type Schedule<E> = (
+    Vec<StoredComponent<E>>,
+    Vec<Option<PhysicalBuffer>>, // indexed by ContextHandle.buffer_ids_start + field_idx
+    HashMap<PhysicalBuffer, Vec<f32>>,
+    SystemBuffers,
+    Vec<Compensation>, // in the order of the components they belong to
+);
 
 struct Ledger<E: Clone + Copy + 'static> {
 
@@ -31,16 +54,31 @@ struct Ledger<E: Clone + Copy + 'static> {
 
     scheduled_components: HashSet<ComponentId>,
 
+    // every successful route, in the order it was made
+    routes: Vec<(BufferKey, BufferKey)>,
+    // the order the runtime was last told to execute components in
+    execution_order: Vec<ComponentId>,
+
+    // constant values for input ports, used while the port isn't routed
+    parameters: HashMap<BufferKey, f32>,
+    // the physical buffers holding those constants in the current schedule
+    parameter_buffers: HashMap<BufferKey, PhysicalBuffer>,
+
+    // samples between the system input and output in the current schedule
+    latency: usize,
+
+    // the Registry id of each instance added through one
+    registry_ids: HashMap<&'static str, String>,
+
 }
 
 struct SearchState {
     removed_component: ComponentId,
     removed_dependencies: Vec<(LogicalBuffer, ComponentId)>,
     buffers_allocated: Vec<LogicalBuffer>,
-    buffers_freed: Vec<LogicalBuffer>,
-    physical_allocated: HashMap<LogicalBuffer, PhysicalBuffer>,
-    physical_freed: Vec<PhysicalBuffer>,
-    max_seen: Option<i32>,
+    // allocator state from before this component was explored
+    free_stack: Vec<PhysicalBuffer>,
+    next_physical_buffer: PhysicalBuffer,
 }
 
 impl<E: Clone + Copy + 'static> Ledger<E> {
@@ -59,17 +97,103 @@ impl<E: Clone + Copy + 'static> Ledger<E> {
             buffer_len: buffer_len,
 
             scheduled_components: HashSet::new(),
+
+            routes: Vec::new(),
+            execution_order: Vec::new(),
+
+            parameters: HashMap::new(),
+            parameter_buffers: HashMap::new(),
+
+            latency: 0,
+
+            registry_ids: HashMap::new(),
+        }
+    }
+    fn add_route(&mut self, from_key: BufferKey, to_key: BufferKey) -> Result<Schedule<E>, RoutingErr> {
+        let (from_ref, from_type) = self.resolve_port(from_key)?;
+        let (to_ref, to_type) = self.resolve_port(to_key)?;
+        if from_type.is_input() {
+            return Err(RoutingErr::FromPortIsInput { port: from_ref });
+        }
+        if to_type.is_output() {
+            return Err(RoutingErr::ToPortIsOutput { port: to_ref });
+        }
+        if let Some(&(existing, _)) = self.routes.iter().find(|&&(_, to)| to == to_key) {
+            return Err(RoutingErr::AlreadyConnected {
+                from: self.resolve_port(existing)?.0,
+                to: to_ref,
+            });
         }
+
+        let from_component = self.get_component_id_for_buffer_key(from_key)?;
+        let to_component = self.get_component_id_for_buffer_key(to_key)?;
+        if let Some(path) = self.cycle_through(from_component, to_component) {
+            return Err(RoutingErr::CycleDetected { path });
+        }
+
+        // Nothing has changed up to here. The route is only kept if the
+        // graph can be scheduled with it
+        self.link(from_key, to_key, from_component, to_component);
+        self.schedule().inspect_err(|_| {
+            self.unlink(from_key, to_key, from_component, to_component);
+        })
     }
-    fn add_route(&mut self, from_key: BufferKey, to_key: BufferKey) -> 
-            Result<(
-                Vec<StoredComponent<E>>, 
-                Vec<Option<PhysicalBuffer>>, // indexed by UserComponent.handle.component_id
-                HashMap<PhysicalBuffer, Vec<f32>>,
-                SystemBuffers,
-            ), 
-            RoutingErr> {
 
+    fn remove_route(&mut self, from_key: BufferKey, to_key: BufferKey) -> Result<Schedule<E>, RoutingErr> {
+        let (from_ref, _) = self.resolve_port(from_key)?;
+        let (to_ref, _) = self.resolve_port(to_key)?;
+        if !self.routes.contains(&(from_key, to_key)) {
+            return Err(RoutingErr::NotConnected { from: from_ref, to: to_ref });
+        }
+        let from_component = self.get_component_id_for_buffer_key(from_key)?;
+        let to_component = self.get_component_id_for_buffer_key(to_key)?;
+
+        self.unlink(from_key, to_key, from_component, to_component);
+        self.schedule().inspect_err(|_| {
+            self.link(from_key, to_key, from_component, to_component);
+        })
+    }
+
+    // If routing from_component into to_component would close a loop, the
+    // instances along it: to_component, the chain of existing routes back to
+    // from_component, and to_component again
+    fn cycle_through(&self, from_component: ComponentId, to_component: ComponentId) -> Option<Vec<&'static str>> {
+        let edges: Vec<(ComponentId, ComponentId)> = self.routes.iter()
+            .filter_map(|&(from, to)| Some((
+                self.get_component_id_for_buffer_key(from).ok()?,
+                self.get_component_id_for_buffer_key(to).ok()?,
+            )))
+            .collect();
+
+        // depth first search downstream of to_component, remembering how each
+        // component was reached
+        let mut reached_from = HashMap::new();
+        let mut seen = HashSet::from([to_component]);
+        let mut stack = vec![to_component];
+        while let Some(current) = stack.pop() {
+            if current == from_component {
+                let mut chain = vec![current];
+                while let Some(&previous) = reached_from.get(chain.last().unwrap()) {
+                    chain.push(previous);
+                }
+                chain.reverse();
+                chain.push(to_component);
+
+                let component_map = self.create_component_id_map();
+                return Some(chain.iter().map(|comp_id| component_map[comp_id].instance_name()).collect());
+            }
+            for &(from, to) in &edges {
+                if from == current && seen.insert(to) {
+                    reached_from.insert(to, current);
+                    stack.push(to);
+                }
+            }
+        }
+        None
+    }
+
+    // Adds a route to the graph without rescheduling or checking it
+    fn link(&mut self, from_key: BufferKey, to_key: BufferKey, from_component: ComponentId, to_component: ComponentId) {
         // Create or get logical buffer for the connection
         let logical_buffer = if let Some(&existing) = self.logical_buffer_map.get(&from_key) {
             existing
@@ -79,37 +203,94 @@ impl<E: Clone + Copy + 'static> Ledger<E> {
             self.logical_buffer_map.insert(from_key, new_buffer);
             new_buffer
         };
-        
 
         // Connect the to_key to the same logical buffer
         self.logical_buffer_map.insert(to_key, logical_buffer);
 
-        // Set up the dependency relationship
-        let from_component = self.get_component_id_for_buffer_key(from_key)?;
-        let to_component = self.get_component_id_for_buffer_key(to_key)?;
-        
         // to_component depends on logical_buffer
         self.dependencies.entry(to_component)
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(logical_buffer);
-        
-        // logical_buffer is depended on by to_component  
+
+        // logical_buffer is depended on by to_component
         self.anti_dependencies.entry(logical_buffer)
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(to_component);
-        
-        // from_component produces logical_buffer
-        self.produces.entry(from_component)
-            .or_insert_with(Vec::new)
-            .push(logical_buffer);
-            
-        // logical_buffer is produced by from_component
-        self.anti_produces.insert(logical_buffer, from_component);
 
+        // from_component produces logical_buffer (once, however many routes read it)
+        let produced = self.produces.entry(from_component).or_default();
+        if !produced.contains(&logical_buffer) {
+            produced.push(logical_buffer);
+        }
+
+        // logical_buffers only count as produced once their producer has been
+        // explored, so anti_produces is filled in by explore()
+
+        self.routes.push((from_key, to_key));
+    }
+
+    // Links an old route into a blend schedule. The input keeps reading its
+    // new source, or stays unrouted, and only depends on the old one
+    fn link_alongside(&mut self, from_key: BufferKey, to_key: BufferKey, from_component: ComponentId, to_component: ComponentId) {
+        let routed = self.logical_buffer_map.get(&to_key).copied();
+        self.link(from_key, to_key, from_component, to_component);
+        match routed {
+            Some(logical_buffer) => self.logical_buffer_map.insert(to_key, logical_buffer),
+            None => self.logical_buffer_map.remove(&to_key),
+        };
+    }
+
+    // The reverse of link
+    fn unlink(&mut self, from_key: BufferKey, to_key: BufferKey, from_component: ComponentId, to_component: ComponentId) {
+        let Some(&logical_buffer) = self.logical_buffer_map.get(&to_key) else {
+            return;
+        };
+
+        self.routes.retain(|&route| route != (from_key, to_key));
+        self.logical_buffer_map.remove(&to_key);
+
+        if let Some(deps) = self.dependencies.get_mut(&to_component) {
+            if let Some(idx) = deps.iter().position(|&buf| buf == logical_buffer) {
+                deps.remove(idx);
+            }
+        }
+        if let Some(readers) = self.anti_dependencies.get_mut(&logical_buffer) {
+            if let Some(idx) = readers.iter().position(|&comp| comp == to_component) {
+                readers.remove(idx);
+            }
+        }
+
+        // the output keeps its logical buffer while other routes still read it
+        if !self.routes.iter().any(|&(from, _)| from == from_key) {
+            self.logical_buffer_map.remove(&from_key);
+            self.anti_dependencies.remove(&logical_buffer);
+            if let Some(produced) = self.produces.get_mut(&from_component) {
+                produced.retain(|&buf| buf != logical_buffer);
+            }
+        }
+    }
+
+    fn set_parameter(&mut self, key: BufferKey, value: f32) -> Result<(), RoutingErr> {
+        self.check_parameter_port(key)?;
+        self.parameters.insert(key, value);
+        Ok(())
+    }
+
+    // Only inputs read parameters
+    fn check_parameter_port(&self, key: BufferKey) -> Result<(), RoutingErr> {
+        let (port, port_type) = self.resolve_port(key)?;
+        if port_type != PortType::Input {
+            return Err(RoutingErr::PortTypeMismatch { port, expected: PortType::Input, found: port_type });
+        }
+        Ok(())
+    }
+
+    // Finds the execution order and buffer assignment that needs the fewest
+    // physical buffers, by searching every topological order of the graph
+    fn schedule(&mut self) -> Result<Schedule<E>, RoutingErr> {
         let mut best_order = Vec::new();
-        let mut best_peak = i32::MAX;
+        let mut best_peak = usize::MAX;
         
-        let mut current_allocated: i32 = 0;
         let mut to_explore = vec![];
         let mut to_undo = Vec::<SearchState>::new();
         let mut current_order = Vec::<ComponentId>::new();
@@ -120,13 +301,11 @@ impl<E: Clone + Copy + 'static> Ledger<E> {
         let mut current_buffer_allocations: HashMap<LogicalBuffer, PhysicalBuffer> = HashMap::new();
         let mut best_buffer_allocations: HashMap<LogicalBuffer, PhysicalBuffer> = current_buffer_allocations.clone();
 
-
-        
         to_explore.push(self.list_kahns());
         
         while let Some(mut nodes) = to_explore.pop() {
             while let Some(node) = nodes.pop() {
-                let mut state = self.explore(
+                let state = self.explore(
                     node, 
                     &mut current_free_buffer_stack,
                     &mut next_physical_buffer,
@@ -134,37 +313,30 @@ impl<E: Clone + Copy + 'static> Ledger<E> {
                 );
                 current_order.push(node);
                 
-                current_allocated += state.buffers_allocated.len() as i32;
-                
                 // Check if we're at a leaf (no more components to schedule)
                 let next_available = self.list_kahns();
-                if next_available.is_empty() && self.dependencies.is_empty() {
-                    // We've scheduled everything!
-                    if current_allocated < best_peak {
-                        best_peak = current_allocated;
+                if self.scheduled_components.len() == self.components.len() {
+                    // We've scheduled everything! The peak is the number of
+                    // physical buffers this order needed at once
+                    if next_physical_buffer.0 < best_peak {
+                        best_peak = next_physical_buffer.0;
                         best_order = current_order.clone();
                         best_buffer_allocations = current_buffer_allocations.clone();
                     }
-                } else if next_available.is_empty() && !self.dependencies.is_empty() {
-                    // Cycle detected - we have dependencies left but no available nodes
-                    for state in to_undo {
+                } else if next_available.is_empty() {
+                    // Cycle detected - we have components left but no available nodes
+                    let path = self.find_cycle();
+                    self.undo(state);
+                    for state in to_undo.into_iter().rev() {
                         self.undo(state)
                     }
-                    return Err(RoutingErr::CycleDetected);
-                }
-                
-                // Calculate frees from previous component (if any)
-                if let Some(last_state) = to_undo.last() {
-                    current_allocated -= last_state.buffers_freed.len() as i32;
-                    let last_max = last_state.max_seen.unwrap_or(0);
-                    state.max_seen = Some(last_max.max(current_allocated));
-                } else {
-                    state.max_seen = Some(current_allocated);
+                    return Err(RoutingErr::CycleDetected { path });
                 }
                 
                 to_undo.push(state);
                 to_explore.push(nodes);
-                nodes = next_available;
+                // an order that already needs as many buffers as the best can't beat it
+                nodes = if next_physical_buffer.0 >= best_peak { Vec::new() } else { next_available };
             }
             
             // Backtrack
@@ -172,37 +344,20 @@ impl<E: Clone + Copy + 'static> Ledger<E> {
                 current_order.pop();
                 
                 // Undo allocations - remove from hashmap
-                for logical_buf in state.physical_allocated.keys() {
+                for logical_buf in &state.buffers_allocated {
                     current_buffer_allocations.remove(logical_buf);
                 }
                 
-                // Un-free buffers - remove them from free stack
-                for _ in 0..state.physical_freed.len() {
-                    current_free_buffer_stack.pop();
-                }
+                // Restore the allocator to how it was before this component
+                current_free_buffer_stack = state.free_stack.clone();
+                next_physical_buffer = state.next_physical_buffer;
                 
-                // Undo logical graph changes
-                current_allocated -= state.buffers_allocated.len() as i32;
-                if to_undo.last().is_some() {
-                    current_allocated += to_undo.last().unwrap().buffers_freed.len() as i32;
-                }
                 self.undo(state);
             }
         }
 
-        let input_handle = input();
-        let input_key = BufferKey::System(SystemKey {
-                marker: TypeId::of::<SystemInput>(),
-                instance_name: input_handle.name,
-            }
-        );
-
-        let output_handle = output();
-        let output_key = BufferKey::System(SystemKey {
-                marker: TypeId::of::<SystemOutput>(),
-                instance_name: output_handle.name,
-            }
-        );
+        let input_key = system_input_key();
+        let output_key = system_output_key();
 
         let input_info = self.logical_buffer_map.get(&input_key)
             .and_then(|&logical_buffer| {
@@ -220,6 +375,10 @@ impl<E: Clone + Copy + 'static> Ledger<E> {
         let input_component_id = self.get_component_id_for_buffer_key(input_key)?;
         let output_component_id = self.get_component_id_for_buffer_key(output_key)?;
 
+        // Remember what the runtime is about to run, for introspection
+        self.execution_order = best_order.clone();
+        self.physical_buffer_map = best_buffer_allocations.clone();
+
         self.convert_results(best_order, best_buffer_allocations, input_component_id, output_component_id)
 
     }
@@ -253,6 +412,52 @@ impl<E: Clone + Copy + 'static> Ledger<E> {
             })
             .collect()
     }
+    // Walks back from an unscheduled component along its unmet dependencies.
+    // Every unmet dependency is produced by another unscheduled component, so
+    // the walk has to come back to a component it has seen
+    fn find_cycle(&self) -> Vec<&'static str> {
+        let component_map = self.create_component_id_map();
+        let producer_of = |buffer: &LogicalBuffer| self.produces.iter()
+            .find(|(_, produced)| produced.contains(buffer))
+            .map(|(&comp_id, _)| comp_id);
+
+        let mut unscheduled: Vec<_> = component_map.keys()
+            .filter(|comp_id| !self.scheduled_components.contains(comp_id))
+            .copied()
+            .collect();
+        unscheduled.sort_by_key(|comp_id| comp_id.0);
+
+        let Some(&start) = unscheduled.first() else {
+            return Vec::new();
+        };
+        let mut visited = Vec::new();
+        let mut current = start;
+        while !visited.contains(&current) {
+            visited.push(current);
+            let next = self.dependencies.get(&current).into_iter()
+                .flatten()
+                .filter(|buffer| !self.anti_produces.contains_key(buffer))
+                .find_map(producer_of);
+            match next {
+                Some(next) => current = next,
+                None => return Vec::new(),
+            }
+        }
+
+        // the walk went against the routes, so reverse it, and start from
+        // the earliest added component
+        let loop_start = visited.iter().position(|&comp_id| comp_id == current).unwrap();
+        let mut cycle: Vec<_> = visited[loop_start..].iter().rev().copied().collect();
+        let first = (0..cycle.len()).min_by_key(|&idx| cycle[idx].0).unwrap();
+        cycle.rotate_left(first);
+
+        let mut path: Vec<_> = cycle.iter()
+            .map(|comp_id| component_map[comp_id].instance_name())
+            .collect();
+        path.push(path[0]);
+        path
+    }
+
     fn explore(&mut self, component_id: ComponentId, 
             free_stack: &mut Vec<PhysicalBuffer>, 
             next_physical_buffer: &mut PhysicalBuffer,
@@ -276,36 +481,42 @@ impl<E: Clone + Copy + 'static> Ledger<E> {
         
         // Get buffers this component produces (these get allocated)
         let produced_buffers = self.produces.get(&component_id).cloned().unwrap_or_default();
+
+        let prev_free_stack = free_stack.clone();
+        let prev_next_physical_buffer = *next_physical_buffer;
+
+        // The system input is written before the tick starts, so it can't
+        // reuse a buffer another component already wrote to this tick
+        let is_system_input = self.get_component_id_for_buffer_key(system_input_key()).ok() == Some(component_id);
+        // The system output is read after the tick ends, so it is never freed
+        let is_system_output = self.get_component_id_for_buffer_key(system_output_key()).ok() == Some(component_id);
         
         // Allocate physical buffers for produced logical buffers
-        let mut physical_allocated = HashMap::new();
         for logical_buf in &produced_buffers {
-            let physical_buf = if let Some(buf) = free_stack.pop() {
-                buf
-            } else {
+            let reused = if is_system_input { None } else { free_stack.pop() };
+            let physical_buf = reused.unwrap_or_else(|| {
                 let buf = *next_physical_buffer;
                 next_physical_buffer.0 += 1;
                 buf
-            };
+            });
             current_buffer_allocations.insert(*logical_buf, physical_buf);
-            physical_allocated.insert(*logical_buf, physical_buf);
         }
         // Add produced buffers to anti_produces
         for buffer in &produced_buffers {
             self.anti_produces.insert(*buffer, component_id);
         }
         
-        // Find buffers that can now be freed (no remaining consumers)
-        let mut buffers_freed = Vec::new();
-        let mut physical_freed = Vec::new();
-        for buffer in &consumed_buffers {
-            if let Some(consumers) = self.anti_dependencies.get(buffer) {
-                if consumers.is_empty() {
-                    buffers_freed.push(*buffer);
-                    // Return the physical buffer to the free stack
-                    if let Some(&physical_buf) = current_buffer_allocations.get(buffer) {
-                        free_stack.push(physical_buf);
-                        physical_freed.push(physical_buf);
+        // Return buffers with no remaining consumers to the free stack
+        if !is_system_output {
+            for buffer in &consumed_buffers {
+                if let Some(consumers) = self.anti_dependencies.get(buffer) {
+                    if consumers.is_empty() {
+                        if let Some(&physical_buf) = current_buffer_allocations.get(buffer) {
+                            // a buffer consumed through several ports is only freed once
+                            if !free_stack.contains(&physical_buf) {
+                                free_stack.push(physical_buf);
+                            }
+                        }
                     }
                 }
             }
@@ -315,10 +526,8 @@ impl<E: Clone + Copy + 'static> Ledger<E> {
             removed_component: component_id,
             removed_dependencies,
             buffers_allocated: produced_buffers,
-            buffers_freed,
-            physical_allocated,
-            physical_freed,
-            max_seen: None,
+            free_stack: prev_free_stack,
+            next_physical_buffer: prev_next_physical_buffer,
         }
     }
 
@@ -361,7 +570,7 @@ impl<E: Clone + Copy + 'static> Ledger<E> {
         };
 
         let stored_component = self.components.get(&component_key)
-            .ok_or(RoutingErr::ProcessorNotFound)?;
+            .ok_or_else(|| RoutingErr::ProcessorNotFound { instance: component_key.1.to_string() })?;
 
         let component_id = match stored_component {
             StoredComponent::System(sys_comp) => {
@@ -386,64 +595,392 @@ impl<E: Clone + Copy + 'static> Ledger<E> {
             .collect()
     }
 
+    fn processors(&self) -> Vec<ProcessorInfo> {
+        let mut components: Vec<_> = self.components.iter().collect();
+        components.sort_by_key(|(_, comp)| comp.component_id().0);
+
+        components.into_iter()
+            .map(|(&(processor_type, instance_name), comp)| {
+                let ports = comp.ports().iter()
+                    .enumerate()
+                    .map(|(field_idx, port)| {
+                        let buffer_key = create_buffer_key_for_field(comp, field_idx);
+                        let physical_buffer = lookup_physical_buffer(
+                                &buffer_key,
+                                &self.logical_buffer_map,
+                                &self.physical_buffer_map,
+                            )
+                            .or_else(|| self.parameter_buffers.get(&buffer_key).copied());
+
+                        PortInfo {
+                            name: port.name,
+                            field_idx,
+                            port_type: port.port_type,
+                            kind: port.kind,
+                            default: port.default,
+                            min: port.min,
+                            max: port.max,
+                            unit: port.unit,
+                            description: port.description,
+                            physical_buffer: physical_buffer.map(|buf| buf.0),
+                            parameter: self.parameters.get(&buffer_key).copied(),
+                        }
+                    })
+                    .collect();
+
+                ProcessorInfo {
+                    instance_name,
+                    processor_type,
+                    registry_id: self.registry_ids.get(instance_name).cloned(),
+                    is_system: matches!(comp, StoredComponent::System(_)),
+                    latency: match comp {
+                        StoredComponent::User(user_comp) => user_comp.latency,
+                        StoredComponent::System(_) => 0,
+                    },
+                    ports,
+                }
+            })
+            .collect()
+    }
+
+    fn connections(&self) -> Vec<Connection> {
+        self.routes.iter()
+            .filter_map(|&(from_key, to_key)| Some(Connection {
+                from: self.port_ref(from_key)?,
+                to: self.port_ref(to_key)?,
+            }))
+            .collect()
+    }
+
+    fn execution_order(&self) -> Vec<&'static str> {
+        let component_map = self.create_component_id_map();
+        self.execution_order.iter()
+            .filter_map(|id| component_map.get(id))
+            .map(|comp| comp.instance_name())
+            .collect()
+    }
+
+    // the id of a processor instance, leaving out the system input and output
+    fn user_component_id(&self, instance_name: &str) -> Result<ComponentId, RoutingErr> {
+        self.components.values()
+            .find_map(|comp| match comp {
+                StoredComponent::User(user_comp) if user_comp.instance_name == instance_name =>
+                    Some(user_comp.context_handle.component_id),
+                _ => None,
+            })
+            .ok_or_else(|| RoutingErr::ProcessorNotFound { instance: instance_name.to_string() })
+    }
+
+    // Every component on a path through one of the given components:
+    // the components themselves, what feeds them and what they feed
+    fn connected_through(&self, components: &HashSet<ComponentId>) -> HashSet<ComponentId> {
+        let edges: Vec<(ComponentId, ComponentId)> = self.routes.iter()
+            .filter_map(|&(from_key, to_key)| Some((
+                self.get_component_id_for_buffer_key(from_key).ok()?,
+                self.get_component_id_for_buffer_key(to_key).ok()?,
+            )))
+            .collect();
+
+        let mut connected = components.clone();
+        for downstream in [true, false] {
+            let mut stack: Vec<ComponentId> = components.iter().copied().collect();
+            while let Some(comp) = stack.pop() {
+                for &(from, to) in &edges {
+                    let (near, far) = if downstream { (from, to) } else { (to, from) };
+                    if near == comp && connected.insert(far) {
+                        stack.push(far);
+                    }
+                }
+            }
+        }
+        connected
+    }
+
+    // resolves a port by instance and port name, for callers without a PortHandle
+    fn find_port(&self, instance_name: &str, port_name: &str) -> Result<(BufferKey, PortType), RoutingErr> {
+        let comp = self.components.values()
+            .find(|comp| comp.instance_name() == instance_name)
+            .ok_or_else(|| RoutingErr::ProcessorNotFound { instance: instance_name.to_string() })?;
+        let field_idx = comp.ports().iter()
+            .position(|port| port.name == port_name)
+            .ok_or_else(|| RoutingErr::PortNotFound {
+                instance: instance_name.to_string(),
+                port: port_name.to_string(),
+            })?;
+
+        Ok((create_buffer_key_for_field(comp, field_idx), comp.ports()[field_idx].port_type))
+    }
+
+    // The port a key points at, and what kind of port it is
+    fn resolve_port(&self, buffer_key: BufferKey) -> Result<(PortRef, PortType), RoutingErr> {
+        let (component_key, field_idx) = match buffer_key {
+            BufferKey::System(key) => ((key.marker, key.instance_name), 0),
+            BufferKey::User(key) => ((key.processor_type, key.instance_name), key.field_idx),
+        };
+        let comp = self.components.get(&component_key)
+            .ok_or_else(|| RoutingErr::ProcessorNotFound { instance: component_key.1.to_string() })?;
+        let port = comp.ports().get(field_idx)
+            .ok_or_else(|| RoutingErr::PortNotFound {
+                instance: component_key.1.to_string(),
+                port: format!("#{}", field_idx),
+            })?;
+
+        let port_ref = PortRef {
+            instance_name: comp.instance_name(),
+            port_name: port.name,
+            field_idx,
+        };
+        Ok((port_ref, port.port_type))
+    }
+
+    fn port_ref(&self, buffer_key: BufferKey) -> Option<PortRef> {
+        self.resolve_port(buffer_key).ok().map(|(port_ref, _)| port_ref)
+    }
+
     fn convert_results(
-        &self,
+        &mut self,
         best_order: Vec<ComponentId>,
         best_buffer_allocations: HashMap<LogicalBuffer, PhysicalBuffer>,
         input_component_id: ComponentId,
         output_component_id: ComponentId,
-    ) -> Result<(
-        Vec<StoredComponent<E>>, 
-        Vec<Option<PhysicalBuffer>>,
-        HashMap<PhysicalBuffer, Vec<f32>>,
-        SystemBuffers,
-    ), RoutingErr> {
+    ) -> Result<Schedule<E>, RoutingErr> {
         
         let component_map = self.create_component_id_map();
         let mut execution_order = create_execution_order(&component_map, &best_order);
         
-        let buffer_map = assign_buffers_to_map(
+        let mut buffer_map = assign_buffers_to_map(
             &mut execution_order,
             &best_buffer_allocations,
             &self.logical_buffer_map
         );
         
-        let physical_buffers = initialize_physical_buffers(
+        let mut physical_buffers = initialize_physical_buffers(
             &buffer_map,
             &best_buffer_allocations,
             self.buffer_len
         );
 
+        // parameter buffers go after every buffer the schedule uses
+        let first_free = physical_buffers.keys().map(|buf| buf.0 + 1).max().unwrap_or(0);
+        self.parameter_buffers = assign_parameter_buffers(
+            &execution_order,
+            &mut buffer_map,
+            &self.parameters,
+            PhysicalBuffer(first_free),
+        );
+        for (key, physical_buf) in &self.parameter_buffers {
+            physical_buffers.insert(*physical_buf, vec![self.parameters[key]; self.buffer_len]);
+        }
+
+        // then the scratch buffers unrouted outputs write into
+        let first_scratch = first_free + self.parameter_buffers.len();
+        let scratch_count = assign_scratch_buffers(
+            &execution_order,
+            &mut buffer_map,
+            PhysicalBuffer(first_scratch),
+        );
+        for scratch in first_scratch..first_scratch + scratch_count {
+            physical_buffers.insert(PhysicalBuffer(scratch), vec![0.0; self.buffer_len]);
+        }
+
         let system_buffers = create_system_buffers(
-            &component_map,
+            &execution_order,
             input_component_id,
             output_component_id,
         );
         
-        Ok((execution_order, buffer_map, physical_buffers, system_buffers))
+        // and last the compensating delays, which read routed buffers into their own
+        let first_delay = first_scratch + scratch_count;
+        let compensations = self.compensate_latency(&execution_order, &mut buffer_map, PhysicalBuffer(first_delay));
+        for compensation in &compensations {
+            physical_buffers.insert(compensation.target, vec![0.0; self.buffer_len]);
+        }
+
+        Ok((execution_order, buffer_map, physical_buffers, system_buffers, compensations))
     }
+
+    // A schedule to play while crossfading from an earlier graph to this
+    // one. It holds the processors and routes of both, and each input whose
+    // source differs reads a Blend of the old source and the new. Every
+    // processor is still in it once, so a removed one plays on while it
+    // fades out. Old routes that would close a loop with the new ones are
+    // left out, and their inputs fade from their parameter instead. None if
+    // no input's source changed
+    fn blend_schedule(&self, from: &SentGraph<E>) -> Option<(Schedule<E>, Vec<Blend>)> {
+        let from_routes = &from.routes[..];
+        let source = |routes: &[(BufferKey, BufferKey)], to_key: BufferKey| routes.iter()
+            .find(|&&(_, to)| to == to_key)
+            .map(|&(from, _)| from);
+        let changed: HashSet<BufferKey> = from_routes.iter()
+            .chain(&self.routes)
+            .map(|&(_, to)| to)
+            .filter(|&to| source(from_routes, to) != source(&self.routes, to))
+            .collect();
+        if changed.is_empty() {
+            return None;
+        }
+
+        // an instance removed and added back since stands in for the old one
+        let mut components = from.components.clone();
+        components.extend(self.components.clone());
+        let mut union = Ledger::new(components, self.buffer_len);
+        union.parameters = from.parameters.iter()
+            .filter(|&(&key, _)| self.resolve_port(key).is_err())
+            .chain(&self.parameters)
+            .map(|(&key, &value)| (key, value))
+            .collect();
+        for &(from, to) in &self.routes {
+            let from_component = self.get_component_id_for_buffer_key(from).ok()?;
+            let to_component = self.get_component_id_for_buffer_key(to).ok()?;
+            union.link(from, to, from_component, to_component);
+        }
+        let mut linked = HashSet::new();
+        for &(from, to) in from_routes.iter().filter(|&&(_, to)| changed.contains(&to)) {
+            let (Ok(from_component), Ok(to_component)) = (
+                union.get_component_id_for_buffer_key(from),
+                union.get_component_id_for_buffer_key(to),
+            ) else {
+                continue;
+            };
+            if union.cycle_through(from_component, to_component).is_none() {
+                union.link_alongside(from, to, from_component, to_component);
+                linked.insert(to);
+            }
+        }
+
+        let (execution_order, mut buffer_map, mut physical_buffers, system_buffers, compensations) = union.schedule().ok()?;
+
+        // the blends write last, into buffers of their own
+        let mut next_target = physical_buffers.keys().map(|buf| buf.0 + 1).max().unwrap_or(0);
+        let mut blends = Vec::new();
+        for component in &execution_order {
+            let buffer_ids_start = match component {
+                StoredComponent::User(user_comp) => user_comp.context_handle.buffer_ids_start,
+                StoredComponent::System(sys_comp) => sys_comp.buffer_idx,
+            };
+            for field_idx in 0..get_length(component) {
+                let key = create_buffer_key_for_field(component, field_idx);
+                if !changed.contains(&key) {
+                    continue;
+                }
+                let resting = BlendSide::Constant(union.parameters.get(&key).copied()
+                    .or(component.ports()[field_idx].default)
+                    .unwrap_or(0.0));
+                let from = source(from_routes, key)
+                    .filter(|_| linked.contains(&key))
+                    .and_then(|from| lookup_physical_buffer(&from, &union.logical_buffer_map, &union.physical_buffer_map))
+                    .map_or(resting, BlendSide::Buffer);
+                // after any compensating delay
+                let buffer_idx = buffer_ids_start.0 + field_idx;
+                let to = buffer_map[buffer_idx].map_or(resting, BlendSide::Buffer);
+
+                let target = PhysicalBuffer(next_target);
+                next_target += 1;
+                physical_buffers.insert(target, vec![0.0; self.buffer_len]);
+                buffer_map[buffer_idx] = Some(target);
+                blends.push(Blend { component_id: component.component_id(), from, to, target });
+            }
+        }
+
+        Some(((execution_order, buffer_map, physical_buffers, system_buffers, compensations), blends))
+    }
+
+    // Works out when each component's inputs arrive, counting the latency
+    // processors report, and delays every input that arrives before the
+    // component's latest one. Also records the latency at the system output
+    fn compensate_latency(
+        &mut self,
+        execution_order: &[StoredComponent<E>],
+        buffer_map: &mut [Option<PhysicalBuffer>],
+        first_delay: PhysicalBuffer,
+    ) -> Vec<Compensation> {
+        // an input's first route is its own, blend schedules add old ones after
+        let mut sources: HashMap<BufferKey, BufferKey> = HashMap::new();
+        for &(from_key, to_key) in &self.routes {
+            sources.entry(to_key).or_insert(from_key);
+        }
+
+        // the latency of each component's outputs, filled in execution order
+        let mut output_latency: HashMap<ComponentId, usize> = HashMap::new();
+        let mut compensations = Vec::new();
+        let mut next_delay = first_delay;
+        self.latency = 0;
+
+        for component in execution_order {
+            let (buffer_ids_start, latency) = match component {
+                StoredComponent::User(user_comp) => (user_comp.context_handle.buffer_ids_start, user_comp.latency),
+                StoredComponent::System(sys_comp) => (sys_comp.buffer_idx, 0),
+            };
+
+            let inputs: Vec<(usize, usize)> = (0..get_length(component))
+                .filter_map(|field_idx| {
+                    let source = sources.get(&create_buffer_key_for_field(component, field_idx))?;
+                    let source_id = self.get_component_id_for_buffer_key(*source).ok()?;
+                    Some((field_idx, output_latency.get(&source_id).copied().unwrap_or(0)))
+                })
+                .collect();
+            let arrival = inputs.iter().map(|&(_, latency)| latency).max().unwrap_or(0);
+
+            for (field_idx, input_latency) in inputs {
+                let buffer_idx = buffer_ids_start.0 + field_idx;
+                let Some(source) = buffer_map[buffer_idx].filter(|_| input_latency < arrival) else {
+                    continue;
+                };
+                compensations.push(Compensation::new(
+                    component.component_id(),
+                    field_idx,
+                    source,
+                    next_delay,
+                    arrival - input_latency,
+                ));
+                buffer_map[buffer_idx] = Some(next_delay);
+                next_delay.0 += 1;
+            }
+
+            if let StoredComponent::System(_) = component {
+                if self.get_component_id_for_buffer_key(system_output_key()).ok() == Some(component.component_id()) {
+                    self.latency = arrival;
+                }
+            }
+            output_latency.insert(component.component_id(), arrival + latency);
+        }
+
+        compensations
+    }
+}
+
+// A graph the Clerk sent the runtime, kept until the runtime settles on a
+// later one, for crossfades to start from
+struct SentGraph<E: Clone + Copy + 'static> {
+    generation: usize,
+    components: HashMap<(TypeId, &'static str), StoredComponent<E>>,
+    routes: Vec<(BufferKey, BufferKey)>,
+    parameters: HashMap<BufferKey, f32>,
+}
+
+// A schedule as the runtime takes it, with its buffers ready to write to
+fn pending_schedule<E: Clone + Copy + 'static>(schedule: Schedule<E>, blends: Vec<Blend>) -> PendingSchedule<E> {
+    let (execution_order, buffer_ids, buffers, system_buffers, compensations) = schedule;
+    let buffers = buffers.into_iter()
+        .map(|(physical_id, buffer_data)| (physical_id, UnsafeCell::new(buffer_data)))
+        .collect();
+    PendingSchedule { execution_order, buffer_ids, buffers, system_buffers, compensations, blends }
 }
 
 fn create_system_buffers<E: Clone + Copy + 'static>(
-    component_map: &HashMap<ComponentId, &StoredComponent<E>>,
+    execution_order: &[StoredComponent<E>],
     input_component_id: ComponentId,
     output_component_id: ComponentId,
 ) -> SystemBuffers {
-    let input_component = component_map.get(&input_component_id)
-        .and_then(|comp| match comp {
-            StoredComponent::System(sys_comp) => Some(sys_comp),
-            _ => None,
-        });
-    
-    let output_component = component_map.get(&output_component_id)
-        .and_then(|comp| match comp {
-            StoredComponent::System(sys_comp) => Some(*sys_comp),
+    let find_system = |id: ComponentId| execution_order.iter()
+        .find_map(|comp| match comp {
+            StoredComponent::System(sys_comp) if sys_comp.component_id == id => Some(*sys_comp),
             _ => None,
         });
     
     SystemBuffers {
-        input: input_component.copied(),
-        output: output_component,
+        input: find_system(input_component_id),
+        output: find_system(output_component_id),
     }
 }
 
@@ -517,6 +1054,63 @@ fn create_buffer_key_for_field<E: Clone + Copy + 'static>(
     }
 }
 
+// Gives every unrouted input with a parameter its own constant buffer
+fn assign_parameter_buffers<E: Clone + Copy + 'static>(
+    execution_order: &[StoredComponent<E>],
+    buffer_map: &mut [Option<PhysicalBuffer>],
+    parameters: &HashMap<BufferKey, f32>,
+    mut next_physical_buffer: PhysicalBuffer,
+) -> HashMap<BufferKey, PhysicalBuffer> {
+    let mut parameter_buffers = HashMap::new();
+
+    for component in execution_order {
+        let StoredComponent::User(user_comp) = component else {
+            continue;
+        };
+        for field_idx in 0..user_comp.field_count {
+            let buffer_idx = user_comp.context_handle.buffer_ids_start.0 + field_idx;
+            let buffer_key = create_buffer_key_for_field(component, field_idx);
+
+            if buffer_map[buffer_idx].is_none() && parameters.contains_key(&buffer_key) {
+                buffer_map[buffer_idx] = Some(next_physical_buffer);
+                parameter_buffers.insert(buffer_key, next_physical_buffer);
+                next_physical_buffer.0 += 1;
+            }
+        }
+    }
+
+    parameter_buffers
+}
+
+// Points every unrouted output at a scratch buffer nobody reads. Components
+// run one at a time, so they can share scratch buffers, but the outputs of
+// one component each need their own. Returns how many scratch buffers
+// were used
+fn assign_scratch_buffers<E: Clone + Copy + 'static>(
+    execution_order: &[StoredComponent<E>],
+    buffer_map: &mut [Option<PhysicalBuffer>],
+    first_scratch: PhysicalBuffer,
+) -> usize {
+    let mut scratch_count = 0;
+
+    for component in execution_order {
+        let StoredComponent::User(user_comp) = component else {
+            continue;
+        };
+        let mut next_scratch = first_scratch.0;
+        for (field_idx, port) in user_comp.ports.iter().enumerate() {
+            let buffer_idx = user_comp.context_handle.buffer_ids_start.0 + field_idx;
+            if port.port_type == PortType::Output && buffer_map[buffer_idx].is_none() {
+                buffer_map[buffer_idx] = Some(PhysicalBuffer(next_scratch));
+                next_scratch += 1;
+            }
+        }
+        scratch_count = scratch_count.max(next_scratch - first_scratch.0);
+    }
+
+    scratch_count
+}
+
 fn lookup_physical_buffer(
     buffer_key: &BufferKey,
     logical_buffer_map: &HashMap<BufferKey, LogicalBuffer>,
@@ -559,6 +1153,55 @@ fn assign_buffers_to_map<E: Clone + Copy + 'static>(
         buffer_map
 }
 
+// Drops whatever the runtime has handed back so far, and opens a channel
+// for the next hand back. Sync channels are allocated up front, so
+// sending on one doesn't allocate on the audio thread
+fn hand_back_channel<T>(channels: &mut Vec<mpsc::Receiver<T>>) -> SyncSender<T> {
+    channels.retain(|rx| matches!(rx.try_recv(), Err(TryRecvError::Empty)));
+    let (tx, rx) = mpsc::sync_channel(1);
+    channels.push(rx);
+    tx
+}
+
+fn switch(set: &mut HashSet<ComponentId>, id: ComponentId, on: bool) {
+    if on {
+        set.insert(id);
+    } else {
+        set.remove(&id);
+    }
+}
+
+fn system_input_key() -> BufferKey {
+    BufferKey::System(SystemKey {
+        marker: TypeId::of::<SystemInput>(),
+        instance_name: input().name,
+    })
+}
+
+fn system_output_key() -> BufferKey {
+    BufferKey::System(SystemKey {
+        marker: TypeId::of::<SystemOutput>(),
+        instance_name: output().name,
+    })
+}
+
+// The key a handle points at. Whether it is the right kind of port for
+// the call is up to the Ledger
+fn handle_key<P: Port + 'static>(handle: &PortHandle<P>) -> BufferKey {
+    match P::port_type() {
+        // The system ports are keyed by their marker type
+        PortType::SystemInput | PortType::SystemOutput => BufferKey::System(SystemKey {
+            marker: TypeId::of::<P>(),
+            instance_name: handle.name,
+        }),
+        PortType::Input | PortType::Output => BufferKey::User(UserKey {
+            processor_type: handle.processor_type,
+            instance_name: handle.name,
+            field_idx: handle.field_idx,
+        }),
+    }
+}
+
 #[derive(Clone, Copy, Hash, PartialEq, Eq)]
 enum BufferKey {
     System(SystemKey),
@@ -578,19 +1221,144 @@ struct SystemKey{
     instance_name: &'static str,
 }
 
+impl BufferKey {
+    fn instance_name(&self) -> &'static str {
+        match self {
+            BufferKey::System(key) => key.instance_name,
+            BufferKey::User(key) => key.instance_name,
+        }
+    }
+}
+
+// One undoable change to the graph
+#[derive(Clone)]
+enum Edit<E: Clone + Copy + 'static> {
+    Route { from: BufferKey, to: BufferKey },
+    Unroute { from: BufferKey, to: BufferKey },
+    // None means the port had, or goes back to having, no parameter
+    Parameter { key: BufferKey, from: Option<f32>, to: Option<f32> },
+    // many parameters ramped at once, by a morph or preset
+    Ramp(Vec<(BufferKey, Option<f32>, Option<f32>)>),
+    Add(Box<Placement<E>>),
+    Remove(Box<Placement<E>>),
+}
+
+impl<E: Clone + Copy + 'static> Edit<E> {
+    fn inverse(&self) -> Edit<E> {
+        match self {
+            &Edit::Route { from, to } => Edit::Unroute { from, to },
+            &Edit::Unroute { from, to } => Edit::Route { from, to },
+            &Edit::Parameter { key, from, to } => Edit::Parameter { key, from: to, to: from },
+            Edit::Ramp(changes) => Edit::Ramp(changes.iter().map(|&(key, from, to)| (key, to, from)).collect()),
+            Edit::Add(placement) => Edit::Remove(placement.clone()),
+            Edit::Remove(placement) => Edit::Add(placement.clone()),
+        }
+    }
+}
+
+// A processor instance, with the routes, parameters and controls it had
+// when it was removed. Adding it puts all of them back, an instance that
+// was just added has none
+#[derive(Clone)]
+struct Placement<E: Clone + Copy + 'static> {
+    instance: Instance<E>,
+    registry_id: Option<String>,
+    routes: Vec<(BufferKey, BufferKey)>,
+    parameters: Vec<(BufferKey, f32)>,
+    bypassed: bool,
+    muted: bool,
+    soloed: bool,
+}
+
+impl<E: Clone + Copy + 'static> Placement<E> {
+    fn new(instance: Instance<E>) -> Self {
+        Self {
+            instance,
+            registry_id: None,
+            routes: Vec::new(),
+            parameters: Vec::new(),
+            bypassed: false,
+            muted: false,
+            soloed: false,
+        }
+    }
+
+    fn instance_name(&self) -> &'static str {
+        self.instance.component.instance_name
+    }
+}
+
 // Clerk handles all routing bookkeeping
 pub(crate) struct Clerk<E: Clone + Copy + 'static> {
 
     ledger: Ledger<E>,
+    history: History<Edit<E>>,
+    // every processor instance in the graph, by name
+    instances: HashMap<&'static str, Instance<E>>,
+    // where the next instance added goes: its id, and its first index in
+    // Runtime::states
+    next_component_id: usize,
+    next_slot: usize,
+    // from Builder::seed, for preparing instances added later
+    seed: u64,
+    // instances switched off with Router::set_bypass, set_mute and solo
+    bypassed: HashSet<ComponentId>,
+    muted: HashSet<ComponentId>,
+    soloed: HashSet<ComponentId>,
+    // what each entry of Runtime::states belongs to
+    #[cfg(feature = "serde")]
+    state_slots: Vec<StateSlot>,
+    // states the runtime hands back, so they aren't dropped on the audio
+    // thread: snapshot copies and restored states, and removed instances'
+    #[cfg(feature = "serde")]
+    pub(crate) retired: Vec<mpsc::Receiver<Vec<StateCopy>>>,
+    removed: Vec<mpsc::Receiver<Vec<BoxedState>>>,
+    // samples routing changes crossfade over, see Router::set_crossfade
+    crossfade_length: usize,
+    // the generation of the latest schedule sent, and every graph sent
+    // since the one the runtime last settled on, which a crossfade starts from
+    generation: usize,
+    sent: Vec<SentGraph<E>>,
+    shared: Arc<Shared>,
     // Channels for updates
     update_tx: lockfree::channel::spsc::Sender<Update<E>>,
     event_tx: lockfree::channel::spsc::Sender<(E)>,
 }
 
 impl<E: Clone + Copy + Debug + 'static> Clerk<E> {
-    pub(crate) fn new(components: HashMap<(TypeId, &'static str), StoredComponent<E>>, buffer_len: usize, update_tx: Sender<Update<E>>, event_tx: Sender<E>) -> Self {
+    pub(crate) fn new(
+        components: HashMap<(TypeId, &'static str), StoredComponent<E>>,
+        buffer_len: usize,
+        update_tx: Sender<Update<E>>,
+        event_tx: Sender<E>,
+        shared: Arc<Shared>,
+    ) -> Self {
+        let sent = SentGraph {
+            generation: 0,
+            components: components.clone(),
+            routes: Vec::new(),
+            parameters: HashMap::new(),
+        };
         Clerk {
             ledger: Ledger::new(components, buffer_len),
+            history: History::new(DEFAULT_HISTORY_LIMIT),
+            instances: HashMap::new(),
+            next_component_id: 2,
+            next_slot: 0,
+            seed: 0,
+            bypassed: HashSet::new(),
+            muted: HashSet::new(),
+            soloed: HashSet::new(),
+            #[cfg(feature = "serde")]
+            state_slots: Vec::new(),
+            #[cfg(feature = "serde")]
+            retired: Vec::new(),
+            removed: Vec::new(),
+            crossfade_length: 0,
+            // the runtime starts out settled on an unrouted graph
+            generation: 0,
+            sent: vec![sent],
+            shared,
             update_tx,
             event_tx,
         }
@@ -600,70 +1368,584 @@ impl<E: Clone + Copy + Debug + 'static> Clerk<E> {
         from: PortHandle<P1>, 
         to: PortHandle<P2>
     ) -> Result<(), RoutingErr> {
+        let from = self.checked_key(&from)?;
+        let to = self.checked_key(&to)?;
+        self.edit(Edit::Route { from, to })
+    }
 
+    pub(crate) fn remove_route<P1: Port + 'static, P2: Port + 'static>(
+        &mut self,
+        from: PortHandle<P1>,
+        to: PortHandle<P2>
+    ) -> Result<(), RoutingErr> {
+        let from = self.checked_key(&from)?;
+        let to = self.checked_key(&to)?;
+        self.edit(Edit::Unroute { from, to })
+    }
 
-        // Convert buffer handles to buffer keys
-        let from_key = match P1::port_type() {
-            // The SystemInput port is an *output* port
-            // *named* SystemInput
-            PortType::SystemInput => BufferKey::System(SystemKey {
-                marker: TypeId::of::<P1>(),
-                instance_name: from.name,
-            }),
-            PortType::Output => BufferKey::User(UserKey {
-                processor_type: from.processor_type,
-                instance_name: from.name,
-                field_idx: from.field_idx,
-            }),
-            _ => {
-                return Err(RoutingErr::FromPortIsInput);
-            }
+    // A handle's key, if its port type matches the port the processor declares
+    fn checked_key<P: Port + 'static>(&self, handle: &PortHandle<P>) -> Result<BufferKey, RoutingErr> {
+        let key = handle_key(handle);
+        let (port, found) = self.ledger.resolve_port(key)?;
+        if found != P::port_type() {
+            return Err(RoutingErr::PortTypeMismatch { port, expected: P::port_type(), found });
+        }
+        Ok(key)
+    }
+
+    // Routes by instance and port name, for callers without PortHandles
+    pub(crate) fn route_named(
+        &mut self,
+        from: (&str, &str),
+        to: (&str, &str),
+    ) -> Result<(), RoutingErr> {
+        let (from_key, _) = self.ledger.find_port(from.0, from.1)?;
+        let (to_key, _) = self.ledger.find_port(to.0, to.1)?;
+        self.edit(Edit::Route { from: from_key, to: to_key })
+    }
+
+    // Applies an edit and records it, so it can be undone
+    fn edit(&mut self, edit: Edit<E>) -> Result<(), RoutingErr> {
+        self.apply(&edit)?;
+        self.history.record(edit);
+        Ok(())
+    }
+
+    fn apply(&mut self, edit: &Edit<E>) -> Result<(), RoutingErr> {
+        // checked before the ledger changes, so it never holds an edit the
+        // runtime didn't get
+        self.check_connected()?;
+        match *edit {
+            Edit::Route { from, to } => {
+                let schedule = self.ledger.add_route(from, to)?;
+                self.send_schedule(schedule)
+            },
+            Edit::Unroute { from, to } => {
+                let schedule = self.ledger.remove_route(from, to)?;
+                self.send_schedule(schedule)
+            },
+            Edit::Parameter { key, to: Some(value), .. } => self.set_parameter_key(key, value),
+            Edit::Parameter { key, to: None, .. } => {
+                self.ledger.parameters.remove(&key);
+                let schedule = self.ledger.schedule()?;
+                self.send_schedule(schedule)
+            },
+            Edit::Ramp(ref changes) => {
+                let targets: Vec<_> = changes.iter().map(|&(key, _, to)| (key, to)).collect();
+                self.ramp_keys(&targets)
+            },
+            Edit::Add(ref placement) => self.add_instance(placement),
+            Edit::Remove(ref placement) => self.remove_instance(placement.instance_name()),
+        }
+    }
+
+    pub(crate) fn add_processor<P: Processor>(&mut self, processor: P, instance_name: &'static str) -> Result<(), RoutingErr> {
+        let instance = Instance::new(processor, instance_name);
+        self.edit(Edit::Add(Box::new(Placement::new(instance))))
+    }
+
+    pub(crate) fn remove_processor(&mut self, instance_name: &str) -> Result<(), RoutingErr> {
+        let placement = self.placement(instance_name)?;
+        self.edit(Edit::Remove(Box::new(placement)))
+    }
+
+    // An instance as it is now, for adding it back once it's removed
+    fn placement(&self, instance_name: &str) -> Result<Placement<E>, RoutingErr> {
+        let instance = self.instances.get(instance_name)
+            .ok_or_else(|| RoutingErr::ProcessorNotFound { instance: instance_name.to_string() })?;
+        let id = instance.component.context_handle.component_id;
+        let touches = |key: &BufferKey| key.instance_name() == instance_name;
+
+        Ok(Placement {
+            instance: instance.clone(),
+            registry_id: self.ledger.registry_ids.get(instance_name).cloned(),
+            routes: self.ledger.routes.iter()
+                .filter(|(from, to)| touches(from) || touches(to))
+                .copied()
+                .collect(),
+            parameters: self.ledger.parameters.iter()
+                .filter(|(key, _)| touches(key))
+                .map(|(&key, &value)| (key, value))
+                .collect(),
+            bypassed: self.bypassed.contains(&id),
+            muted: self.muted.contains(&id),
+            soloed: self.soloed.contains(&id),
+        })
+    }
+
+    // Makes the instance's states and prepares them here, then hands them
+    // to the runtime ahead of the schedule that runs them
+    fn add_instance(&mut self, placement: &Placement<E>) -> Result<(), RoutingErr> {
+        let instance_name = placement.instance_name();
+        if self.ledger.components.values().any(|comp| comp.instance_name() == instance_name) {
+            return Err(RoutingErr::DuplicateInstance { instance: instance_name.to_string() });
+        }
+
+        let mut instance = placement.instance.clone();
+        let states = (instance.create_states)();
+        instance.place(ComponentId(self.next_component_id), self.next_slot, states.len());
+        let component = instance.component;
+        let id = component.context_handle.component_id;
+
+        self.ledger.components.insert((component.processor_type, instance_name), StoredComponent::User(component));
+        let scheduled = placement.routes.iter()
+            .try_for_each(|&(from, to)| {
+                let from_component = self.ledger.get_component_id_for_buffer_key(from)?;
+                let to_component = self.ledger.get_component_id_for_buffer_key(to)?;
+                self.ledger.link(from, to, from_component, to_component);
+                Ok(())
+            })
+            .and_then(|()| {
+                self.ledger.parameters.extend(placement.parameters.iter().copied());
+                self.ledger.schedule()
+            });
+        let schedule = match scheduled {
+            Ok(schedule) => schedule,
+            Err(err) => {
+                self.detach(instance_name, id);
+                return Err(err);
+            },
         };
-        
-        let to_key = match P2::port_type() {
-            // The SystemOutput port is an *input* port
-            // *named* SystemOutput
-            PortType::SystemOutput => BufferKey::System(SystemKey {
-                marker: TypeId::of::<P2>(),
-                instance_name: to.name,
-            }),
-            PortType::Input => BufferKey::User(UserKey {
-                processor_type: to.processor_type,
-                instance_name: to.name,
-                field_idx: to.field_idx,
+
+        let lifecycle = instance.lifecycle();
+        let states = Runtime::prepare_instance(lifecycle, states, self.audio_config(), self.seed);
+        #[cfg(feature = "serde")]
+        let state_slots = instance.state_slots();
+        #[cfg(feature = "serde")]
+        self.state_slots.extend(&state_slots);
+
+        let update = Update(Box::new(move |runtime: &mut Runtime<E>| {
+            runtime.add_instance(states, lifecycle);
+            #[cfg(feature = "serde")]
+            runtime.state_slots.extend(state_slots);
+        }));
+        self.update_tx.send(update).map_err(|_| RoutingErr::RuntimeDisconnected)?;
+
+        self.next_component_id += 1;
+        self.next_slot += instance.state_count;
+        if let Some(registry_id) = &placement.registry_id {
+            self.ledger.registry_ids.insert(instance_name, registry_id.clone());
+        }
+        switch(&mut self.bypassed, id, placement.bypassed);
+        switch(&mut self.muted, id, placement.muted);
+        switch(&mut self.soloed, id, placement.soloed);
+        self.instances.insert(instance_name, instance);
+
+        self.send_schedule(schedule)?;
+        self.send_controls()
+    }
+
+    // Takes an instance out of the graph. The runtime keeps playing it
+    // through any crossfade away from it, then releases it and hands its
+    // states back
+    fn remove_instance(&mut self, instance_name: &str) -> Result<(), RoutingErr> {
+        let instance = self.instances.get(instance_name)
+            .ok_or_else(|| RoutingErr::ProcessorNotFound { instance: instance_name.to_string() })?
+            .clone();
+        let handle = instance.component.context_handle;
+
+        self.detach(instance_name, handle.component_id);
+        let schedule = self.ledger.schedule()?;
+        self.send_schedule(schedule)?;
+
+        #[cfg(feature = "serde")]
+        for slot in &mut self.state_slots[handle.slot_ids_start..handle.slot_ids_start + instance.state_count] {
+            slot.codec = None;
+        }
+        let placeholders = (0..instance.state_count)
+            .map(|_| Box::new(UnsafeCell::new(())) as BoxedState)
+            .collect();
+        let removed_tx = hand_back_channel(&mut self.removed);
+        let removal = Removal {
+            component_id: handle.component_id,
+            slot_ids_start: handle.slot_ids_start,
+            states: placeholders,
+            hand_back: Box::new(move |states| {
+                let _ = removed_tx.try_send(states);
             }),
-            _  => {
-                return Err(RoutingErr::ToPortIsOutput);
+        };
+        let update = Update(Box::new(move |runtime: &mut Runtime<E>| runtime.remove_instance(removal)));
+        self.update_tx.send(update).map_err(|_| RoutingErr::RuntimeDisconnected)?;
+
+        // a removed solo may leave nothing soloed
+        self.send_controls()
+    }
+
+    // Drops an instance and everything that refers to it from the ledger
+    // and the controls, without rescheduling
+    fn detach(&mut self, instance_name: &str, id: ComponentId) {
+        let touching: Vec<_> = self.ledger.routes.iter()
+            .filter(|(from, to)| from.instance_name() == instance_name || to.instance_name() == instance_name)
+            .copied()
+            .collect();
+        for (from, to) in touching {
+            if let (Ok(from_component), Ok(to_component)) = (
+                self.ledger.get_component_id_for_buffer_key(from),
+                self.ledger.get_component_id_for_buffer_key(to),
+            ) {
+                self.ledger.unlink(from, to, from_component, to_component);
             }
+        }
+        self.ledger.parameters.retain(|key, _| key.instance_name() != instance_name);
+        self.ledger.components.retain(|_, comp| comp.instance_name() != instance_name);
+        self.ledger.registry_ids.remove(instance_name);
+        self.instances.remove(instance_name);
+        self.bypassed.remove(&id);
+        self.muted.remove(&id);
+        self.soloed.remove(&id);
+    }
+
+    // Reverts the latest edit. Returns false if there was nothing to undo
+    pub(crate) fn undo(&mut self) -> Result<bool, RoutingErr> {
+        let Some(edit) = self.history.pop_undo() else {
+            return Ok(false);
         };
+        if let Err(err) = self.apply(&edit.inverse()) {
+            self.history.push_undo(edit);
+            return Err(err);
+        }
+        self.history.push_redo(edit);
+        Ok(true)
+    }
 
-        match self.ledger.add_route(from_key, to_key) {
-
-            Ok((new_order, buffer_assignments, physical_buffers, system_buffers)) => {
-                // Send update to runtime
-                let update = Update(Box::new(move |runtime: &mut Runtime<E>| {
-                    runtime.execution_order = new_order;
-                    runtime.buffer_ids = buffer_assignments;
-                    runtime.system_buffers = system_buffers;
-                    
-                    // Update physical buffers
-                    for (physical_id, buffer_data) in physical_buffers {
-                        runtime.buffers.insert(physical_id, UnsafeCell::new(buffer_data));
-                    }
-                }));
-                
-                self.update_tx.send(update).map_err(|_| RoutingErr::PortNotFound)?;
-                Ok(())
-            },
-            Err(e) => {
-                return Err(e)
-            },
+    // Reapplies the latest undone edit. Returns false if there was nothing to redo
+    pub(crate) fn redo(&mut self) -> Result<bool, RoutingErr> {
+        let Some(edit) = self.history.pop_redo() else {
+            return Ok(false);
+        };
+        if let Err(err) = self.apply(&edit) {
+            self.history.push_redo(edit);
+            return Err(err);
+        }
+        self.history.push_undo(edit);
+        Ok(true)
+    }
+
+    pub(crate) fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    pub(crate) fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    pub(crate) fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    pub(crate) fn set_history_limit(&mut self, limit: usize) {
+        self.history.set_limit(limit);
+    }
+
+    pub(crate) fn set_parameter<P: Port + 'static>(&mut self, port: PortHandle<P>, value: f32) -> Result<(), RoutingErr> {
+        let key = self.checked_key(&port)?;
+        self.record_parameter(key, value)
+    }
+
+    pub(crate) fn set_parameter_named(&mut self, instance_name: &str, port_name: &str, value: f32) -> Result<(), RoutingErr> {
+        let (key, _) = self.ledger.find_port(instance_name, port_name)?;
+        self.record_parameter(key, value)
+    }
+
+    fn record_parameter(&mut self, key: BufferKey, value: f32) -> Result<(), RoutingErr> {
+        let from = self.ledger.parameters.get(&key).copied();
+        self.edit(Edit::Parameter { key, from, to: Some(value) })
+    }
+
+    fn set_parameter_key(&mut self, key: BufferKey, value: f32) -> Result<(), RoutingErr> {
+        self.ledger.set_parameter(key, value)?;
+
+        // A parameter that already has a buffer only needs its value changed
+        if let Some(&physical_buf) = self.ledger.parameter_buffers.get(&key) {
+            let update = Update(Box::new(move |runtime: &mut Runtime<E>| {
+                runtime.fill_parameter(physical_buf, value);
+            }));
+            return self.update_tx.send(update).map_err(|_| RoutingErr::RuntimeDisconnected);
         }
+
+        let schedule = self.ledger.schedule()?;
+        self.send_schedule(schedule)
+    }
+
+    // Sets many parameters at once, as one edit for undo. Values with a
+    // buffer already are ramped to over the next block instead of jumping,
+    // so morphs don't click
+    pub(crate) fn ramp_parameters(&mut self, parameters: &[PatchParameter]) -> Result<(), RoutingErr> {
+        // resolve every port first, so a bad one changes nothing
+        let changes = parameters.iter()
+            .map(|parameter| {
+                let (key, _) = self.ledger.find_port(&parameter.port.instance, &parameter.port.port)?;
+                self.ledger.check_parameter_port(key)?;
+                Ok((key, self.ledger.parameters.get(&key).copied(), Some(parameter.value)))
+            })
+            .collect::<Result<Vec<_>, RoutingErr>>()?;
+        if changes.is_empty() {
+            return Ok(());
+        }
+        self.edit(Edit::Ramp(changes))
+    }
+
+    // Ramps parameters to their targets, removing the ones whose target is None
+    fn ramp_keys(&mut self, targets: &[(BufferKey, Option<f32>)]) -> Result<(), RoutingErr> {
+        let mut ramps = Vec::with_capacity(targets.len());
+        let mut needs_schedule = false;
+        for &(key, value) in targets {
+            let Some(value) = value else {
+                self.ledger.parameters.remove(&key);
+                needs_schedule = true;
+                continue;
+            };
+            self.ledger.set_parameter(key, value)?;
+            if let Some(&physical_buf) = self.ledger.parameter_buffers.get(&key) {
+                ramps.push((physical_buf, value));
+            } else if !self.ledger.logical_buffer_map.contains_key(&key) {
+                needs_schedule = true;
+            }
+        }
+
+        // a new parameter buffer means a new schedule, which fills every
+        // parameter buffer with its value anyway
+        if needs_schedule {
+            let schedule = self.ledger.schedule()?;
+            return self.send_schedule(schedule);
+        }
+        if ramps.is_empty() {
+            return Ok(());
+        }
+
+        let update = Update(Box::new(move |runtime: &mut Runtime<E>| {
+            runtime.ramp_parameters(ramps);
+        }));
+        self.update_tx.send(update).map_err(|_| RoutingErr::RuntimeDisconnected)
+    }
+
+    fn send_schedule(&mut self, schedule: Schedule<E>) -> Result<(), RoutingErr> {
+        let schedule = pending_schedule(schedule, Vec::new());
+
+        // crossfades start from whatever the runtime last settled on
+        let settled = self.shared.settled.load(Ordering::Acquire);
+        self.sent.retain(|sent| sent.generation >= settled);
+        let blend = self.sent.iter()
+            .find(|sent| sent.generation == settled && self.crossfade_length > 0)
+            .and_then(|sent| self.ledger.blend_schedule(sent))
+            .map(|(blend, blends)| (pending_schedule(blend, blends), settled));
+
+        self.generation += 1;
+        self.sent.push(SentGraph {
+            generation: self.generation,
+            components: self.ledger.components.clone(),
+            routes: self.ledger.routes.clone(),
+            parameters: self.ledger.parameters.clone(),
+        });
+        let generation = self.generation;
+
+        // Send update to runtime
+        let update = Update(Box::new(move |runtime: &mut Runtime<E>| {
+            runtime.install_schedule(schedule, generation, blend);
+        }));
+        
+        self.update_tx.send(update).map_err(|_| RoutingErr::RuntimeDisconnected)?;
+
+        // rerouting changes which instances a solo keeps audible
+        if !self.soloed.is_empty() {
+            self.send_controls()?;
+        }
+        Ok(())
+    }
+
+    // Once the runtime is dropped it stays dropped, so an update that gets
+    // past this can only fail if it goes in the meantime, when there's no
+    // runtime left to disagree with
+    fn check_connected(&self) -> Result<(), RoutingErr> {
+        if self.update_tx.is_connected() {
+            Ok(())
+        } else {
+            Err(RoutingErr::RuntimeDisconnected)
+        }
+    }
+
+    pub(crate) fn set_bypass(&mut self, instance_name: &str, bypassed: bool) -> Result<(), RoutingErr> {
+        self.check_connected()?;
+        let id = self.ledger.user_component_id(instance_name)?;
+        switch(&mut self.bypassed, id, bypassed);
+        self.send_controls()
+    }
+
+    pub(crate) fn set_mute(&mut self, instance_name: &str, muted: bool) -> Result<(), RoutingErr> {
+        self.check_connected()?;
+        let id = self.ledger.user_component_id(instance_name)?;
+        switch(&mut self.muted, id, muted);
+        self.send_controls()
+    }
+
+    pub(crate) fn solo(&mut self, instance_name: &str, soloed: bool) -> Result<(), RoutingErr> {
+        self.check_connected()?;
+        let id = self.ledger.user_component_id(instance_name)?;
+        switch(&mut self.soloed, id, soloed);
+        self.send_controls()
+    }
+
+    // Sends every instance's bypass and mute state. While anything is
+    // soloed, instances off the soloed signal paths are muted as well
+    fn send_controls(&mut self) -> Result<(), RoutingErr> {
+        let audible = (!self.soloed.is_empty()).then(|| self.ledger.connected_through(&self.soloed));
+        let targets: Vec<(usize, bool, bool)> = self.ledger.components.values()
+            .filter(|comp| matches!(comp, StoredComponent::User(_)))
+            .map(|comp| {
+                let id = comp.component_id();
+                let muted = self.muted.contains(&id)
+                    || audible.as_ref().is_some_and(|audible| !audible.contains(&id));
+                (id.0, self.bypassed.contains(&id), muted)
+            })
+            .collect();
+
+        let update = Update(Box::new(move |runtime: &mut Runtime<E>| {
+            for (idx, bypassed, muted) in targets {
+                runtime.controls[idx].set_targets(bypassed, muted);
+            }
+        }));
+        self.update_tx.send(update).map_err(|_| RoutingErr::RuntimeDisconnected)
     }
     
+    pub(crate) fn set_crossfade(&mut self, samples: usize) -> Result<(), RoutingErr> {
+        self.crossfade_length = samples;
+        let update = Update(Box::new(move |runtime: &mut Runtime<E>| runtime.crossfade_length = samples));
+        self.update_tx.send(update).map_err(|_| RoutingErr::RuntimeDisconnected)
+    }
+
+    pub(crate) fn reset(&mut self) -> Result<(), RoutingErr> {
+        let update = Update(Box::new(|runtime: &mut Runtime<E>| runtime.reset()));
+        self.update_tx.send(update).map_err(|_| RoutingErr::RuntimeDisconnected)
+    }
+
     pub(crate) fn send_event(&mut self, event: E) {
         self.event_tx.send(event).unwrap();
     }
+
+    pub(crate) fn with_crossfade(mut self, samples: usize) -> Self {
+        self.crossfade_length = samples;
+        self
+    }
+
+    pub(crate) fn with_registry_ids(mut self, registry_ids: HashMap<&'static str, String>) -> Self {
+        self.ledger.registry_ids = registry_ids;
+        self
+    }
+
+    // The instances the graph was built with, which later ones are placed after
+    pub(crate) fn with_instances(mut self, instances: Vec<Instance<E>>, seed: u64) -> Self {
+        for instance in instances {
+            let handle = instance.component.context_handle;
+            self.next_component_id = self.next_component_id.max(handle.component_id.0 + 1);
+            self.next_slot = self.next_slot.max(handle.slot_ids_start + instance.state_count);
+            self.instances.insert(instance.component.instance_name, instance);
+        }
+        self.seed = seed;
+        self
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn with_state_slots(mut self, state_slots: Vec<StateSlot>) -> Self {
+        self.state_slots = state_slots;
+        self
+    }
+
+    #[cfg(feature = "serde")]
+    fn retire_channel(&mut self) -> SyncSender<Vec<StateCopy>> {
+        hand_back_channel(&mut self.retired)
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn request_snapshot(&mut self) -> PendingSnapshot {
+        // the states are copied into these on the audio thread, and encoded
+        // by whoever takes the snapshot
+        let mut copies = copy_targets(&self.state_slots);
+        let (copies_tx, copies_rx) = mpsc::sync_channel(1);
+        let retired_tx = self.retire_channel();
+
+        // runs at the start of a tick, so every state is between ticks
+        let update = Update(Box::new(move |runtime: &mut Runtime<E>| {
+            runtime.copy_states(&mut copies);
+            // nobody's waiting for them if the PendingSnapshot was dropped
+            if let Err(TrySendError::Disconnected(copies) | TrySendError::Full(copies)) = copies_tx.try_send(copies) {
+                let _ = retired_tx.try_send(copies);
+            }
+        }));
+        // if the runtime is gone the sender is dropped with the update,
+        // and the pending snapshot reports RuntimeDisconnected
+        let _ = self.update_tx.send(update);
+
+        PendingSnapshot::new(copies_rx)
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn restore(&mut self, snapshot: &StateSnapshot) -> Result<(), SnapshotErr> {
+        // decode here, so the audio thread only swaps the new states in and
+        // hands the old ones back to be dropped
+        let mut decoded: HashMap<usize, BoxedState> = decode_states(&self.state_slots, snapshot)?
+            .into_iter()
+            .map(StateCopy::into_parts)
+            .collect();
+
+        // every instance with a saved state is replaced by a fresh one,
+        // prepared here, which the audio thread releases the old one for
+        let config = self.audio_config();
+        let mut replacements = Vec::new();
+        for instance in self.instances.values() {
+            let start = instance.component.context_handle.slot_ids_start;
+            let slots = start..start + instance.state_count;
+            if !slots.clone().any(|idx| decoded.contains_key(&idx)) {
+                continue;
+            }
+            let mut states = (instance.create_states)();
+            for (idx, state) in slots.clone().zip(&mut states) {
+                if let Some(saved) = decoded.remove(&idx) {
+                    *state = saved;
+                }
+            }
+            let states = Runtime::prepare_instance(instance.lifecycle(), states, config, self.seed);
+            replacements.extend(slots.zip(states).map(|(idx, state)| StateCopy::new(idx, self.state_slots[idx], state)));
+        }
+        let retired_tx = self.retire_channel();
+
+        let update = Update(Box::new(move |runtime: &mut Runtime<E>| {
+            runtime.swap_states(&mut replacements);
+            let _ = retired_tx.try_send(replacements);
+        }));
+        self.update_tx.send(update).map_err(|_| SnapshotErr::RuntimeDisconnected)
+    }
+
+    // what the runtime was last prepared with
+    fn audio_config(&self) -> AudioConfig {
+        AudioConfig {
+            sample_rate: f32::from_bits(self.shared.sample_rate.load(Ordering::Acquire)),
+            max_block: self.ledger.buffer_len,
+        }
+    }
+
+    pub(crate) fn processors(&self) -> Vec<ProcessorInfo> {
+        self.ledger.processors()
+    }
+
+    pub(crate) fn latency(&self) -> usize {
+        self.ledger.latency
+    }
+
+    pub(crate) fn connections(&self) -> Vec<Connection> {
+        self.ledger.connections()
+    }
+
+    pub(crate) fn execution_order(&self) -> Vec<&'static str> {
+        self.ledger.execution_order()
+    }
+
+    pub(crate) fn describe(&self) -> GraphDescription {
+        GraphDescription {
+            processors: self.ledger.processors(),
+            connections: self.ledger.connections(),
+            execution_order: self.ledger.execution_order(),
+        }
+    }
     
 }
 
@@ -0,0 +1,157 @@
+use super::processor::{get_input, get_output, PortDescriptor, PortKind};
+use super::runtime::Runtime;
+use super::types::UserComponent;
+
+// How long bypass, mute and solo take to fade in or out
+pub(crate) const CONTROL_FADE_SECONDS: f32 = 0.005;
+
+// A gain moving linearly towards its target, 1.0 meaning fully on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Fade {
+    pub(crate) value: f32,
+    pub(crate) target: f32,
+}
+
+impl Default for Fade {
+    fn default() -> Self {
+        Self { value: 1.0, target: 1.0 }
+    }
+}
+
+impl Fade {
+    // the gain n samples into the current block
+    fn at(&self, n: usize, step: f32) -> f32 {
+        let moved = step * n as f32;
+        if self.target > self.value {
+            (self.value + moved).min(self.target)
+        } else {
+            (self.value - moved).max(self.target)
+        }
+    }
+
+    fn is_settled(&self) -> bool {
+        self.value == self.target
+    }
+
+    fn is_off(&self) -> bool {
+        self.is_settled() && self.value == 0.0
+    }
+
+    fn advance(&mut self, samples: usize, step: f32) {
+        self.value = self.at(samples, step);
+    }
+}
+
+// The audio thread's side of bypass, mute and solo for one instance
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct InstanceControl {
+    // 1.0 runs the processor, 0.0 passes its main input through
+    pub(crate) active: Fade,
+    // 0.0 silences every output; solo mutes through this too
+    pub(crate) level: Fade,
+}
+
+impl InstanceControl {
+    pub(crate) fn set_targets(&mut self, bypassed: bool, muted: bool) {
+        self.active.target = if bypassed { 0.0 } else { 1.0 };
+        self.level.target = if muted { 0.0 } else { 1.0 };
+    }
+
+    fn is_untouched(&self) -> bool {
+        self.active == Fade::default() && self.level == Fade::default()
+    }
+
+    pub(crate) fn advance(&mut self, samples: usize, step: f32) {
+        self.active.advance(samples, step);
+        self.level.advance(samples, step);
+    }
+}
+
+// The ports a bypass connects: the ones marked main, or else the first
+// audio input and output
+pub(crate) fn main_ports(ports: &[PortDescriptor]) -> (Option<usize>, Option<usize>) {
+    let find = |is_input: bool| {
+        let mut candidates = ports.iter()
+            .enumerate()
+            .filter(|(_, port)| port.port_type.is_input() == is_input);
+        candidates.clone()
+            .find(|(_, port)| port.main)
+            .or_else(|| candidates.find(|(_, port)| port.kind == PortKind::Audio))
+            .map(|(idx, _)| idx)
+    };
+    (find(true), find(false))
+}
+
+// Runs one instance under its control, in place of calling it directly
+pub(crate) fn run_controlled<E: Clone + Copy>(
+    runtime: &Runtime<E>,
+    component: &UserComponent<E>,
+    control: &InstanceControl,
+    step: f32,
+) {
+    let handle = component.context_handle;
+    if control.is_untouched() {
+        (component.component)(runtime, handle);
+        return;
+    }
+
+    // fully muted or fully bypassed instances aren't run at all
+    let silent = control.level.is_off();
+    let bypassed = control.active.is_off();
+    if !silent && !bypassed {
+        (component.component)(runtime, handle);
+    }
+
+    for (field_idx, port) in component.ports.iter().enumerate() {
+        if port.port_type.is_input() {
+            continue;
+        }
+        let mut out = get_output(runtime, handle.buffer_ids_start + field_idx);
+        if silent {
+            out.fill(0.0);
+            continue;
+        }
+
+        // the main output blends in the main input as the processor fades out,
+        // every other output just fades
+        let dry = if Some(field_idx) == component.main_output {
+            component.main_input.and_then(|idx| *get_input(runtime, handle.buffer_ids_start + idx))
+        } else {
+            None
+        };
+        for (i, sample) in out.iter_mut().enumerate() {
+            let active = control.active.at(i + 1, step);
+            let wet = if bypassed { 0.0 } else { *sample * active };
+            let dry = dry.and_then(|dry| dry.get(i)).map_or(0.0, |dry| dry * (1.0 - active));
+            *sample = (wet + dry) * control.level.at(i + 1, step);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fade() {
+        let mut fade = Fade { value: 1.0, target: 0.0 };
+        assert_eq!(fade.at(1, 0.25), 0.75);
+        assert_eq!(fade.at(8, 0.25), 0.0);
+        fade.advance(2, 0.25);
+        assert_eq!(fade.value, 0.5);
+        fade.advance(2, 0.25);
+        assert!(fade.is_off());
+    }
+
+    #[test]
+    fn test_main_ports() {
+        const PORTS: &[PortDescriptor] = &[
+            PortDescriptor::input("gain").kind(PortKind::Control),
+            PortDescriptor::input("left"),
+            PortDescriptor::input("right").main(),
+            PortDescriptor::output("out"),
+        ];
+        assert_eq!(main_ports(PORTS), (Some(2), Some(3)));
+        assert_eq!(main_ports(&PORTS[..1]), (None, None));
+    }
+}
@@ -0,0 +1,42 @@
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+use super::types::{ComponentId, PhysicalBuffer};
+
+// Where one side of a Blend reads from. An input that isn't routed on that
+// side reads its parameter, or its port's default
+#[derive(Clone, Copy)]
+pub(crate) enum BlendSide {
+    Buffer(PhysicalBuffer),
+    Constant(f32),
+}
+
+// Feeds an input whose source a routing change moved a mix of its old
+// source and its new one while the change is crossfaded. Like a
+// Compensation, it writes a buffer of its own which the input reads instead
+pub(crate) struct Blend {
+    pub(crate) component_id: ComponentId,
+    pub(crate) from: BlendSide,
+    pub(crate) to: BlendSide,
+    pub(crate) target: PhysicalBuffer,
+}
+
+impl Blend {
+    // Runs before the component reads the target, with the fade `done`
+    // samples in out of `length`
+    pub(crate) fn run(&self, buffers: &HashMap<PhysicalBuffer, UnsafeCell<Vec<f32>>>, done: usize, length: usize) {
+        let Some(target) = buffers.get(&self.target) else {
+            return
+        };
+        // Safety: the sides are buffers written earlier this tick, never the
+        // target, and only the component that owns this blend reads it
+        let target = unsafe { &mut *target.get() };
+        let side = |side: BlendSide, i: usize| match side {
+            BlendSide::Buffer(buf) => buffers.get(&buf).map_or(0.0, |cell| unsafe { (&*cell.get())[i] }),
+            BlendSide::Constant(value) => value,
+        };
+        for (i, sample) in target.iter_mut().enumerate() {
+            let t = ((done + i + 1) as f32 / length.max(1) as f32).min(1.0);
+            *sample = side(self.from, i) * (1.0 - t) + side(self.to, i) * t;
+        }
+    }
+}
@@ -0,0 +1,158 @@
+// read-only snapshots of the routing graph, handed out by the Router
+
+use std::any::TypeId;
+use std::fmt::{self, Display, Formatter, Write};
+use super::processor::{PortKind, PortType};
+
+// A processor instance, as the Clerk currently knows it
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ProcessorInfo {
+    pub instance_name: &'static str,
+    // TypeIds aren't stable across builds, so they aren't serialized
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub processor_type: TypeId,
+    // the Registry id it was added under, see Registry::add_to
+    pub registry_id: Option<String>,
+    // true for the system input and output
+    pub is_system: bool,
+    // samples the processor delays its outputs by, see Processor::latency
+    pub latency: usize,
+    pub ports: Vec<PortInfo>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PortInfo {
+    pub name: &'static str,
+    pub field_idx: usize,
+    pub port_type: PortType,
+    // metadata from the processor's PortDescriptor
+    pub kind: PortKind,
+    pub default: Option<f32>,
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+    pub unit: Option<&'static str>,
+    pub description: Option<&'static str>,
+    // the physical buffer this port reads or writes, None if it isn't routed
+    pub physical_buffer: Option<usize>,
+    // the constant this input reads while it isn't routed
+    pub parameter: Option<f32>,
+}
+
+// One end of a connection
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PortRef {
+    pub instance_name: &'static str,
+    pub port_name: &'static str,
+    pub field_idx: usize,
+}
+
+impl Display for PortRef {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.instance_name, self.port_name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Connection {
+    pub from: PortRef,
+    pub to: PortRef,
+}
+
+// Everything the Router knows about the graph, in one snapshot
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct GraphDescription {
+    pub processors: Vec<ProcessorInfo>,
+    pub connections: Vec<Connection>,
+    pub execution_order: Vec<&'static str>,
+}
+
+impl GraphDescription {
+    // Renders the graph in Graphviz DOT. Processors are record nodes with
+    // their inputs on the left and outputs on the right, labelled with their
+    // step in the execution order. Edges are labelled with the physical
+    // buffer they travel through, so reused buffers share a label.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        let node_id = |instance_name: &str| {
+            self.processors.iter()
+                .position(|p| p.instance_name == instance_name)
+                .map(|idx| format!("n{}", idx))
+        };
+
+        dot.push_str("digraph lyris {\n");
+        dot.push_str("    rankdir=LR;\n");
+        dot.push_str("    node [shape=record];\n");
+
+        for (idx, processor) in self.processors.iter().enumerate() {
+            let ports = |input: bool| processor.ports.iter()
+                .filter(|port| port.port_type.is_input() == input)
+                .map(|port| format!("<p{}> {}", port.field_idx, escape_record(port.name)))
+                .collect::<Vec<_>>()
+                .join("|");
+
+            let step = match self.execution_order.iter().position(|&name| name == processor.instance_name) {
+                Some(step) => format!("step {}", step),
+                None => String::from("unscheduled"),
+            };
+
+            let style = if processor.is_system { ", style=rounded" } else { "" };
+
+            let _ = writeln!(
+                dot,
+                "    n{} [label=\"{{{{{}}}|{}\\n{}|{{{}}}}}\"{}];",
+                idx,
+                ports(true),
+                escape_record(processor.instance_name),
+                step,
+                ports(false),
+                style,
+            );
+        }
+
+        for connection in &self.connections {
+            let (Some(from), Some(to)) = (node_id(connection.from.instance_name), node_id(connection.to.instance_name)) else {
+                continue;
+            };
+
+            let buffer = self.processors.iter()
+                .find(|p| p.instance_name == connection.from.instance_name)
+                .and_then(|p| p.ports.get(connection.from.field_idx))
+                .and_then(|port| port.physical_buffer);
+
+            let label = match buffer {
+                Some(buffer) => format!("buf {}", buffer),
+                None => String::from("unallocated"),
+            };
+
+            let _ = writeln!(
+                dot,
+                "    {}:p{} -> {}:p{} [label=\"{}\"];",
+                from,
+                connection.from.field_idx,
+                to,
+                connection.to.field_idx,
+                label,
+            );
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+// escapes the characters that mean something inside a DOT record label
+fn escape_record(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '{' | '}' | '|' | '<' | '>' | '"' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
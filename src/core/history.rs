@@ -0,0 +1,93 @@
+use std::collections::VecDeque;
+
+pub(crate) const DEFAULT_HISTORY_LIMIT: usize = 128;
+
+// Bounded undo and redo stacks. Once full, the oldest edit is forgotten
+pub(crate) struct History<T> {
+    undo: VecDeque<T>,
+    redo: Vec<T>,
+    limit: usize,
+}
+
+impl<T> History<T> {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            limit,
+        }
+    }
+
+    // A new edit makes everything that was undone unreachable
+    pub(crate) fn record(&mut self, edit: T) {
+        self.redo.clear();
+        self.push_undo(edit);
+    }
+
+    pub(crate) fn pop_undo(&mut self) -> Option<T> {
+        self.undo.pop_back()
+    }
+
+    pub(crate) fn pop_redo(&mut self) -> Option<T> {
+        self.redo.pop()
+    }
+
+    pub(crate) fn push_undo(&mut self, edit: T) {
+        if self.limit == 0 {
+            return;
+        }
+        if self.undo.len() == self.limit {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(edit);
+    }
+
+    pub(crate) fn push_redo(&mut self, edit: T) {
+        self.redo.push(edit);
+    }
+
+    pub(crate) fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub(crate) fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    pub(crate) fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        while self.undo.len() > limit {
+            self.undo.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounded_history() {
+        let mut history = History::new(2);
+        history.record(1);
+        history.record(2);
+        history.record(3);
+        assert_eq!(history.pop_undo(), Some(3));
+        history.push_redo(3);
+        assert_eq!(history.pop_undo(), Some(2));
+        // 1 fell off the end
+        assert_eq!(history.pop_undo(), None);
+
+        history.record(4);
+        assert!(!history.can_redo());
+
+        history.set_limit(0);
+        history.record(5);
+        assert!(!history.can_undo());
+    }
+}
@@ -0,0 +1,71 @@
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+use super::types::{ComponentId, PhysicalBuffer};
+
+// A delay the Clerk puts in front of an input that would otherwise arrive
+// ahead of the component's other inputs. It copies the source buffer into
+// a buffer of its own, which the input reads instead
+pub(crate) struct Compensation {
+    pub(crate) component_id: ComponentId,
+    pub(crate) field_idx: usize,
+    pub(crate) source: PhysicalBuffer,
+    pub(crate) target: PhysicalBuffer,
+    line: UnsafeCell<DelayLine>,
+}
+
+struct DelayLine {
+    samples: Vec<f32>,
+    position: usize,
+}
+
+impl Compensation {
+    pub(crate) fn new(
+        component_id: ComponentId,
+        field_idx: usize,
+        source: PhysicalBuffer,
+        target: PhysicalBuffer,
+        delay: usize,
+    ) -> Self {
+        Self {
+            component_id,
+            field_idx,
+            source,
+            target,
+            line: UnsafeCell::new(DelayLine { samples: vec![0.0; delay], position: 0 }),
+        }
+    }
+
+    pub(crate) fn delay(&self) -> usize {
+        unsafe { (*self.line.get()).samples.len() }
+    }
+
+    // Keeps the signal another schedule's delay was holding, if it delays
+    // the same input by as much, so rescheduling doesn't drop it
+    pub(crate) fn take_line(&mut self, previous: &mut Compensation) {
+        let same_input = (self.component_id, self.field_idx) == (previous.component_id, previous.field_idx);
+        if same_input && self.delay() == previous.delay() {
+            std::mem::swap(self.line.get_mut(), previous.line.get_mut());
+        }
+    }
+
+    // Silences the delay, for Runtime::reset
+    pub(crate) fn clear(&mut self) {
+        self.line.get_mut().samples.fill(0.0);
+    }
+
+    // Runs once a tick, after the source is written and before the
+    // component reads the target
+    pub(crate) fn run(&self, buffers: &HashMap<PhysicalBuffer, UnsafeCell<Vec<f32>>>) {
+        let (Some(source), Some(target)) = (buffers.get(&self.source), buffers.get(&self.target)) else {
+            return
+        };
+        // Safety: source and target are different buffers, and only the
+        // component that owns this delay touches it
+        let (source, target, line) = unsafe { (&*source.get(), &mut *target.get(), &mut *self.line.get()) };
+        for (input, output) in source.iter().zip(target.iter_mut()) {
+            *output = line.samples[line.position];
+            line.samples[line.position] = *input;
+            line.position = (line.position + 1) % line.samples.len();
+        }
+    }
+}
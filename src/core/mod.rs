@@ -5,8 +5,27 @@ pub mod processor;
 
 mod builder;
 pub(crate) mod router;
+pub(crate) mod graph;
+pub(crate) mod registry;
+pub(crate) mod patch;
+pub(crate) mod preset;
+mod history;
+mod control;
+mod latency;
+mod crossfade;
+pub mod testing;
+#[cfg(feature = "wav")]
+pub(crate) mod wav;
+#[cfg(feature = "wav")]
+pub(crate) mod render;
+#[cfg(feature = "serde")]
+pub(crate) mod snapshot;
+#[cfg(test)]
+pub(crate) mod test_processors;
 
 pub use runtime::Runtime;
 pub(crate) use clerk::Clerk;
 pub use router::Router;
-pub use builder::Builder;
\ No newline at end of file
+pub use builder::Builder;
+pub use registry::Registry;
+pub use patch::Patch;
\ No newline at end of file
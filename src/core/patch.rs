@@ -0,0 +1,480 @@
+use std::collections::HashSet;
+use std::sync::{Mutex, OnceLock};
+use std::fmt::{self, Debug, Display, Formatter};
+use super::builder::Builder;
+use super::registry::Registry;
+use super::router::{Router, RoutingErr};
+use super::runtime::Runtime;
+
+// Bumped whenever the patch format changes in a way older readers can't load
+pub const PATCH_VERSION: u32 = 1;
+
+// A saved graph: the processor instances, the routes between them and
+// the parameter values set on their inputs. Processors are named by their
+// Registry id, so a patch can be loaded by any build that registers them
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Patch {
+    pub version: u32,
+    pub instances: Vec<PatchInstance>,
+    pub connections: Vec<PatchConnection>,
+    pub parameters: Vec<PatchParameter>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PatchInstance {
+    pub name: String,
+    // the processor's Registry id
+    pub processor: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PatchPort {
+    pub instance: String,
+    pub port: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PatchConnection {
+    pub from: PatchPort,
+    pub to: PatchPort,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PatchParameter {
+    pub port: PatchPort,
+    pub value: f32,
+}
+
+impl PatchPort {
+    pub fn new(instance: &str, port: &str) -> Self {
+        Self {
+            instance: instance.to_string(),
+            port: port.to_string(),
+        }
+    }
+}
+
+impl Display for PatchPort {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.instance, self.port)
+    }
+}
+
+impl Patch {
+    // Captures the router's current graph. Every processor in it must be
+    // registered, so the patch can name it
+    pub fn from_router<E: Clone + Copy + Debug + 'static>(
+        router: &Router<E>,
+        registry: &Registry<E>,
+    ) -> Result<Patch, PatchErr> {
+        let graph = router.describe();
+
+        let mut instances = Vec::new();
+        let mut parameters = Vec::new();
+        for processor in graph.processors.iter().filter(|p| !p.is_system) {
+            // instances added through the registry know their id, others are
+            // looked up by type
+            let id = match &processor.registry_id {
+                Some(id) if registry.contains(id) => id.as_str(),
+                Some(_) => return Err(PatchErr::UnregisteredProcessor(processor.instance_name.to_string())),
+                None => registry.id_of(processor.processor_type).ok_or_else(|| {
+                    let instance = processor.instance_name.to_string();
+                    if registry.registers(processor.processor_type) {
+                        PatchErr::AmbiguousProcessor(instance)
+                    } else {
+                        PatchErr::UnregisteredProcessor(instance)
+                    }
+                })?,
+            };
+
+            instances.push(PatchInstance {
+                name: processor.instance_name.to_string(),
+                processor: id.to_string(),
+            });
+
+            for port in &processor.ports {
+                if let Some(value) = port.parameter {
+                    parameters.push(PatchParameter {
+                        port: PatchPort {
+                            instance: processor.instance_name.to_string(),
+                            port: port.name.to_string(),
+                        },
+                        value,
+                    });
+                }
+            }
+        }
+
+        let connections = graph.connections.iter()
+            .map(|connection| PatchConnection {
+                from: PatchPort {
+                    instance: connection.from.instance_name.to_string(),
+                    port: connection.from.port_name.to_string(),
+                },
+                to: PatchPort {
+                    instance: connection.to.instance_name.to_string(),
+                    port: connection.to.port_name.to_string(),
+                },
+            })
+            .collect();
+
+        Ok(Patch {
+            version: PATCH_VERSION,
+            instances,
+            connections,
+            parameters,
+        })
+    }
+
+    // Builds the patch on top of `builder`, which sets the buffer length and
+    // may already hold other processors.
+    //
+    // Instance names are `&'static str` throughout the runtime, so the names
+    // read from the patch are interned for the life of the program. Loading
+    // the same names again reuses them, and all the names patches intern
+    // together stay under NAME_BYTES.
+    //
+    // The instances are prepared when the runtime is built. If a connection
+    // or parameter fails, that runtime is dropped, which releases them
+    pub fn load<E: Clone + Copy + Debug + 'static>(
+        &self,
+        registry: &Registry<E>,
+        mut builder: Builder<E>,
+    ) -> Result<(Runtime<E>, Router<E>), PatchErr> {
+        if self.version > PATCH_VERSION {
+            return Err(PatchErr::UnsupportedVersion {
+                found: self.version,
+                supported: PATCH_VERSION,
+            });
+        }
+
+        // the processors and names are checked before anything is interned.
+        // Connections and parameters can only be checked on the built graph,
+        // so a patch that fails on those has already interned its names
+        let mut names = HashSet::new();
+        for instance in &self.instances {
+            if !registry.contains(&instance.processor) {
+                return Err(PatchErr::UnknownProcessor {
+                    instance: instance.name.clone(),
+                    processor: instance.processor.clone(),
+                });
+            }
+            if !names.insert(instance.name.as_str()) {
+                return Err(PatchErr::DuplicateInstance(instance.name.clone()));
+            }
+        }
+
+        let interned = intern_all(self.instances.iter().map(|instance| instance.name.as_str()))
+            .ok_or(PatchErr::NameLimit { limit: NAME_BYTES })?;
+        for (instance, name) in self.instances.iter().zip(interned) {
+            builder = registry.add_to(&instance.processor, builder, name)
+                .expect("processor was checked against the registry");
+        }
+
+        let (runtime, router) = builder.try_build().map_err(|err| match err {
+            RoutingErr::DuplicateInstance { instance } => PatchErr::DuplicateInstance(instance),
+            err => unreachable!("try_build only checks instance names, got: {}", err),
+        })?;
+
+        {
+            let mut clerk = router.clerk.lock().unwrap();
+
+            for connection in &self.connections {
+                clerk.route_named(
+                    (&connection.from.instance, &connection.from.port),
+                    (&connection.to.instance, &connection.to.port),
+                ).map_err(|err| PatchErr::Connection {
+                    from: connection.from.to_string(),
+                    to: connection.to.to_string(),
+                    err: Box::new(err),
+                })?;
+            }
+
+            for parameter in &self.parameters {
+                clerk.set_parameter_named(&parameter.port.instance, &parameter.port.port, parameter.value)
+                    .map_err(|err| PatchErr::Parameter {
+                        port: parameter.port.to_string(),
+                        err: Box::new(err),
+                    })?;
+            }
+
+            // the loaded patch is where editing starts, not something to undo
+            clerk.clear_history();
+        }
+
+        Ok((runtime, router))
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> Result<String, PatchErr> {
+        serde_json::to_string_pretty(self).map_err(|err| PatchErr::Format(err.to_string()))
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn from_json(text: &str) -> Result<Patch, PatchErr> {
+        serde_json::from_str(text).map_err(|err| PatchErr::Format(err.to_string()))
+    }
+
+    #[cfg(feature = "ron")]
+    pub fn to_ron(&self) -> Result<String, PatchErr> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| PatchErr::Format(err.to_string()))
+    }
+
+    #[cfg(feature = "ron")]
+    pub fn from_ron(text: &str) -> Result<Patch, PatchErr> {
+        ron::from_str(text).map_err(|err| PatchErr::Format(err.to_string()))
+    }
+}
+
+// How many bytes of names may be interned for the life of the program
+pub const NAME_BYTES: usize = 64 * 1024;
+
+// The one &'static str kept for each name interned so far, and their length
+#[derive(Default)]
+struct Interned {
+    names: HashSet<&'static str>,
+    bytes: usize,
+}
+
+fn interned() -> &'static Mutex<Interned> {
+    static NAMES: OnceLock<Mutex<Interned>> = OnceLock::new();
+    NAMES.get_or_init(Default::default)
+}
+
+// Interns every name, or none of them if the new ones would take the
+// interned names past NAME_BYTES
+fn intern_all<'a>(names: impl Iterator<Item = &'a str> + Clone) -> Option<Vec<&'static str>> {
+    let mut interned = interned().lock().unwrap();
+    let new: HashSet<&str> = names.clone().filter(|name| !interned.names.contains(name)).collect();
+    let bytes = interned.bytes + new.iter().map(|name| name.len()).sum::<usize>();
+    if bytes > NAME_BYTES {
+        return None;
+    }
+    for name in new {
+        interned.names.insert(Box::leak(name.to_string().into_boxed_str()));
+    }
+    interned.bytes = bytes;
+    Some(names.map(|name| *interned.names.get(name).unwrap()).collect())
+}
+
+// Interns one name the crate made up itself, e.g. from a port name, which
+// doesn't count towards NAME_BYTES
+pub(crate) fn intern(name: &str) -> &'static str {
+    let mut interned = interned().lock().unwrap();
+    if let Some(name) = interned.names.get(name) {
+        return name;
+    }
+    let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+    interned.names.insert(name);
+    name
+}
+
+#[derive(Debug)]
+pub enum PatchErr {
+    UnsupportedVersion { found: u32, supported: u32 },
+    UnknownProcessor { instance: String, processor: String },
+    UnregisteredProcessor(String),
+    // the instance's type is registered under several ids, and it wasn't
+    // added through Registry::add_to to say which
+    AmbiguousProcessor(String),
+    DuplicateInstance(String),
+    // the patch's new instance names don't fit in what's left of NAME_BYTES
+    NameLimit { limit: usize },
+    Connection { from: String, to: String, err: Box<RoutingErr> },
+    Parameter { port: String, err: Box<RoutingErr> },
+    Format(String),
+}
+
+impl Display for PatchErr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            PatchErr::UnsupportedVersion { found, supported } =>
+                write!(f, "Patch version {} is newer than the supported version {}", found, supported),
+            PatchErr::UnknownProcessor { instance, processor } =>
+                write!(f, "Instance \"{}\" uses processor \"{}\", which is not in the registry", instance, processor),
+            PatchErr::UnregisteredProcessor(instance) =>
+                write!(f, "Instance \"{}\" uses a processor that is not in the registry", instance),
+            PatchErr::AmbiguousProcessor(instance) =>
+                write!(f, "Instance \"{}\" uses a processor registered under more than one id", instance),
+            PatchErr::DuplicateInstance(instance) =>
+                write!(f, "Instance \"{}\" appears more than once", instance),
+            PatchErr::NameLimit { limit } =>
+                write!(f, "The patch's instance names would take interned names past {} bytes", limit),
+            PatchErr::Connection { from, to, err } =>
+                write!(f, "Could not route {} to {}: {}", from, to, err),
+            PatchErr::Parameter { port, err } =>
+                write!(f, "Could not set parameter {}: {}", port, err),
+            PatchErr::Format(message) =>
+                write!(f, "Malformed patch: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for PatchErr {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::processor::*;
+    use crate::core::test_processors::*;
+
+    fn registry() -> Registry<TestEvent> {
+        Registry::new()
+            .register("source", || Source)
+            .register("gain", || Gain)
+    }
+
+    fn saved_patch() -> Patch {
+        let (_runtime, router) = Builder::<TestEvent>::new()
+            .add_processor(Source, "osc")
+            .add_processor(Gain, "amp")
+            .build();
+
+        router.route(named_port::<Source, Output>("osc", 0), named_port::<Gain, Input>("amp", 0)).unwrap();
+        router.route(named_port::<Gain, Output>("amp", 2), output()).unwrap();
+        router.set_parameter(named_port::<Gain, Input>("amp", 1), 0.25).unwrap();
+
+        Patch::from_router(&router, &registry()).unwrap()
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let patch = saved_patch();
+        assert_eq!(patch.version, PATCH_VERSION);
+        assert_eq!(patch.instances, vec![
+            PatchInstance { name: "osc".into(), processor: "source".into() },
+            PatchInstance { name: "amp".into(), processor: "gain".into() },
+        ]);
+        assert_eq!(patch.connections.len(), 2);
+        assert_eq!(patch.connections[1].to.instance, "__system_output__");
+        assert_eq!(patch.parameters, vec![PatchParameter {
+            port: PatchPort { instance: "amp".into(), port: "gain".into() },
+            value: 0.25,
+        }]);
+
+        let (mut runtime, router) = patch.load(&registry(), Builder::new().buffer_length(4)).unwrap();
+        let mut out = [0.0; 4];
+        runtime.process(None, &mut out);
+        assert_eq!(out, [0.25; 4]);
+
+        // saving the loaded graph gives back the same patch
+        assert_eq!(Patch::from_router(&router, &registry()).unwrap(), patch);
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let mut patch = saved_patch();
+        patch.instances[1].processor = "reverb".into();
+        let err = patch.load(&registry(), Builder::new()).err().unwrap();
+        assert!(matches!(&err, PatchErr::UnknownProcessor { instance, processor } if instance == "amp" && processor == "reverb"));
+        assert_eq!(err.to_string(), "Instance \"amp\" uses processor \"reverb\", which is not in the registry");
+
+        let mut patch = saved_patch();
+        patch.version = PATCH_VERSION + 1;
+        assert!(matches!(patch.load(&registry(), Builder::new()), Err(PatchErr::UnsupportedVersion { .. })));
+
+        let mut patch = saved_patch();
+        patch.connections[0].to.port = "sidechain".into();
+        let err = patch.load(&registry(), Builder::new()).err().unwrap();
+        let PatchErr::Connection { err: routing_err, .. } = &err else { panic!("expected a connection error") };
+        assert_eq!(**routing_err, RoutingErr::PortNotFound { instance: "amp".into(), port: "sidechain".into() });
+        assert_eq!(err.to_string(), "Could not route osc.audio_out to amp.sidechain: \"amp\" has no port named \"sidechain\"");
+
+        // saving needs every processor to be registered
+        let (_runtime, router) = Builder::<TestEvent>::new().add_processor(Source, "osc").build();
+        let registry = Registry::<TestEvent>::new().register("gain", || Gain);
+        assert!(matches!(Patch::from_router(&router, &registry), Err(PatchErr::UnregisteredProcessor(name)) if name == "osc"));
+    }
+
+    #[test]
+    fn test_load_releases() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let released = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&released);
+        let registry = registry().register("tracker", move || Tracker { released: Arc::clone(&counted) });
+        let patch = Patch {
+            version: PATCH_VERSION,
+            instances: vec![PatchInstance { name: "t".into(), processor: "tracker".into() }],
+            connections: Vec::new(),
+            parameters: Vec::new(),
+        };
+
+        // the runtime a load replaces releases its instances when dropped
+        let (runtime, _router) = patch.load(&registry, Builder::new()).unwrap();
+        let (_runtime, _router) = patch.load(&registry, Builder::new()).unwrap();
+        drop(runtime);
+        assert_eq!(released.load(Ordering::SeqCst), 1);
+
+        // and so does one built for a patch that fails to load
+        let mut broken = patch.clone();
+        broken.parameters.push(PatchParameter {
+            port: PatchPort { instance: "t".into(), port: "gain".into() },
+            value: 0.5,
+        });
+        assert!(broken.load(&registry, Builder::new()).is_err());
+        assert_eq!(released.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_registry_ids() {
+        // one type under two ids, told apart by the id each instance was added under
+        let registry = Registry::<TestEvent>::new()
+            .register("short", || Delay::new(3))
+            .register("long", || Delay::new(5));
+        let builder = registry.add_to("short", Builder::new(), "first").unwrap();
+        let builder = registry.add_to("long", builder, "second").unwrap();
+        let (_runtime, router) = builder.build();
+        let patch = Patch::from_router(&router, &registry).unwrap();
+        assert_eq!(patch.instances, vec![
+            PatchInstance { name: "first".into(), processor: "short".into() },
+            PatchInstance { name: "second".into(), processor: "long".into() },
+        ]);
+
+        let (_runtime, router) = patch.load(&registry, Builder::new()).unwrap();
+        assert_eq!(Patch::from_router(&router, &registry).unwrap(), patch);
+
+        // added without the registry, the type alone can't say which
+        let (_runtime, router) = Builder::<TestEvent>::new().add_processor(Delay::new(3), "delay").build();
+        assert!(matches!(Patch::from_router(&router, &registry), Err(PatchErr::AmbiguousProcessor(name)) if name == "delay"));
+    }
+
+    #[test]
+    fn test_names_interned() {
+        let patch = saved_patch();
+        let (_runtime, first) = patch.load(&registry(), Builder::new()).unwrap();
+        let (_runtime, second) = patch.load(&registry(), Builder::new()).unwrap();
+        let name_of = |router: &Router<TestEvent>| router.describe().processors[2].instance_name;
+        assert!(std::ptr::eq(name_of(&first), name_of(&second)));
+
+        // names that would go past the limit fail the load and intern nothing
+        let mut patch = saved_patch();
+        patch.instances[0].name = "x".repeat(NAME_BYTES + 1);
+        assert!(matches!(patch.load(&registry(), Builder::new()), Err(PatchErr::NameLimit { limit: NAME_BYTES })));
+        assert!(!interned().lock().unwrap().names.contains(patch.instances[0].name.as_str()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json_round_trip() {
+        let patch = saved_patch();
+        let json = patch.to_json().unwrap();
+        assert_eq!(Patch::from_json(&json).unwrap(), patch);
+        assert!(matches!(Patch::from_json("{"), Err(PatchErr::Format(_))));
+    }
+
+    #[cfg(feature = "ron")]
+    #[test]
+    fn test_ron_round_trip() {
+        let patch = saved_patch();
+        let ron = patch.to_ron().unwrap();
+        assert_eq!(Patch::from_ron(&ron).unwrap(), patch);
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use super::patch::{PatchParameter, PatchPort};
+use super::router::Router;
+
+// The parameter values of a graph at one moment, for recalling sounds and
+// morphing between them. Unlike a Patch it says nothing about routing
+#[derive(Clone, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Preset {
+    pub parameters: Vec<PatchParameter>,
+}
+
+// How a parameter travels from its value in one preset to the other
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MorphCurve {
+    #[default]
+    Linear,
+    // equal ratios for equal steps of t, e.g. for frequencies. Falls back
+    // to linear unless both values are positive
+    Exponential,
+    // eases in and out of both ends
+    Smooth,
+    // jumps from one value to the other at t = 0.5, e.g. for switches
+    Step,
+}
+
+impl MorphCurve {
+    pub fn interpolate(&self, from: f32, to: f32, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            MorphCurve::Linear => from + (to - from) * t,
+            MorphCurve::Exponential if from > 0.0 && to > 0.0 => from * (to / from).powf(t),
+            MorphCurve::Exponential => from + (to - from) * t,
+            MorphCurve::Smooth => from + (to - from) * t * t * (3.0 - 2.0 * t),
+            MorphCurve::Step => if t < 0.5 { from } else { to },
+        }
+    }
+}
+
+// Per-parameter curves, and parameters the morph leaves alone
+#[derive(Clone, Debug, Default)]
+pub struct MorphOptions {
+    pub default_curve: MorphCurve,
+    pub curves: HashMap<PatchPort, MorphCurve>,
+    pub excluded: HashSet<PatchPort>,
+}
+
+impl MorphOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn default_curve(mut self, curve: MorphCurve) -> Self {
+        self.default_curve = curve;
+        self
+    }
+
+    pub fn curve(mut self, instance: &str, port: &str, curve: MorphCurve) -> Self {
+        self.curves.insert(PatchPort::new(instance, port), curve);
+        self
+    }
+
+    pub fn exclude(mut self, instance: &str, port: &str) -> Self {
+        self.excluded.insert(PatchPort::new(instance, port));
+        self
+    }
+}
+
+impl Preset {
+    // Captures every parameter currently set on the router
+    pub fn from_router<E: Clone + Copy + Debug + 'static>(router: &Router<E>) -> Preset {
+        let parameters = router.processors().into_iter()
+            .flat_map(|processor| {
+                let instance_name = processor.instance_name;
+                processor.ports.into_iter().filter_map(move |port| Some(PatchParameter {
+                    port: PatchPort::new(instance_name, port.name),
+                    value: port.parameter?,
+                }))
+            })
+            .collect();
+
+        Preset { parameters }
+    }
+
+    pub fn get(&self, instance: &str, port: &str) -> Option<f32> {
+        self.parameters.iter()
+            .find(|parameter| parameter.port.instance == instance && parameter.port.port == port)
+            .map(|parameter| parameter.value)
+    }
+
+    // The values `t` of the way from `self` to `other`. Only parameters
+    // found in both presets are morphed; the rest keep their current value
+    pub fn morph(&self, other: &Preset, t: f32, options: &MorphOptions) -> Vec<PatchParameter> {
+        self.parameters.iter()
+            .filter(|parameter| !options.excluded.contains(&parameter.port))
+            .filter_map(|from| {
+                let to = other.get(&from.port.instance, &from.port.port)?;
+                let curve = options.curves.get(&from.port).unwrap_or(&options.default_curve);
+                Some(PatchParameter {
+                    port: from.port.clone(),
+                    value: curve.interpolate(from.value, to, t),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::processor::*;
+    use crate::core::test_processors::*;
+    use crate::Builder;
+
+    fn preset(values: &[(&str, f32)]) -> Preset {
+        Preset {
+            parameters: values.iter()
+                .map(|&(port, value)| PatchParameter { port: PatchPort::new("amp", port), value })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_curves() {
+        assert_eq!(MorphCurve::Linear.interpolate(1.0, 3.0, 0.5), 2.0);
+        assert_eq!(MorphCurve::Exponential.interpolate(100.0, 400.0, 0.5), 200.0);
+        assert_eq!(MorphCurve::Exponential.interpolate(-1.0, 1.0, 0.5), 0.0);
+        assert_eq!(MorphCurve::Smooth.interpolate(0.0, 1.0, 0.25), 0.15625);
+        assert_eq!(MorphCurve::Step.interpolate(0.0, 1.0, 0.49), 0.0);
+        assert_eq!(MorphCurve::Step.interpolate(0.0, 1.0, 0.5), 1.0);
+        // t is clamped
+        assert_eq!(MorphCurve::Linear.interpolate(1.0, 3.0, 2.0), 3.0);
+    }
+
+    #[test]
+    fn test_morph_values() {
+        let a = preset(&[("gain", 1.0), ("mix", 0.0), ("freq", 100.0), ("only_a", 5.0)]);
+        let b = preset(&[("gain", 3.0), ("mix", 1.0), ("freq", 400.0)]);
+        let options = MorphOptions::new()
+            .curve("amp", "freq", MorphCurve::Exponential)
+            .exclude("amp", "mix");
+
+        assert_eq!(a.morph(&b, 0.5, &options), preset(&[("gain", 2.0), ("freq", 200.0)]).parameters);
+    }
+
+    #[test]
+    fn test_router_morph() {
+        let (mut runtime, router) = Builder::<TestEvent>::new()
+            .add(Source)
+            .add(Gain)
+            .buffer_length(4)
+            .build();
+        router.route(source_out(), gain_in()).unwrap();
+        router.route(gain_out(), output()).unwrap();
+
+        router.set_parameter(gain_amount(), 1.0).unwrap();
+        let a = Preset::from_router(&router);
+        router.set_parameter(gain_amount(), 3.0).unwrap();
+        let b = Preset::from_router(&router);
+        assert_eq!(b.get(std::any::type_name::<Gain>(), "gain"), Some(3.0));
+
+        let mut out = [0.0; 4];
+        runtime.process(None, &mut out);
+        assert_eq!(out, [3.0; 4]);
+
+        // ramps over one block, then holds
+        router.morph(&a, &b, 0.5).unwrap();
+        runtime.process(None, &mut out);
+        assert_eq!(out, [2.75, 2.5, 2.25, 2.0]);
+        runtime.process(None, &mut out);
+        assert_eq!(out, [2.0; 4]);
+        assert_eq!(Preset::from_router(&router).get(std::any::type_name::<Gain>(), "gain"), Some(2.0));
+
+        // morphing twice before a tick ramps from the value last played
+        router.morph(&a, &b, 0.0).unwrap();
+        router.morph(&a, &b, 1.0).unwrap();
+        runtime.process(None, &mut out);
+        assert_eq!(out, [2.25, 2.5, 2.75, 3.0]);
+
+        let options = MorphOptions::new().exclude(std::any::type_name::<Gain>(), "gain");
+        router.morph_with(&a, &b, 0.0, &options).unwrap();
+        runtime.process(None, &mut out);
+        assert_eq!(out, [3.0; 4]);
+
+        // each morph is one edit, undone with a ramp too
+        router.undo().unwrap();
+        runtime.process(None, &mut out);
+        assert_eq!(out, [2.5, 2.0, 1.5, 1.0]);
+        router.undo().unwrap();
+        runtime.process(None, &mut out);
+        assert_eq!(out, [1.25, 1.5, 1.75, 2.0]);
+        router.redo().unwrap();
+        runtime.process(None, &mut out);
+        runtime.process(None, &mut out);
+        assert_eq!(out, [1.0; 4]);
+
+        // setting a parameter cuts its ramp short
+        router.morph(&a, &b, 1.0).unwrap();
+        router.set_parameter(gain_amount(), 0.5).unwrap();
+        runtime.process(None, &mut out);
+        assert_eq!(out, [0.5; 4]);
+        runtime.process(None, &mut out);
+        assert_eq!(out, [0.5; 4]);
+
+        let unknown = Preset {
+            parameters: vec![PatchParameter { port: PatchPort::new("reverb", "mix"), value: 0.0 }],
+        };
+        assert!(matches!(router.morph(&unknown, &unknown, 0.5), Err(crate::RoutingErr::ProcessorNotFound { instance }) if instance == "reverb"));
+    }
+}
@@ -3,15 +3,19 @@ pub use std::any::TypeId;
 pub use std::cell::UnsafeCell;
 pub use std::marker::PhantomData;
 pub use std::any::Any;
-pub use super::types::{ContextHandle, Context, BufferIdx};
+pub use super::types::{ContextHandle, Context, BufferIdx, AudioConfig};
 pub use super::Runtime;
 pub use super::router::PortHandle;
 pub use std::ops::{Deref, DerefMut};
+#[cfg(feature = "serde")]
+pub use super::snapshot::{StateCodec, PersistentState};
 
 
 pub trait Port{
     fn port_type() -> PortType;
 }
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum PortType{
     SystemInput,
     SystemOutput,
@@ -19,10 +23,125 @@ pub enum PortType{
     Output
 }
 
+impl PortType {
+    // SystemOutput is an *input* port named after the system output,
+    // and SystemInput is an *output* port named after the system input
+    pub fn is_input(&self) -> bool {
+        matches!(self, PortType::Input | PortType::SystemOutput)
+    }
+
+    pub fn is_output(&self) -> bool {
+        !self.is_input()
+    }
+}
+
+// What the samples in a port's buffer mean. Every port is a buffer of
+// f32s; the kind tells UIs and mappers how to treat it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum PortKind {
+    Audio,
+    // a slowly changing value, e.g. a cutoff frequency
+    Control,
+    // gates and triggers, nonzero while active
+    Event,
+}
+
+// Describes one port of a processor, indexed by field_idx. Built with
+// const fns, so a processor can keep its table in a const:
+//
+//     PortDescriptor::input("cutoff")
+//         .kind(PortKind::Control)
+//         .default_value(1000.0)
+//         .range(20.0, 20000.0)
+//         .unit("Hz")
+//         .description("Filter cutoff frequency")
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PortDescriptor {
+    pub name: &'static str,
+    pub port_type: PortType,
+    pub kind: PortKind,
+    // the value the processor uses while the input isn't routed or set
+    pub default: Option<f32>,
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+    pub unit: Option<&'static str>,
+    pub description: Option<&'static str>,
+    // what a bypass passes through, see main()
+    pub main: bool,
+}
+
+impl PortDescriptor {
+    const fn new(name: &'static str, port_type: PortType) -> Self {
+        Self {
+            name,
+            port_type,
+            kind: PortKind::Audio,
+            default: None,
+            min: None,
+            max: None,
+            unit: None,
+            description: None,
+            main: false,
+        }
+    }
+
+    // an audio input, until told otherwise
+    pub const fn input(name: &'static str) -> Self {
+        Self::new(name, PortType::Input)
+    }
+
+    // an audio output, until told otherwise
+    pub const fn output(name: &'static str) -> Self {
+        Self::new(name, PortType::Output)
+    }
+
+    pub const fn kind(self, kind: PortKind) -> Self {
+        Self { kind, ..self }
+    }
+
+    pub const fn default_value(self, default: f32) -> Self {
+        Self { default: Some(default), ..self }
+    }
+
+    pub const fn range(self, min: f32, max: f32) -> Self {
+        Self { min: Some(min), max: Some(max), ..self }
+    }
+
+    pub const fn unit(self, unit: &'static str) -> Self {
+        Self { unit: Some(unit), ..self }
+    }
+
+    pub const fn description(self, description: &'static str) -> Self {
+        Self { description: Some(description), ..self }
+    }
+
+    // Marks the input a bypass copies from, or the output it copies to.
+    // Without one the first audio input or output is used
+    pub const fn main(self) -> Self {
+        Self { main: true, ..self }
+    }
+
+    // Clamps a value into the declared range, for UIs and controller mappings
+    pub fn clamp(&self, value: f32) -> f32 {
+        let value = self.min.map_or(value, |min| value.max(min));
+        self.max.map_or(value, |max| value.min(max))
+    }
+}
+
+pub(crate) const SYSTEM_INPUT_PORTS: &[PortDescriptor] = &[
+    PortDescriptor::new("input", PortType::SystemInput),
+];
+
+pub(crate) const SYSTEM_OUTPUT_PORTS: &[PortDescriptor] = &[
+    PortDescriptor::new("output", PortType::SystemOutput),
+];
+
 // Processor argument marker types
 pub struct Input<'a>(Option<&'a [f32]>);
 pub struct Output<'a>(&'a mut [f32]);
-pub struct State<'a, T: Default + Send + 'static>(&'a mut T);
+pub struct State<'a, T: Send + 'static>(&'a mut T);
 pub struct Events<'a, E>(&'a mut E);
 
 impl<'a> Deref for Input<'a> {
@@ -47,7 +166,7 @@ impl<'a> DerefMut for Output<'a> {
     }
 }
 
-impl<T: Default + Send> Deref for State<'_, T> {
+impl<T: Send> Deref for State<'_, T> {
     type Target = T;
     
     fn deref(&self) -> &Self::Target {
@@ -55,7 +174,7 @@ impl<T: Default + Send> Deref for State<'_, T> {
     }
 }
 
-impl<T: Default + Send> DerefMut for State<'_, T> {
+impl<T: Send> DerefMut for State<'_, T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         self.0  // Remove the &mut - self.0 is already &mut T
     }
@@ -106,17 +225,54 @@ pub trait Processor: 'static {
     type Handle: ProcessorHandle;
     fn buffers_count() -> usize;
     fn slot_count() -> usize;
+    // one descriptor per buffer, in field_idx order
+    fn ports() -> &'static [PortDescriptor];
     fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle);
-    fn create_states() -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>>;
+    // Builds this instance's states from the processor value handed to
+    // Builder::add, so they can depend on its configuration
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>>;
     fn get_handle() -> Self::Handle;
+
+    // Samples this instance delays its outputs by, e.g. for lookahead or
+    // FFT frames. The Clerk delays parallel paths to line up with it
+    fn latency(&self) -> usize {
+        0
+    }
+
+    // Optional lifecycle hooks. They run outside of call, where no buffers
+    // are assigned, so they should only touch the instance's states.
+    //
+    // prepare runs off the audio thread, once when the runtime is built or
+    // the instance is added through Router::add_processor, and again on
+    // Runtime::prepare and when a snapshot is restored, so it's the place
+    // to allocate
+    fn prepare<E: Clone + Copy>(_runtime: &Runtime<E>, _handle: ContextHandle, _config: AudioConfig) {}
+
+    // Clears tails and other history, e.g. on transport stop or a panic button
+    fn reset<E: Clone + Copy>(_runtime: &Runtime<E>, _handle: ContextHandle) {}
+
+    // Runs once when the instance goes away: when Router::remove_processor
+    // takes it out of the graph, when a restored snapshot replaces its
+    // states, or when the runtime is dropped, which is how a runtime
+    // replaced by Patch::load releases its instances
+    fn release<E: Clone + Copy>(_runtime: &Runtime<E>, _handle: ContextHandle) {}
+
+    // Opts states into snapshots, aligned with create_states().
+    // States without a codec are left out of snapshots
+    #[cfg(feature = "serde")]
+    fn state_codecs() -> Vec<Option<StateCodec>> {
+        Vec::new()
+    }
 }
 
 pub trait ProcessorHandle {}
 
 pub fn get_input<E: Clone + Copy + 'static>(runtime: &Runtime<E>, buffer_idx: BufferIdx) -> Input {
 
-    let buffer_id = runtime.buffer_ids[buffer_idx.0]
-        .expect("Input buffer not routed to physical buffer");
+    // inputs may or may not receive a signal
+    let Some(buffer_id) = runtime.buffer_ids[buffer_idx.0] else {
+        return Input(None);
+    };
     
     if let Some(buffer_cell) = runtime.buffers.get(&buffer_id) {
 
@@ -143,7 +299,7 @@ pub fn get_output<E: Clone + Copy + 'static>(runtime: &Runtime<E>, buffer_idx: B
     Output(buffer_ref)
 }
 
-pub fn get_state<T: Default + Send + 'static, E: Clone + Copy + 'static>(
+pub fn get_state<T: Send + 'static, E: Clone + Copy + 'static>(
     runtime: &Runtime<E>, 
     state_idx: usize
 ) -> State<T> {
@@ -207,6 +363,14 @@ mod test_filter{
         fn buffers_count() -> usize {2}
         fn slot_count() -> usize {1}
 
+        fn ports() -> &'static [PortDescriptor] {
+            const PORTS: &[PortDescriptor] = &[
+                PortDescriptor::input("audio_in"),
+                PortDescriptor::output("audio_out"),
+            ];
+            PORTS
+        }
+
         fn call<E: Clone + Copy>(runtime: &Runtime<E>, ctx_handle: ContextHandle){
             let ctx = runtime.get_ctx(ctx_handle);
             let audio_in_idx = ctx.handle.buffer_ids_start + 0;
@@ -221,7 +385,7 @@ mod test_filter{
 
         }
 
-        fn create_states() -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
             vec![
                 Box::new(UnsafeCell::new(FilterState::default())),
             ]
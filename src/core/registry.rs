@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use std::any::TypeId;
+use std::fmt::Debug;
+use super::builder::Builder;
+use super::processor::Processor;
+
+// Adds one instance of a registered processor to a builder
+type AddFn<E> = Box<dyn Fn(Builder<E>, &'static str) -> Builder<E>>;
+
+struct RegistryEntry<E: Clone + Copy + Debug + 'static> {
+    processor_type: TypeId,
+    add: AddFn<E>,
+}
+
+// Registry maps stable string identifiers to processor factories, so that
+// patches can name processors without relying on TypeIds
+pub struct Registry<E: Clone + Copy + Debug + 'static> {
+    entries: HashMap<String, RegistryEntry<E>>,
+}
+
+impl<E: Clone + Copy + Debug + 'static> Default for Registry<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E: Clone + Copy + Debug + 'static> Registry<E> {
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+
+    // Registering the same id twice replaces the earlier factory. The
+    // factory can capture configuration, e.g. `move || Delay::new(max_seconds)`
+    pub fn register<P: Processor>(mut self, id: impl Into<String>, factory: impl Fn() -> P + 'static) -> Self {
+        let entry = RegistryEntry {
+            processor_type: TypeId::of::<P>(),
+            add: Box::new(move |builder: Builder<E>, instance_name| {
+                builder.add_processor(factory(), instance_name)
+            }),
+        };
+        self.entries.insert(id.into(), entry);
+        self
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.entries.contains_key(id)
+    }
+
+    // The id a processor type was registered under, None unless there's
+    // exactly one. A type registered under several ids, each with its own
+    // configuration, can't tell them apart from the type alone
+    pub fn id_of(&self, processor_type: TypeId) -> Option<&str> {
+        let mut ids = self.entries.iter()
+            .filter(|(_, entry)| entry.processor_type == processor_type)
+            .map(|(id, _)| id.as_str());
+        let id = ids.next()?;
+        ids.next().is_none().then_some(id)
+    }
+
+    pub(crate) fn registers(&self, processor_type: TypeId) -> bool {
+        self.entries.values().any(|entry| entry.processor_type == processor_type)
+    }
+
+    // Adds an instance of the processor registered as id, or None if there's
+    // no such id. The instance remembers the id, which is what
+    // Patch::from_router saves for it
+    pub fn add_to(&self, id: &str, builder: Builder<E>, instance_name: &'static str) -> Option<Builder<E>> {
+        let entry = self.entries.get(id)?;
+        Some((entry.add)(builder, instance_name).registry_id(instance_name, id))
+    }
+}
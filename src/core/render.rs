@@ -0,0 +1,159 @@
+use std::path::Path;
+use super::runtime::Runtime;
+use super::wav::{WavFormat, WavWriter};
+
+// How much to render
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderLength {
+    Seconds(f32),
+    Frames(usize),
+}
+
+// What to render: how long, what to play into the system input, and
+// events to send at given frames
+//
+//     runtime.render("bounce.wav", Render::seconds(4.0)
+//         .input(&recording)
+//         .event(48_000, Event::NoteOn(60))
+//         .format(WavFormat::Int24))?;
+pub struct Render<'a, E> {
+    length: RenderLength,
+    input: Option<&'a [f32]>,
+    events: Vec<(usize, E)>,
+    format: WavFormat,
+}
+
+impl<'a, E: Clone + Copy> Render<'a, E> {
+    pub fn new(length: RenderLength) -> Self {
+        Self {
+            length,
+            input: None,
+            events: Vec::new(),
+            format: WavFormat::Float32,
+        }
+    }
+
+    pub fn seconds(seconds: f32) -> Self {
+        Self::new(RenderLength::Seconds(seconds))
+    }
+
+    pub fn frames(frames: usize) -> Self {
+        Self::new(RenderLength::Frames(frames))
+    }
+
+    // Played into the system input, followed by silence
+    pub fn input(mut self, input: &'a [f32]) -> Self {
+        self.input = Some(input);
+        self
+    }
+
+    // Sends an event at a frame, counted from the start of the render
+    pub fn event(mut self, frame: usize, event: E) -> Self {
+        self.events.push((frame, event));
+        self
+    }
+
+    // 32-bit float unless set
+    pub fn format(mut self, format: WavFormat) -> Self {
+        self.format = format;
+        self
+    }
+}
+
+impl<E: Clone + Copy + 'static> Runtime<E> {
+    // Runs ticks as fast as it can and writes the system output to a WAV
+    // file at the runtime's sample rate. Returns the number of frames written.
+    // Updates and events from the Router still land at the start of ticks
+    pub fn render(&mut self, path: impl AsRef<Path>, render: Render<E>) -> Result<usize, hound::Error> {
+        let frames = match render.length {
+            RenderLength::Seconds(seconds) => (seconds.max(0.0) * self.sample_rate).round() as usize,
+            RenderLength::Frames(frames) => frames,
+        };
+        let mut events = render.events;
+        events.sort_by_key(|(frame, _)| *frame);
+        let mut events = events.into_iter().peekable();
+
+        let mut writer = WavWriter::create(path.as_ref(), 1, self.sample_rate as u32, render.format)?;
+        let mut input = vec![0.0; self.buffer_size];
+
+        let mut start = 0;
+        while start < frames {
+            let end = start + self.buffer_size;
+            input.fill(0.0);
+            if let Some(source) = render.input {
+                let block = source.get(start.min(source.len())..end.min(source.len())).unwrap_or_default();
+                input[..block.len()].copy_from_slice(block);
+            }
+            self.read_from(&input);
+
+            self.current_events.clear();
+            self.event_offsets.clear();
+            while let Some((frame, event)) = events.next_if(|(frame, _)| *frame < end) {
+                self.current_events.push(event);
+                self.event_offsets.push(frame.saturating_sub(start));
+            }
+            self.run_tick();
+
+            // silence while nothing is routed to the output
+            let block = self.output_block();
+            for i in 0..(frames - start).min(self.buffer_size) {
+                writer.write(block.map_or(0.0, |block| block[i]))?;
+            }
+            start = end;
+        }
+
+        writer.finalize()?;
+        Ok(frames)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::processor::*;
+    use crate::core::test_processors::*;
+    use crate::core::wav::read_wav;
+    use crate::Builder;
+
+    #[test]
+    fn test_render_with_events() {
+        let (mut runtime, router) = Builder::<TestEvent>::new()
+            .add(Trigger)
+            .buffer_length(4)
+            .sample_rate(10.0)
+            .build();
+        router.route(port::<Trigger, Output>(0), output()).unwrap();
+
+        // 1 second at 10Hz ends partway through the third block
+        let dir = test_dir("render_events");
+        let path = dir.join("render.wav");
+        let render = Render::seconds(1.0)
+            .event(9, TestEvent)
+            .event(5, TestEvent);
+        assert_eq!(runtime.render(&path, render).unwrap(), 10);
+
+        let (channels, sample_rate) = read_wav(&path).unwrap();
+        assert_eq!(sample_rate, 10);
+        assert_eq!(channels, [[0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_render_input() {
+        let (mut runtime, router) = Builder::<TestEvent>::new()
+            .add(Gain)
+            .buffer_length(4)
+            .build();
+        router.route(input(), gain_in()).unwrap();
+        router.route(gain_out(), output()).unwrap();
+
+        let dir = test_dir("render_input");
+        let path = dir.join("render.wav");
+        runtime.render(&path, Render::frames(6).input(&[0.5, 1.0, -0.75])).unwrap();
+
+        // floats aren't clipped
+        let (channels, _) = read_wav(&path).unwrap();
+        assert_eq!(channels, [[1.0, 2.0, -1.5, 0.0, 0.0, 0.0]]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,7 +1,11 @@
 use std::sync::{Arc, Mutex};
 use std::any::TypeId;
 use super::clerk::Clerk;
-use super::processor::Port;
+use super::processor::{Processor, Port, PortType, Input};
+use super::graph::{ProcessorInfo, PortRef, Connection, GraphDescription};
+use super::preset::{Preset, MorphOptions};
+#[cfg(feature = "serde")]
+use super::snapshot::{PendingSnapshot, StateSnapshot, SnapshotErr};
 use std::marker::PhantomData;
 use std::fmt::{Display, Formatter};
 
@@ -24,9 +28,162 @@ impl<E: Clone + Copy + 'static + std::fmt::Debug> Router<E> {
         clerk.add_route(from, to)
     }
     
+    pub fn unroute<P1: Port + 'static, P2: Port + 'static>(&self, from: PortHandle<P1>, to: PortHandle<P2>) -> Result<(), RoutingErr> {
+        let mut clerk = self.clerk.lock().unwrap();
+        clerk.remove_route(from, to)
+    }
+
+    // Adds a processor to the running graph, unrouted. Its states are made
+    // and prepared here, off the audio thread, at the rate the runtime was
+    // last prepared at. Panics like Builder::add_processor if its ports and
+    // buffers don't agree
+    pub fn add_processor<P: Processor>(&self, processor: P, instance_name: &'static str) -> Result<(), RoutingErr> {
+        self.clerk.lock().unwrap().add_processor(processor, instance_name)
+    }
+
+    // Removes a processor along with its routes and parameters. While a
+    // crossfade runs it plays on, fading out, and it's released once it's
+    // done. Undoing adds it back as it was routed and set, with fresh states
+    pub fn remove_processor(&self, instance_name: &str) -> Result<(), RoutingErr> {
+        self.clerk.lock().unwrap().remove_processor(instance_name)
+    }
+
+    // Routes, unroutes, set_parameter calls, morphs, presets and processor
+    // adds and removes are kept in a bounded history. Undoing reschedules
+    // the graph just like the original edit.
+    // Returns false when there is nothing to undo
+    pub fn undo(&self) -> Result<bool, RoutingErr> {
+        self.clerk.lock().unwrap().undo()
+    }
+
+    pub fn redo(&self) -> Result<bool, RoutingErr> {
+        self.clerk.lock().unwrap().redo()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.clerk.lock().unwrap().can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.clerk.lock().unwrap().can_redo()
+    }
+
+    pub fn clear_history(&self) {
+        self.clerk.lock().unwrap().clear_history()
+    }
+
+    // How many edits undo can go back. Defaults to 128
+    pub fn set_history_limit(&self, limit: usize) {
+        self.clerk.lock().unwrap().set_history_limit(limit)
+    }
+
+    // Sets a constant value for an input port. The processor reads it as a
+    // buffer filled with `value` for as long as the port isn't routed
+    pub fn set_parameter(&self, port: PortHandle<Input<'static>>, value: f32) -> Result<(), RoutingErr> {
+        let mut clerk = self.clerk.lock().unwrap();
+        clerk.set_parameter(port, value)
+    }
+
+    // Moves every parameter found in both presets `t` of the way from `a`
+    // to `b`. The runtime ramps to the new values over one block
+    pub fn morph(&self, a: &Preset, b: &Preset, t: f32) -> Result<(), RoutingErr> {
+        self.morph_with(a, b, t, &MorphOptions::default())
+    }
+
+    pub fn morph_with(&self, a: &Preset, b: &Preset, t: f32, options: &MorphOptions) -> Result<(), RoutingErr> {
+        let parameters = a.morph(b, t, options);
+        self.clerk.lock().unwrap().ramp_parameters(&parameters)
+    }
+
+    // Ramps every parameter in the preset to its saved value
+    pub fn apply_preset(&self, preset: &Preset) -> Result<(), RoutingErr> {
+        self.clerk.lock().unwrap().ramp_parameters(&preset.parameters)
+    }
+
+    // From the next routing change on, crossfades from the old graph to the
+    // new one over this many samples, so live rerouting doesn't click. Every
+    // input whose source changed hears the old source fade out as the new
+    // one fades in, and processors keep running once a tick throughout.
+    // 0 swaps graphs instantly
+    pub fn set_crossfade(&self, samples: usize) -> Result<(), RoutingErr> {
+        self.clerk.lock().unwrap().set_crossfade(samples)
+    }
+
+    // Clears every processor's tails at the start of the next tick
+    pub fn reset(&self) -> Result<(), RoutingErr> {
+        self.clerk.lock().unwrap().reset()
+    }
+
+    // Bypass, mute and solo fade in and out over a few milliseconds,
+    // starting at the next tick. A bypassed processor passes its main
+    // input to its main output, see PortDescriptor::main
+    pub fn set_bypass(&self, instance_name: &str, bypassed: bool) -> Result<(), RoutingErr> {
+        self.clerk.lock().unwrap().set_bypass(instance_name, bypassed)
+    }
+
+    // A muted processor outputs silence
+    pub fn set_mute(&self, instance_name: &str, muted: bool) -> Result<(), RoutingErr> {
+        self.clerk.lock().unwrap().set_mute(instance_name, muted)
+    }
+
+    // While any processor is soloed, only soloed processors and the ones
+    // feeding or fed by them are heard, everything else is muted
+    pub fn solo(&self, instance_name: &str, soloed: bool) -> Result<(), RoutingErr> {
+        self.clerk.lock().unwrap().solo(instance_name, soloed)
+    }
+
     pub fn send_event(&self, event: E) {
         self.clerk.lock().unwrap().send_event(event);
     }
+
+    // Asks the runtime to copy every persistent state at the start of its
+    // next tick. The copies are serialized by whoever takes the snapshot
+    #[cfg(feature = "serde")]
+    pub fn request_snapshot(&self) -> PendingSnapshot {
+        self.clerk.lock().unwrap().request_snapshot()
+    }
+
+    // Decodes the snapshot here, then swaps the states in at the next tick.
+    // Every instance with a saved state is replaced: it's released on the
+    // audio thread, and its new states are prepared here, so the ones it
+    // doesn't save start out fresh
+    #[cfg(feature = "serde")]
+    pub fn restore(&self, snapshot: &StateSnapshot) -> Result<(), SnapshotErr> {
+        self.clerk.lock().unwrap().restore(snapshot)
+    }
+
+    // Every processor instance, including the system input and output,
+    // with its ports and the physical buffer each port maps to
+    pub fn processors(&self) -> Vec<ProcessorInfo> {
+        self.clerk.lock().unwrap().processors()
+    }
+
+    // The routes currently in the graph, in the order they were made
+    pub fn connections(&self) -> Vec<Connection> {
+        self.clerk.lock().unwrap().connections()
+    }
+
+    // Instance names in the order the runtime executes them
+    pub fn execution_order(&self) -> Vec<&'static str> {
+        self.clerk.lock().unwrap().execution_order()
+    }
+
+    // Samples between the system input and output, including the delays
+    // added to line parallel paths up, for hosts to report
+    pub fn latency(&self) -> usize {
+        self.clerk.lock().unwrap().latency()
+    }
+
+    // A consistent snapshot of processors, connections and execution order.
+    // With the `serde` feature it can be serialized, e.g. to JSON
+    pub fn describe(&self) -> GraphDescription {
+        self.clerk.lock().unwrap().describe()
+    }
+
+    // The current graph in Graphviz DOT format
+    pub fn to_dot(&self) -> String {
+        self.describe().to_dot()
+    }
 }
 
 // Buffer handle for type-safe routing
@@ -50,26 +207,508 @@ impl<P: Port> PortHandle<P> {
         }
     }
 }
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum RoutingErr {
-    CycleDetected,
-    ProcessorNotFound,
-    PortNotFound,
-    FromPortIsInput,
-    ToPortIsOutput
-
+    // the instances along the cycle, in routing order, ending where it started
+    CycleDetected { path: Vec<&'static str> },
+    ProcessorNotFound { instance: String },
+    PortNotFound { instance: String, port: String },
+    FromPortIsInput { port: PortRef },
+    ToPortIsOutput { port: PortRef },
+    // a handle or call expected a different kind of port than the processor declares
+    PortTypeMismatch { port: PortRef, expected: PortType, found: PortType },
+    // `to` already reads from `from`; an input can only be routed once
+    AlreadyConnected { from: PortRef, to: PortRef },
+    NotConnected { from: PortRef, to: PortRef },
+    DuplicateInstance { instance: String },
+    RuntimeDisconnected,
 }
 
 impl Display for RoutingErr {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         match self {
-            RoutingErr::CycleDetected => write!(f, "Cycle detected in routing graph"),
-            RoutingErr::ProcessorNotFound => write!(f, "Component not found"),
-            RoutingErr::PortNotFound => write!(f, "Buffer not found"),
-            RoutingErr::FromPortIsInput => write!(f, "\"from\" must be an Output port"),
-            RoutingErr::ToPortIsOutput => write!(f, "\"to\" must be an Input port"),
+            RoutingErr::CycleDetected { path } =>
+                write!(f, "Routing would create a cycle: {}", path.join(" -> ")),
+            RoutingErr::ProcessorNotFound { instance } =>
+                write!(f, "No processor instance is named \"{}\"", instance),
+            RoutingErr::PortNotFound { instance, port } =>
+                write!(f, "\"{}\" has no port named \"{}\"", instance, port),
+            RoutingErr::FromPortIsInput { port } =>
+                write!(f, "Can't route from {}, it is an input", port),
+            RoutingErr::ToPortIsOutput { port } =>
+                write!(f, "Can't route to {}, it is an output", port),
+            RoutingErr::PortTypeMismatch { port, expected, found } =>
+                write!(f, "{} is a {:?} port, expected {:?}", port, found, expected),
+            RoutingErr::AlreadyConnected { from, to } =>
+                write!(f, "{} already reads from {}", to, from),
+            RoutingErr::NotConnected { from, to } =>
+                write!(f, "{} is not routed to {}", from, to),
+            RoutingErr::DuplicateInstance { instance } =>
+                write!(f, "More than one processor is named \"{}\"", instance),
+            RoutingErr::RuntimeDisconnected => write!(f, "The runtime has been dropped"),
         }
     }
 }
 
-impl std::error::Error for RoutingErr {}
\ No newline at end of file
+impl std::error::Error for RoutingErr {}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::processor::*;
+    use crate::core::graph::PortRef;
+    use crate::core::test_processors::*;
+    use crate::{Builder, RoutingErr};
+
+    #[test]
+    fn test_introspection_lists_graph() {
+        let (mut runtime, router) = Builder::<TestEvent>::new()
+            .add(Source)
+            .add(Gain)
+            .buffer_length(4)
+            .build();
+
+        router.route(source_out(), gain_in()).unwrap();
+        router.route(gain_out(), output()).unwrap();
+
+        let source = std::any::type_name::<Source>();
+        let gain = std::any::type_name::<Gain>();
+
+        let processors = router.processors();
+        let names: Vec<_> = processors.iter().map(|p| p.instance_name).collect();
+        assert_eq!(names, vec!["__system_input__", "__system_output__", source, gain]);
+        assert!(processors[0].is_system && !processors[3].is_system);
+
+        let gain_info = &processors[3];
+        assert_eq!(gain_info.ports[0].name, "audio_in");
+        assert!(gain_info.ports[0].port_type.is_input());
+        assert_eq!(gain_info.ports[2].name, "audio_out");
+        assert!(gain_info.ports[2].port_type.is_output());
+
+        // metadata comes from the processor's PortDescriptors
+        assert_eq!(gain_info.ports[0].kind, PortKind::Audio);
+        assert_eq!(gain_info.ports[1].kind, PortKind::Control);
+        assert_eq!(gain_info.ports[1].default, Some(2.0));
+        assert_eq!((gain_info.ports[1].min, gain_info.ports[1].max), (Some(0.0), Some(4.0)));
+        assert_eq!(gain_info.ports[1].description, Some("Multiplies audio_in"));
+        assert_eq!(Gain::ports()[1].clamp(9.0), 4.0);
+        assert_eq!(Gain::ports()[0].clamp(9.0), 9.0);
+
+        // the gain reads the source's buffer, and writes to the output's
+        let source_buf = processors[2].ports[0].physical_buffer;
+        let output_buf = processors[1].ports[0].physical_buffer;
+        assert!(source_buf.is_some());
+        assert_eq!(gain_info.ports[0].physical_buffer, source_buf);
+        assert_eq!(gain_info.ports[2].physical_buffer, output_buf);
+        assert_ne!(source_buf, output_buf);
+        // the system input was never routed
+        assert_eq!(processors[0].ports[0].physical_buffer, None);
+
+        let connections = router.connections();
+        assert_eq!(connections.len(), 2);
+        assert_eq!(connections[0].from, PortRef { instance_name: source, port_name: "audio_out", field_idx: 0 });
+        assert_eq!(connections[0].to, PortRef { instance_name: gain, port_name: "audio_in", field_idx: 0 });
+        assert_eq!(connections[1].to.instance_name, "__system_output__");
+
+        let order = router.execution_order();
+        let position = |name| order.iter().position(|&n| n == name).unwrap();
+        assert!(position(source) < position(gain));
+        assert!(position(gain) < position("__system_output__"));
+
+        let mut out = [0.0; 4];
+        runtime.process(None, &mut out);
+        assert_eq!(out, [2.0; 4]);
+    }
+
+    #[test]
+    fn test_introspection_before_routing() {
+        let (_runtime, router) = Builder::<TestEvent>::new().add(Source).build();
+
+        assert_eq!(router.processors().len(), 3);
+        assert!(router.connections().is_empty());
+        assert!(router.execution_order().is_empty());
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let (_runtime, router) = Builder::<TestEvent>::new()
+            .add(Source)
+            .add(Gain)
+            .build();
+
+        router.route(source_out(), gain_in()).unwrap();
+        router.route(gain_out(), output()).unwrap();
+
+        let dot = router.to_dot();
+        assert!(dot.starts_with("digraph lyris {"));
+        assert!(dot.contains("n3 [label=\"{{<p0> audio_in|<p1> gain}|lyris::core::test_processors::Gain\\nstep "));
+        assert!(dot.contains("n2:p0 -> n3:p0 [label=\"buf "));
+        assert!(dot.contains("n3:p2 -> n1:p0 [label=\"buf "));
+        assert!(dot.contains("style=rounded"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_describe_serializes() {
+        let (_runtime, router) = Builder::<TestEvent>::new().add(Source).add(Gain).build();
+        router.route(source_out(), output()).unwrap();
+
+        let json = serde_json::to_value(router.describe()).unwrap();
+        assert_eq!(json["processors"][3]["ports"][1]["kind"], "Control");
+        assert_eq!(json["processors"][3]["ports"][1]["max"], 4.0);
+        assert!(json["processors"][3]["ports"][1]["unit"].is_null());
+        assert_eq!(json["processors"].as_array().unwrap().len(), 4);
+        assert_eq!(json["connections"][0]["from"]["port_name"], "audio_out");
+        assert_eq!(json["processors"][1]["ports"][0]["port_type"], "SystemOutput");
+        assert!(json["processors"][0].get("processor_type").is_none());
+    }
+
+    #[test]
+    fn test_set_parameter() {
+        let (mut runtime, router) = Builder::<TestEvent>::new()
+            .add(Source)
+            .add(Gain)
+            .buffer_length(4)
+            .build();
+
+        router.route(source_out(), gain_in()).unwrap();
+        router.route(gain_out(), output()).unwrap();
+        router.set_parameter(gain_amount(), 0.5).unwrap();
+
+        let mut out = [0.0; 4];
+        runtime.process(None, &mut out);
+        assert_eq!(out, [0.5; 4]);
+
+        // the parameter already has a buffer, so this only changes its value
+        router.set_parameter(gain_amount(), 3.0).unwrap();
+        runtime.process(None, &mut out);
+        assert_eq!(out, [3.0; 4]);
+
+        let gain_info = &router.processors()[3];
+        assert_eq!(gain_info.ports[1].parameter, Some(3.0));
+        assert!(gain_info.ports[1].physical_buffer.is_some());
+        assert_eq!(gain_info.ports[0].parameter, None);
+    }
+
+    #[test]
+    fn test_bypass_mute_solo() {
+        // at 1kHz controls fade over 5 samples, one block
+        let (mut runtime, router) = Builder::<TestEvent>::new()
+            .add_processor(Source, "a")
+            .add_processor(Gain, "g1")
+            .add_processor(Source, "b")
+            .add_processor(Gain, "g2")
+            .buffer_length(5)
+            .sample_rate(1000.0)
+            .build();
+        let gain = |name| (named_port::<Gain, Input>(name, 0), named_port::<Gain, Output>(name, 2));
+        router.route(named_port::<Source, Output>("a", 0), gain("g1").0).unwrap();
+        router.route(gain("g1").1, output()).unwrap();
+        router.route(named_port::<Source, Output>("b", 0), gain("g2").0).unwrap();
+
+        let mut out = [0.0; 5];
+        let mut play = |runtime: &mut Runtime<TestEvent>| {
+            runtime.process(None, &mut out);
+            out.map(|sample| (sample * 1000.0).round() / 1000.0)
+        };
+        assert_eq!(play(&mut runtime), [2.0; 5]);
+
+        // the dry source fades in as the gain fades out
+        router.set_bypass("g1", true).unwrap();
+        assert_eq!(play(&mut runtime), [1.8, 1.6, 1.4, 1.2, 1.0]);
+        assert_eq!(play(&mut runtime), [1.0; 5]);
+        router.set_bypass("g1", false).unwrap();
+        assert_eq!(play(&mut runtime), [1.2, 1.4, 1.6, 1.8, 2.0]);
+
+        router.set_mute("g1", true).unwrap();
+        assert_eq!(play(&mut runtime), [1.6, 1.2, 0.8, 0.4, 0.0]);
+        assert_eq!(play(&mut runtime), [0.0; 5]);
+        router.set_mute("g1", false).unwrap();
+        play(&mut runtime);
+
+        // g1 isn't on g2's path, so soloing g2 silences it
+        router.solo("g2", true).unwrap();
+        play(&mut runtime);
+        assert_eq!(play(&mut runtime), [0.0; 5]);
+
+        // with g2 on the output, its source is heard too, being upstream of the solo
+        router.unroute(gain("g1").1, output()).unwrap();
+        router.route(gain("g2").1, output()).unwrap();
+        play(&mut runtime);
+        assert_eq!(play(&mut runtime), [2.0; 5]);
+        router.solo("g2", false).unwrap();
+
+        assert_eq!(router.set_bypass("nope", true), Err(RoutingErr::ProcessorNotFound { instance: "nope".into() }));
+        assert!(router.set_mute("__system_output__", true).is_err());
+    }
+
+    #[test]
+    fn test_latency_compensation() {
+        let (mut runtime, router) = Builder::<TestEvent>::new()
+            .add(Source)
+            .add_processor(Lookahead { samples: 3 }, "lookahead")
+            .add_processor(Mix, "mix")
+            .buffer_length(2)
+            .build();
+        let mix = |field_idx| named_port::<Mix, Input>("mix", field_idx);
+
+        router.route(source_out(), named_port::<Lookahead, Input>("lookahead", 0)).unwrap();
+        router.route(named_port::<Lookahead, Output>("lookahead", 1), mix(0)).unwrap();
+        router.route(named_port::<Mix, Output>("mix", 2), output()).unwrap();
+        assert_eq!(router.latency(), 3);
+
+        // the direct path is delayed to meet the lookahead's
+        router.route(source_out(), mix(1)).unwrap();
+        assert_eq!(router.latency(), 3);
+        assert_eq!(router.processors()[3].latency, 3);
+
+        let mut out = [0.0; 2];
+        let mut played = Vec::new();
+        for _ in 0..3 {
+            runtime.process(None, &mut out);
+            played.extend_from_slice(&out);
+        }
+        assert_eq!(played, [0.0, 0.0, 0.0, 2.0, 2.0, 2.0]);
+
+        // reset empties the added delay, so the direct path is silent again
+        runtime.reset();
+        played.clear();
+        for _ in 0..2 {
+            runtime.process(None, &mut out);
+            played.extend_from_slice(&out);
+        }
+        assert_eq!(played, [1.0, 1.0, 1.0, 2.0]);
+
+        router.unroute(named_port::<Lookahead, Output>("lookahead", 1), mix(0)).unwrap();
+        assert_eq!(router.latency(), 0);
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let (mut runtime, router) = Builder::<TestEvent>::new()
+            .add(Source)
+            .add(Gain)
+            .buffer_length(2)
+            .build();
+        assert!(!router.can_undo());
+
+        router.route(source_out(), gain_in()).unwrap();
+        router.route(gain_out(), output()).unwrap();
+        router.set_parameter(gain_amount(), 0.5).unwrap();
+
+        let mut out = [0.0; 2];
+        runtime.process(None, &mut out);
+        assert_eq!(out, [0.5; 2]);
+
+        // back to the default gain
+        assert!(router.undo().unwrap());
+        runtime.process(None, &mut out);
+        assert_eq!(out, [2.0; 2]);
+        assert_eq!(router.processors()[3].ports[1].parameter, None);
+
+        // nothing reaches the output
+        assert!(router.undo().unwrap());
+        runtime.process(None, &mut out);
+        assert_eq!(out, [0.0; 2]);
+        assert_eq!(router.connections().len(), 1);
+
+        assert!(router.redo().unwrap());
+        assert!(router.redo().unwrap());
+        assert!(!router.redo().unwrap());
+        runtime.process(None, &mut out);
+        assert_eq!(out, [0.5; 2]);
+
+        // unrouting is an edit too, and a new edit clears the redo stack
+        router.undo().unwrap();
+        router.unroute(source_out(), gain_in()).unwrap();
+        assert!(!router.can_redo());
+        runtime.process(None, &mut out);
+        assert_eq!(out, [0.0; 2]);
+        assert!(matches!(router.unroute(source_out(), gain_in()), Err(RoutingErr::NotConnected { .. })));
+
+        router.undo().unwrap();
+        runtime.process(None, &mut out);
+        assert_eq!(out, [2.0; 2]);
+
+        router.set_history_limit(1);
+        assert!(router.undo().unwrap());
+        assert!(!router.undo().unwrap());
+    }
+
+    #[test]
+    fn test_add_remove_processors() {
+        let (mut runtime, router) = Builder::<TestEvent>::new()
+            .add(Source)
+            .buffer_length(2)
+            .build();
+        let has_gain = |router: &crate::Router<TestEvent>| router.processors().iter().any(|info| info.instance_name == "g");
+
+        router.add_processor(Gain, "g").unwrap();
+        router.route(source_out(), named_port::<Gain, Input>("g", 0)).unwrap();
+        router.route(named_port::<Gain, Output>("g", 2), output()).unwrap();
+        router.set_parameter(named_port::<Gain, Input>("g", 1), 0.5).unwrap();
+        let mut out = [0.0; 2];
+        runtime.process(None, &mut out);
+        assert_eq!(out, [0.5; 2]);
+
+        assert_eq!(router.add_processor(Gain, "g"), Err(RoutingErr::DuplicateInstance { instance: "g".to_string() }));
+        assert_eq!(router.remove_processor("__system_output__"), Err(RoutingErr::ProcessorNotFound { instance: "__system_output__".to_string() }));
+
+        router.remove_processor("g").unwrap();
+        runtime.process(None, &mut out);
+        assert_eq!(out, [0.0; 2]);
+        assert!(!has_gain(&router));
+        assert!(router.connections().is_empty());
+
+        // undoing puts it back as it was routed and set
+        assert!(router.undo().unwrap());
+        runtime.process(None, &mut out);
+        assert_eq!(out, [0.5; 2]);
+        assert_eq!(router.connections().len(), 2);
+
+        // and the rest of the history goes back to before it was added
+        for _ in 0..4 {
+            assert!(router.undo().unwrap());
+        }
+        assert!(!router.can_undo());
+        assert!(!has_gain(&router));
+
+        while router.redo().unwrap() {}
+        runtime.process(None, &mut out);
+        assert_eq!(out, [0.0; 2]);
+        assert!(!has_gain(&router));
+
+        router.undo().unwrap();
+        runtime.process(None, &mut out);
+        assert_eq!(out, [0.5; 2]);
+    }
+
+    #[test]
+    fn test_routing_errors() {
+        let (runtime, router) = Builder::<TestEvent>::new()
+            .add(Source)
+            .add(Gain)
+            .add_processor(Gain, "a")
+            .build();
+
+        router.route(source_out(), gain_in()).unwrap();
+        let err = router.route(source_out(), gain_in()).unwrap_err();
+        assert!(matches!(err, RoutingErr::AlreadyConnected { .. }));
+        assert!(err.to_string().ends_with("Gain.audio_in already reads from lyris::core::test_processors::Source.audio_out"));
+
+        // a handle that claims field 0 of Gain is an output
+        let err = router.route(port::<Gain, Output>(0), output()).unwrap_err();
+        assert!(matches!(err, RoutingErr::PortTypeMismatch { expected: PortType::Output, found: PortType::Input, .. }));
+
+        let err = router.route(named_port::<Source, Output>("osc", 0), output()).unwrap_err();
+        assert_eq!(err, RoutingErr::ProcessorNotFound { instance: "osc".into() });
+        assert_eq!(err.to_string(), "No processor instance is named \"osc\"");
+
+        let err = router.route(named_port::<Gain, Input>("a", 1), output()).unwrap_err();
+        assert!(matches!(err, RoutingErr::FromPortIsInput { port } if port.port_name == "gain"));
+
+        let err = router.set_parameter(named_port::<Gain, Input>("a", 9), 1.0).unwrap_err();
+        assert_eq!(err, RoutingErr::PortNotFound { instance: "a".into(), port: "#9".into() });
+
+        drop(runtime);
+        let connections = router.connections();
+        assert_eq!(router.route(gain_out(), output()), Err(RoutingErr::RuntimeDisconnected));
+        assert_eq!(router.unroute(source_out(), gain_in()), Err(RoutingErr::RuntimeDisconnected));
+        // the failed edits left the graph and history as they were
+        assert_eq!(router.connections(), connections);
+        router.clear_history();
+        assert_eq!(router.set_parameter(gain_amount(), 0.5), Err(RoutingErr::RuntimeDisconnected));
+        assert!(!router.can_undo());
+        assert_eq!(router.processors()[3].ports[1].parameter, None);
+
+        let err = Builder::<TestEvent>::new()
+            .add_processor(Source, "osc")
+            .add_processor(Gain, "osc")
+            .try_build()
+            .err()
+            .unwrap();
+        assert_eq!(err, RoutingErr::DuplicateInstance { instance: "osc".into() });
+
+        let (_runtime, router) = Builder::<TestEvent>::new()
+            .add_processor(Gain, "a")
+            .add_processor(Gain, "b")
+            .build();
+        router.route(named_port::<Gain, Output>("a", 2), named_port::<Gain, Input>("b", 0)).unwrap();
+        let err = router.route(named_port::<Gain, Output>("b", 2), named_port::<Gain, Input>("a", 0)).unwrap_err();
+        assert_eq!(err, RoutingErr::CycleDetected { path: vec!["a", "b", "a"] });
+        assert_eq!(err.to_string(), "Routing would create a cycle: a -> b -> a");
+    }
+
+    // A tiny xorshift generator, so the random graphs are the same every run
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+    }
+
+    #[test]
+    fn test_failed_edits_leave_graph_unchanged() {
+        const GAINS: [&str; 4] = ["g0", "g1", "g2", "g3"];
+        let outputs = || {
+            let mut outputs = vec![named_port::<Source, Output>("src", 0)];
+            outputs.extend(GAINS.iter().map(|&name| named_port::<Gain, Output>(name, 2)));
+            outputs
+        };
+        let inputs = || {
+            GAINS.iter()
+                .flat_map(|&name| [named_port::<Gain, Input>(name, 0), named_port::<Gain, Input>(name, 1)])
+                .collect::<Vec<_>>()
+        };
+
+        for seed in 1..=16u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let mut builder = Builder::<TestEvent>::new().add_processor(Source, "src").buffer_length(4);
+            for name in GAINS {
+                builder = builder.add_processor(Gain, name);
+            }
+            let (mut runtime, router) = builder.build();
+            let mut out = [0.0; 4];
+
+            for _ in 0..32 {
+                let before = router.describe();
+                let result = match rng.below(6) {
+                    0 => {
+                        let connections = router.connections();
+                        if connections.is_empty() {
+                            continue;
+                        }
+                        let connection = connections[rng.below(connections.len())];
+                        // the system output is never picked as a target above
+                        let from = outputs().into_iter()
+                            .find(|port| port.name == connection.from.instance_name)
+                            .unwrap();
+                        let to = inputs().into_iter()
+                            .find(|port| port.name == connection.to.instance_name && port.field_idx == connection.to.field_idx)
+                            .unwrap();
+                        router.unroute(from, to)
+                    },
+                    // routing from an input always fails
+                    1 => router.route(inputs().swap_remove(rng.below(8)), inputs().swap_remove(rng.below(8))),
+                    _ => router.route(outputs().swap_remove(rng.below(5)), inputs().swap_remove(rng.below(8))),
+                };
+
+                let after = router.describe();
+                match result {
+                    Err(_) => assert_eq!(after, before, "seed {}", seed),
+                    Ok(()) => assert_ne!(after.connections, before.connections, "seed {}", seed),
+                }
+
+                // every connection runs its source before its destination
+                let step = |instance| after.execution_order.iter().position(|&name| name == instance).unwrap();
+                for connection in &after.connections {
+                    assert!(step(connection.from.instance_name) < step(connection.to.instance_name), "seed {}", seed);
+                }
+                runtime.process(None, &mut out);
+            }
+        }
+    }
+}
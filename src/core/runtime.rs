@@ -3,6 +3,59 @@ use std::marker::PhantomData;
 use std::cell::UnsafeCell;
 use super::types::*;
 use std::any::Any;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use super::control::{run_controlled, InstanceControl, CONTROL_FADE_SECONDS};
+use super::latency::Compensation;
+use super::crossfade::Blend;
+
+// A schedule from the Clerk, waiting to be switched to
+pub(crate) struct PendingSchedule<E: Clone + Copy + 'static> {
+    pub(crate) buffer_ids: Vec<Option<PhysicalBuffer>>,
+    pub(crate) buffers: HashMap<PhysicalBuffer, UnsafeCell<Vec<f32>>>,
+    pub(crate) execution_order: Vec<StoredComponent<E>>,
+    pub(crate) system_buffers: SystemBuffers,
+    pub(crate) compensations: Vec<Compensation>,
+    // only blend schedules have any
+    pub(crate) blends: Vec<Blend>,
+}
+
+// A routing change being crossfaded. The runtime plays a blend schedule
+// from the Clerk, which holds the old routes and the new, and feeds every
+// input whose source changed old * (1 - t) + new * t. Once t reaches 1 it
+// switches to the new schedule. Each processor is in the blend schedule
+// once, so oscillators, delay lines and envelopes still run once a tick
+// and carry on where they were
+pub(crate) struct Crossfade<E: Clone + Copy + 'static> {
+    // the schedule to switch to once the fade is done, and its generation
+    target: PendingSchedule<E>,
+    generation: usize,
+    // samples faded so far, out of length
+    done: usize,
+    length: usize,
+}
+
+// What the runtime lets the Clerk know without waiting on an update
+pub(crate) struct Shared {
+    // the generation of the last schedule switched to without a fade still
+    // running, which crossfades start from
+    pub(crate) settled: AtomicUsize,
+    // the rate processors were last prepared at, as f32 bits, which
+    // instances added through the Router are prepared at too
+    pub(crate) sample_rate: AtomicU32,
+}
+
+// An instance the Clerk removed. It's taken out once no graph the runtime
+// plays holds it, which is after any crossfade away from it. Its states
+// are swapped for placeholders and handed back, so they aren't dropped on
+// the audio thread
+pub(crate) struct Removal {
+    pub(crate) component_id: ComponentId,
+    // where its states start in states, and placeholders for them
+    pub(crate) slot_ids_start: usize,
+    pub(crate) states: Vec<BoxedState>,
+    pub(crate) hand_back: Box<dyn FnOnce(Vec<BoxedState>)>,
+}
 
 // Runtime uses UnsafeCell for interior mutability
 pub struct Runtime<E: Clone + Copy + 'static,> {
@@ -11,6 +64,7 @@ pub struct Runtime<E: Clone + Copy + 'static,> {
     pub(crate) buffer_ids: Vec<Option<PhysicalBuffer>>,
     pub(crate) buffers: HashMap<PhysicalBuffer, UnsafeCell<Vec<f32>>>,
     pub(crate) buffer_size: usize,
+    pub(crate) sample_rate: f32,
     
     pub(crate) execution_order: Vec<StoredComponent<E>>,
     _event_type: PhantomData<E>,
@@ -20,10 +74,41 @@ pub struct Runtime<E: Clone + Copy + 'static,> {
 
     // system buffers lookup table
     pub(crate) system_buffers: SystemBuffers,
+    // delays lining up parallel paths, in execution order
+    pub(crate) compensations: Vec<Compensation>,
+    // mixes of old and new sources while crossfading, in execution order
+    blends: Vec<Blend>,
 
     pub(crate) current_events: Vec<E>,
+    // where in the tick each of current_events lands
+    pub(crate) event_offsets: Vec<usize>,
+
+    // the states of removed instances are left as placeholders, so the
+    // others keep their indices
+    pub(crate) states: Vec<BoxedState>,
+    pub(crate) lifecycles: Vec<Lifecycle<E>>,
+    // instances waiting for a crossfade to finish before they're removed
+    removals: Vec<Removal>,
+    // bypass, mute and solo, indexed by ComponentId
+    pub(crate) controls: Vec<InstanceControl>,
 
-    pub(crate) states: Vec<Box<UnsafeCell<dyn Any + Send + 'static>>>,
+    // parameter buffers that ramped last tick, with the value the ramp
+    // started from and the value to hold next
+    pub(crate) settling_parameters: Vec<(PhysicalBuffer, f32, f32)>,
+
+    // samples a graph update crossfades over, 0 swaps graphs instantly
+    pub(crate) crossfade_length: usize,
+    pub(crate) fading: Option<Crossfade<E>>,
+    // the generation of the last schedule switched to without a fade still
+    // running, shared with the Clerk so crossfades start from it
+    settled_generation: usize,
+    pub(crate) shared: Arc<Shared>,
+    // from Builder::seed
+    pub(crate) seed: u64,
+    // the block read_from was last given
+    input_block: Vec<f32>,
+    #[cfg(feature = "serde")]
+    pub(crate) state_slots: Vec<StateSlot>,
 
 }
 
@@ -32,7 +117,10 @@ impl<E: Clone + Copy> Runtime<E> {
         update_rx: lockfree::channel::spsc::Receiver<Update<E>>,
         event_rx: lockfree::channel::spsc::Receiver<E>,
         states: Vec<Box<UnsafeCell<dyn Any + Send + 'static>>>,
+        lifecycles: Vec<Lifecycle<E>>,
+        component_count: usize,
         buffer_size: usize,
+        sample_rate: f32,
     ) -> Self {
 
         let mut buffers = HashMap::new();
@@ -44,13 +132,32 @@ impl<E: Clone + Copy> Runtime<E> {
             buffer_ids: Vec::new(),
             buffers: buffers,
             buffer_size: buffer_size,
+            sample_rate,
             execution_order: Vec::new(),
             _event_type: PhantomData,
             update_rx,
             event_rx,
             system_buffers: SystemBuffers{input: None, output: None},
+            compensations: Vec::new(),
+            blends: Vec::new(),
             current_events: Vec::new(),
+            event_offsets: Vec::new(),
             states: states,
+            lifecycles,
+            removals: Vec::new(),
+            controls: vec![InstanceControl::default(); component_count],
+            settling_parameters: Vec::new(),
+            crossfade_length: 0,
+            fading: None,
+            settled_generation: 0,
+            shared: Arc::new(Shared {
+                settled: AtomicUsize::new(0),
+                sample_rate: AtomicU32::new(sample_rate.to_bits()),
+            }),
+            seed: 0,
+            input_block: vec![0.0; buffer_size],
+            #[cfg(feature = "serde")]
+            state_slots: Vec::new(),
         }
     }
     
@@ -62,7 +169,70 @@ impl<E: Clone + Copy> Runtime<E> {
         }
     }
     
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    // A seed for one instance's random numbers, from the graph's seed and
+    // its instance name. It doesn't depend on what else is in the graph, so
+    // renders and tests come out the same on every run
+    pub fn instance_seed(&self, handle: ContextHandle) -> u64 {
+        let name = self.lifecycles.iter()
+            .find(|lifecycle| lifecycle.context_handle.component_id == handle.component_id)
+            .map_or("", |lifecycle| lifecycle.instance_name);
+        // FNV-1a of the name, mixed with the seed by a SplitMix64 step
+        let hash = name.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3));
+        let mut z = (hash ^ self.seed).wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Prepares every processor for a new sample rate. Processors may
+    // allocate here, so call it off the audio thread, e.g. while the stream
+    // is stopped. The builder has already prepared them at its own rate
+    pub fn prepare(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.shared.sample_rate.store(sample_rate.to_bits(), Ordering::Release);
+        let config = AudioConfig { sample_rate, max_block: self.buffer_size };
+        for lifecycle in &self.lifecycles {
+            (lifecycle.prepare)(self, lifecycle.context_handle, config);
+        }
+    }
+
+    // Clears every processor's tails and the delays lining parallel paths up,
+    // for transport stops and panic buttons.
+    // Router::reset does the same from the control thread
+    pub fn reset(&mut self) {
+        for lifecycle in &self.lifecycles {
+            (lifecycle.reset)(self, lifecycle.context_handle);
+        }
+        for compensation in &mut self.compensations {
+            compensation.clear();
+        }
+    }
+
     pub fn tick(&mut self) {
+        self.current_events.clear();
+        self.event_offsets.clear();
+        self.run_tick();
+    }
+
+    // A tick on top of whatever events are already queued for it
+    pub(crate) fn run_tick(&mut self) {
+        // Parameters that ramped last tick hold their final value from now on
+        for (physical_buf, _, value) in self.settling_parameters.drain(..) {
+            if let Some(buffer_cell) = self.buffers.get_mut(&physical_buf) {
+                buffer_cell.get_mut().fill(value);
+            }
+        }
+
+        // a crossfade that finished last tick hands over to the new graph
+        if let Some(fading) = self.fading.take_if(|fading| fading.done >= fading.length) {
+            self.switch_to(fading.target);
+            self.settle(fading.generation);
+        }
+
         // Check for updates at the start of each tick
         while let Ok(update) = self.update_rx.recv() {
             (update.0)(self);
@@ -70,14 +240,48 @@ impl<E: Clone + Copy> Runtime<E> {
         
         // Process events
         while let Ok((event)) = self.event_rx.recv() {
-            self.current_events.push(event)
+            self.current_events.push(event);
+            self.event_offsets.push(0);
         }
         
+        self.load_input();
+
         // Execute components
+        let fade_step = self.fade_step();
+        self.run_graph(fade_step);
+        if let Some(fading) = &mut self.fading {
+            fading.done += self.buffer_size;
+            // edits from here on crossfade from the new graph, which the
+            // next tick switches to before taking them
+            if fading.done >= fading.length {
+                self.shared.settled.store(fading.generation, Ordering::Release);
+            }
+        }
+
+        for control in &mut self.controls {
+            control.advance(self.buffer_size, fade_step);
+        }
+    }
+
+    fn run_graph(&self, fade_step: f32) {
+        let mut compensations = self.compensations.iter().peekable();
+        let mut blends = self.blends.iter().peekable();
         for component in self.execution_order.iter(){
+            // delay the inputs that would arrive early
+            let component_id = component.component_id();
+            while let Some(compensation) = compensations.next_if(|c| c.component_id == component_id) {
+                compensation.run(&self.buffers);
+            }
+            // and mix the old and new sources of the ones being crossfaded
+            while let Some(blend) = blends.next_if(|b| b.component_id == component_id) {
+                if let Some(fading) = &self.fading {
+                    blend.run(&self.buffers, fading.done, fading.length);
+                }
+            }
             match component {
-                StoredComponent::User(UserComponent{component, context_handle, field_count, instance_name, processor_type}) => {
-                    component(&self, *context_handle)
+                StoredComponent::User(user_comp) => {
+                    let control = &self.controls[user_comp.context_handle.component_id.0];
+                    run_controlled(self, user_comp, control, fade_step)
                 },
                 StoredComponent::System(SystemComponent{component_id, instance_name, buffer_idx}) => {
                     // system components need to tell write_from and read_to what buffer ids they were assigned
@@ -86,59 +290,204 @@ impl<E: Clone + Copy> Runtime<E> {
             }
         }
     }
-    pub fn read_from(&mut self, input: &[f32]) {
 
-        let input_buf = if let Some(input_comp) = self.system_buffers.input {
-            self.buffer_ids[input_comp.buffer_idx.0].expect("fatal error: system buffer not allocated")
+    // Takes a new schedule from the Clerk, crossfading to it when it comes
+    // with a blend schedule. A blend has to start from the graph the runtime
+    // settled on. One the Clerk made just before the last crossfade
+    // finished starts from an older graph, so it's skipped and the schedule
+    // switched to straight away. A blend that comes in during a crossfade
+    // takes it over where it is
+    pub(crate) fn install_schedule(
+        &mut self,
+        schedule: PendingSchedule<E>,
+        generation: usize,
+        blend: Option<(PendingSchedule<E>, usize)>,
+    ) {
+        match blend {
+            Some((blend, from)) if self.crossfade_length > 0 && from == self.settled_generation => {
+                let done = self.fading.take().map_or(0, |fading| fading.done);
+                self.switch_to(blend);
+                self.fading = Some(Crossfade { target: schedule, generation, done, length: self.crossfade_length });
+            },
+            _ => {
+                self.fading = None;
+                self.switch_to(schedule);
+                self.settle(generation);
+            },
+        }
+    }
+
+    // Nothing plays the old graphs from here on, so the instances removed
+    // from them can go
+    fn settle(&mut self, generation: usize) {
+        self.settled_generation = generation;
+        self.shared.settled.store(generation, Ordering::Release);
+        while let Some(removal) = self.removals.pop() {
+            self.remove_now(removal);
+        }
+    }
+
+    // Takes in an instance the Clerk made and prepared. Its states go after
+    // every existing one, where the Clerk placed them
+    pub(crate) fn add_instance(&mut self, states: Vec<BoxedState>, lifecycle: Lifecycle<E>) {
+        self.states.extend(states);
+        let component_id = lifecycle.context_handle.component_id.0;
+        if self.controls.len() <= component_id {
+            self.controls.resize(component_id + 1, InstanceControl::default());
+        }
+        self.lifecycles.push(lifecycle);
+    }
+
+    // Removes an instance, or waits while a crossfade still plays it
+    pub(crate) fn remove_instance(&mut self, removal: Removal) {
+        if self.fading.is_some() {
+            self.removals.push(removal);
         } else {
-            // fail silently, this means that the output wasn't routed.
-            return
-        };
+            self.remove_now(removal);
+        }
+    }
 
-        if let Some(buffer_cell) = self.buffers.get(&input_buf) {
-            unsafe {
-                let buffer = &mut *buffer_cell.get();
-                let copy_len = input.len().min(buffer.len());
-                buffer[..copy_len].copy_from_slice(&input[..copy_len]);
-                
-                // Fill remaining with zeros if input is shorter
-                if copy_len < buffer.len() {
-                    buffer[copy_len..].fill(0.0);
-                }
+    fn remove_now(&mut self, mut removal: Removal) {
+        let lifecycle = self.lifecycles.iter()
+            .position(|lifecycle| lifecycle.context_handle.component_id == removal.component_id)
+            .map(|idx| self.lifecycles.remove(idx));
+        if let Some(lifecycle) = lifecycle {
+            (lifecycle.release)(self, lifecycle.context_handle);
+        }
+
+        let slots = removal.slot_ids_start..removal.slot_ids_start + removal.states.len();
+        for (state, placeholder) in self.states[slots.clone()].iter_mut().zip(&mut removal.states) {
+            std::mem::swap(state, placeholder);
+        }
+        // nothing left to snapshot
+        #[cfg(feature = "serde")]
+        for slot in &mut self.state_slots[slots] {
+            slot.codec = None;
+        }
+        self.controls[removal.component_id.0] = InstanceControl::default();
+        (removal.hand_back)(removal.states);
+    }
+
+    // Prepares a new instance's states the way prepare would, in a runtime
+    // of their own, so it runs on the caller's thread before the instance
+    // joins the graph
+    pub(crate) fn prepare_instance(lifecycle: Lifecycle<E>, states: Vec<BoxedState>, config: AudioConfig, seed: u64) -> Vec<BoxedState> {
+        let (_, update_rx) = lockfree::channel::spsc::create();
+        let (_, event_rx) = lockfree::channel::spsc::create();
+        // the states start at 0 here
+        let context_handle = ContextHandle { slot_ids_start: 0, ..lifecycle.context_handle };
+        let lifecycles = vec![Lifecycle { context_handle, ..lifecycle }];
+        let mut scratch = Runtime::new(update_rx, event_rx, states, lifecycles, 0, config.max_block, config.sample_rate);
+        scratch.seed = seed;
+        scratch.prepare(config.sample_rate);
+        // the instance isn't going away, so it isn't released
+        scratch.lifecycles.clear();
+        std::mem::take(&mut scratch.states)
+    }
+
+    // The new buffers join the old ones, and delays lining up the same
+    // inputs keep what they hold
+    fn switch_to(&mut self, mut schedule: PendingSchedule<E>) {
+        // the new buffers already hold every parameter's final value
+        self.settling_parameters.clear();
+
+        for compensation in &mut schedule.compensations {
+            for previous in &mut self.compensations {
+                compensation.take_line(previous);
             }
         }
+        self.execution_order = schedule.execution_order;
+        self.buffer_ids = schedule.buffer_ids;
+        self.system_buffers = schedule.system_buffers;
+        self.compensations = schedule.compensations;
+        self.blends = schedule.blends;
+        self.buffers.extend(schedule.buffers);
     }
 
-    pub fn write_to(&self, output: &mut [f32]) {
-        
-        let output_buf = if let Some(output_comp) = self.system_buffers.output {
-            self.buffer_ids[output_comp.buffer_idx.0].expect("fatal error: system buffer not allocated")
-        } else {
-            panic!("output buffer allocated incorrectly"); // debugging panic
-            // fail silently, this means that the output wasn't routed.
-            return
-        };
+    // The buffers the Clerk's latest schedule uses, which are still waiting
+    // while a crossfade runs
+    fn latest_buffers(&mut self) -> &mut HashMap<PhysicalBuffer, UnsafeCell<Vec<f32>>> {
+        match &mut self.fading {
+            Some(fading) => &mut fading.target.buffers,
+            None => &mut self.buffers,
+        }
+    }
 
-        if let Some(buffer_cell) = self.buffers.get(&output_buf) {
-            unsafe {
-                let buffer = &*buffer_cell.get();
-                let copy_len = output.len().min(buffer.len());
-                output[..copy_len].copy_from_slice(&buffer[..copy_len]);
-                
-                // Debug: Check if buffer has non-zero values
-                let has_signal = buffer.iter().any(|&x| x != 0.0);
-                if !has_signal {
-                    println!("Warning: System output buffer is silent!");
-                }
-                
-                // Fill remaining with zeros if output is longer
-                if copy_len < output.len() {
-                    output[copy_len..].fill(0.0);
-                }
+    // Sets a parameter buffer of the latest schedule to value, cutting
+    // short any ramp it was on
+    pub(crate) fn fill_parameter(&mut self, physical_buf: PhysicalBuffer, value: f32) {
+        self.settling_parameters.retain(|(settling, ..)| *settling != physical_buf);
+        if let Some(buffer_cell) = self.latest_buffers().get_mut(&physical_buf) {
+            buffer_cell.get_mut().fill(value);
+        }
+    }
+
+    // how far a control fade moves each sample
+    fn fade_step(&self) -> f32 {
+        1.0 / (self.sample_rate * CONTROL_FADE_SECONDS).max(1.0)
+    }
+    // Fills each parameter buffer with a ramp from the value it last played
+    // to the target, which tick() replaces with the target itself a block
+    // later. A schedule still waiting on a crossfade just takes the targets
+    pub(crate) fn ramp_parameters(&mut self, targets: Vec<(PhysicalBuffer, f32)>) {
+        if self.fading.is_some() {
+            for (physical_buf, target) in targets {
+                self.fill_parameter(physical_buf, target);
             }
-        } else {
-            println!("Warning: System output buffer not found!");
+            return;
         }
+        for (physical_buf, target) in targets {
+            let Some(buffer_cell) = self.buffers.get_mut(&physical_buf) else {
+                continue
+            };
+            let buffer = buffer_cell.get_mut();
+            // settling ramps were drained before this tick's updates, so one
+            // still here came earlier this tick and hasn't played yet. The
+            // new ramp starts where it would have
+            let settling = self.settling_parameters.iter().position(|(settling, ..)| *settling == physical_buf);
+            let start = match settling {
+                Some(idx) => self.settling_parameters.swap_remove(idx).1,
+                None => buffer.last().copied().unwrap_or(target),
+            };
+            let len = buffer.len() as f32;
+            for (i, sample) in buffer.iter_mut().enumerate() {
+                *sample = start + (target - start) * (i + 1) as f32 / len;
+            }
+            self.settling_parameters.push((physical_buf, start, target));
+        }
+    }
+
+    // Holds the input for the next tick, which copies it into the graph
+    // once any routing updates are in, so a new graph hears it too
+    pub fn read_from(&mut self, input: &[f32]) {
+        let copy_len = input.len().min(self.input_block.len());
+        self.input_block[..copy_len].copy_from_slice(&input[..copy_len]);
+
+        // Fill remaining with zeros if input is shorter
+        self.input_block[copy_len..].fill(0.0);
+    }
+
+    fn load_input(&mut self) {
+        let input = self.system_buffers.input
+            .and_then(|input_comp| self.buffer_ids[input_comp.buffer_idx.0])
+            .and_then(|physical_buf| self.buffers.get_mut(&physical_buf));
+        // nothing reads the input if it isn't routed
+        if let Some(buffer_cell) = input {
+            buffer_cell.get_mut().copy_from_slice(&self.input_block);
+        }
+    }
+
+    pub fn write_to(&self, output: &mut [f32]) {
+        // nothing is routed to the output, which plays silence
+        let block = self.output_block().unwrap_or_default();
+        let copy_len = output.len().min(block.len());
+        output[..copy_len].copy_from_slice(&block[..copy_len]);
+        output[copy_len..].fill(0.0);
+    }
+
+    // What the last tick played, None if nothing is routed to the output
+    pub(crate) fn output_block(&self) -> Option<&[f32]> {
+        system_output(&self.buffers, &self.buffer_ids, &self.system_buffers)
     }
 
     pub fn process(&mut self, input: Option<&[f32]>, output: &mut[f32]) {
@@ -153,6 +502,26 @@ impl<E: Clone + Copy> Runtime<E> {
 
 unsafe impl<E: Clone + Copy + 'static> Send for Runtime<E> {}
 
+// The graph's output for this tick, if anything is routed to it
+fn system_output<'a>(
+    buffers: &'a HashMap<PhysicalBuffer, UnsafeCell<Vec<f32>>>,
+    buffer_ids: &[Option<PhysicalBuffer>],
+    system_buffers: &SystemBuffers,
+) -> Option<&'a [f32]> {
+    let physical_buf = system_buffers.output.and_then(|output| buffer_ids[output.buffer_idx.0])?;
+    let buffer_cell = buffers.get(&physical_buf)?;
+    Some(unsafe { &*buffer_cell.get() })
+}
+
+// Instances can't outlive the runtime, so dropping it releases them all
+impl<E: Clone + Copy + 'static> Drop for Runtime<E> {
+    fn drop(&mut self) {
+        for lifecycle in &self.lifecycles {
+            (lifecycle.release)(self, lifecycle.context_handle);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -195,4 +564,262 @@ mod tests {
         test_send::<Runtime<TestEvent>>();
         println!("Runtime Send test passed!");
     }
+
+    #[test]
+    fn test_lifecycle_hooks() {
+        use crate::core::processor::{get_state, output};
+        use crate::core::test_processors::{self as tp, Tracker, TrackerState};
+        use std::sync::atomic::Ordering;
+
+        let tracker = Tracker::default();
+        let released = Arc::clone(&tracker.released);
+        let (mut runtime, router) = crate::Builder::<tp::TestEvent>::new()
+            .add(tracker)
+            .buffer_length(4)
+            .sample_rate(44_100.0)
+            .build();
+        router.route(tp::tracker_out(), output()).unwrap();
+
+        // prepared while building, before anything was routed
+        let config = |runtime: &Runtime<tp::TestEvent>| get_state::<TrackerState, _>(runtime, 0).config;
+        assert_eq!(config(&runtime), Some(AudioConfig { sample_rate: 44_100.0, max_block: 4 }));
+
+        runtime.prepare(96_000.0);
+        assert_eq!(runtime.sample_rate(), 96_000.0);
+        assert_eq!(config(&runtime), Some(AudioConfig { sample_rate: 96_000.0, max_block: 4 }));
+
+        let mut out = [0.0; 4];
+        runtime.reset();
+        runtime.process(None, &mut out);
+        assert_eq!(out, [1.0; 4]);
+
+        // the router's reset lands at the start of the next tick
+        router.reset().unwrap();
+        runtime.process(None, &mut out);
+        assert_eq!(out, [2.0; 4]);
+
+        assert_eq!(released.load(Ordering::SeqCst), 0);
+        drop(runtime);
+        assert_eq!(released.load(Ordering::SeqCst), 1);
+        assert_eq!(router.reset(), Err(crate::RoutingErr::RuntimeDisconnected));
+    }
+
+    #[test]
+    fn test_removal_releases() {
+        use crate::core::processor::{output, Output};
+        use crate::core::test_processors::{self as tp, Tracker};
+        use std::sync::atomic::Ordering;
+
+        let tracker = Tracker::default();
+        let released = Arc::clone(&tracker.released);
+        let (mut runtime, router) = crate::Builder::<tp::TestEvent>::new()
+            .add_processor(tracker, "t")
+            .buffer_length(2)
+            .build();
+        router.route(tp::named_port::<Tracker, Output>("t", 0), output()).unwrap();
+
+        let mut out = [0.0; 2];
+        router.remove_processor("t").unwrap();
+        assert_eq!(released.load(Ordering::SeqCst), 0);
+        runtime.process(None, &mut out);
+        assert_eq!(released.load(Ordering::SeqCst), 1);
+
+        // added back with states of its own, released once more on drop
+        router.undo().unwrap();
+        runtime.process(None, &mut out);
+        drop(runtime);
+        assert_eq!(released.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_crossfaded_reroute() {
+        use crate::core::processor::{input, output};
+        use crate::core::test_processors::{self as tp, Gain};
+
+        let (mut runtime, router) = crate::Builder::<tp::TestEvent>::new()
+            .add(Gain)
+            .buffer_length(2)
+            .crossfade(4)
+            .build();
+        router.route(input(), tp::gain_in()).unwrap();
+        router.route(tp::gain_out(), output()).unwrap();
+
+        let mut out = [0.0; 2];
+        let mut play = |runtime: &mut Runtime<tp::TestEvent>| {
+            runtime.process(Some(&[1.0, 1.0]), &mut out);
+            out
+        };
+        // both routes fade in from silence, the gain's input and the output
+        // each going t of the way, so the output is 2 * t * t
+        assert_eq!(play(&mut runtime), [0.125, 0.5]);
+        assert_eq!(play(&mut runtime), [1.125, 2.0]);
+        assert_eq!(play(&mut runtime), [2.0, 2.0]);
+
+        // two edits land in the same tick and fade as one, from the gain's
+        // 2.0 to the input's 1.0
+        router.unroute(tp::gain_out(), output()).unwrap();
+        router.route(input(), output()).unwrap();
+        assert_eq!(play(&mut runtime), [1.75, 1.5]);
+        assert_eq!(play(&mut runtime), [1.25, 1.0]);
+        assert_eq!(play(&mut runtime), [1.0, 1.0]);
+
+        // back to instant swaps
+        router.set_crossfade(0).unwrap();
+        router.unroute(input(), output()).unwrap();
+        assert_eq!(play(&mut runtime), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_crossfade_mixes_graphs() {
+        use crate::core::processor::output;
+        use crate::core::test_processors::{self as tp, Gain};
+        use crate::processors::osc::{Oscillator, SineOsc};
+
+        // two sines, with the one named playing
+        let build = |playing: &'static str| {
+            let (runtime, router) = crate::Builder::<tp::TestEvent>::new()
+                .add_processor(SineOsc::new(), "a")
+                .add_processor(SineOsc::new(), "b")
+                .add(Gain)
+                .buffer_length(4)
+                .crossfade(8)
+                .build();
+            router.set_parameter(SineOsc::frequency_in("b"), 660.0).unwrap();
+            router.route(SineOsc::audio_out(playing), output()).unwrap();
+            (runtime, router)
+        };
+        let (mut faded, router) = build("a");
+        let (mut a, _a_router) = build("a");
+        let (mut b, _b_router) = build("b");
+
+        let play = |runtime: &mut Runtime<tp::TestEvent>| {
+            let mut out = [0.0; 4];
+            runtime.process(None, &mut out);
+            out
+        };
+        let play_all = |faded: &mut Runtime<tp::TestEvent>, a: &mut Runtime<tp::TestEvent>, b: &mut Runtime<tp::TestEvent>| {
+            (0..3).map(|_| (play(faded), play(a), play(b)))
+                .flat_map(|(faded, a, b)| (0..4).map(move |i| (faded[i], a[i], b[i])))
+                .collect::<Vec<_>>()
+        };
+        play_all(&mut faded, &mut a, &mut b);
+
+        // a route the output doesn't hear changes nothing it plays
+        router.route(SineOsc::audio_out("a"), tp::gain_in()).unwrap();
+        for (faded, a, _) in play_all(&mut faded, &mut a, &mut b) {
+            assert_eq!(faded, a);
+        }
+
+        // both sines run through the fade, once a tick each, and the output
+        // goes from one to the other
+        router.unroute(SineOsc::audio_out("a"), output()).unwrap();
+        router.route(SineOsc::audio_out("b"), output()).unwrap();
+        for (i, (faded, a, b)) in play_all(&mut faded, &mut a, &mut b).into_iter().enumerate() {
+            let t = ((i + 1) as f32 / 8.0).min(1.0);
+            let expected = a * (1.0 - t) + b * t;
+            assert!((faded - expected).abs() < 1e-6, "{}: {} != {}", i, faded, expected);
+        }
+    }
+
+    #[test]
+    fn test_crossfade_reversed_routes() {
+        use crate::core::processor::{input, output, Input, Output};
+        use crate::core::test_processors::{self as tp, Gain};
+
+        let (mut runtime, router) = crate::Builder::<tp::TestEvent>::new()
+            .add_processor(Gain, "x")
+            .add_processor(Gain, "y")
+            .buffer_length(2)
+            .crossfade(4)
+            .build();
+        let gain_in = |name| tp::named_port::<Gain, Input>(name, 0);
+        let gain_out = |name| tp::named_port::<Gain, Output>(name, 2);
+        router.route(input(), gain_in("x")).unwrap();
+        router.route(gain_out("x"), gain_in("y")).unwrap();
+        router.route(gain_out("y"), output()).unwrap();
+
+        let mut out = [0.0; 2];
+        for _ in 0..3 {
+            runtime.process(Some(&[1.0, 1.0]), &mut out);
+        }
+        assert_eq!(out, [4.0, 4.0]);
+
+        // x into y becomes y into x. Both at once would loop, so the old
+        // route is left out of the fade and y's input fades in from silence
+        router.unroute(input(), gain_in("x")).unwrap();
+        router.unroute(gain_out("x"), gain_in("y")).unwrap();
+        router.unroute(gain_out("y"), output()).unwrap();
+        router.route(input(), gain_in("y")).unwrap();
+        router.route(gain_out("y"), gain_in("x")).unwrap();
+        router.route(gain_out("x"), output()).unwrap();
+        for _ in 0..3 {
+            runtime.process(Some(&[1.0, 1.0]), &mut out);
+        }
+        assert_eq!(out, [4.0, 4.0]);
+    }
+
+    #[test]
+    fn test_crossfaded_removal() {
+        use crate::core::processor::{Input, Output, output};
+        use crate::core::test_processors::{self as tp, named_port, Delay, Source};
+
+        let (mut runtime, router) = crate::Builder::<tp::TestEvent>::new()
+            .add(Source)
+            .add_processor(Delay::new(2), "d")
+            .buffer_length(2)
+            .crossfade(4)
+            .build();
+        router.route(tp::source_out(), named_port::<Delay, Input>("d", 0)).unwrap();
+        router.route(named_port::<Delay, Output>("d", 1), output()).unwrap();
+
+        let mut out = [0.0; 2];
+        let mut play = |runtime: &mut Runtime<tp::TestEvent>| {
+            runtime.process(None, &mut out);
+            out
+        };
+        for _ in 0..3 {
+            play(&mut runtime);
+        }
+        assert_eq!(play(&mut runtime), [1.0, 1.0]);
+
+        // the delay plays on while it fades out, and only then goes. Its
+        // input fades out too, which it plays two samples late
+        router.remove_processor("d").unwrap();
+        assert_eq!(play(&mut runtime), [0.75, 0.5]);
+        assert_eq!(play(&mut runtime), [0.75 * 0.25, 0.0]);
+        assert_eq!(play(&mut runtime), [0.0, 0.0]);
+        assert_eq!(runtime.lifecycles.len(), 1);
+
+        // undoing adds it back with an empty line, and it fades in along
+        // with its input
+        router.undo().unwrap();
+        assert_eq!(play(&mut runtime), [0.0, 0.0]);
+        assert_eq!(play(&mut runtime), [0.25 * 0.75, 0.5]);
+        assert_eq!(play(&mut runtime), [0.75, 1.0]);
+        assert_eq!(play(&mut runtime), [1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_reset_clears_delay_line() {
+        use crate::core::processor::{output, Input, Output};
+        use crate::core::test_processors::{self as tp, Delay};
+
+        let (mut runtime, router) = crate::Builder::<tp::TestEvent>::new()
+            .add(tp::Source)
+            .add(Delay::new(2))
+            .buffer_length(2)
+            .build();
+        router.route(tp::source_out(), tp::port::<Delay, Input>(0)).unwrap();
+        router.route(tp::port::<Delay, Output>(1), output()).unwrap();
+
+        let mut out = [0.0; 2];
+        runtime.process(None, &mut out);
+        runtime.process(None, &mut out);
+        assert_eq!(out, [1.0, 1.0]);
+
+        // the line is silent again, so the source takes 2 samples to come through
+        runtime.reset();
+        runtime.process(None, &mut out);
+        assert_eq!(out, [0.0, 0.0]);
+    }
 }
\ No newline at end of file
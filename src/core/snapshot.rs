@@ -0,0 +1,408 @@
+// Snapshots of processor state, for presets, session recall and
+// freezing long-running patches
+
+use std::any::Any;
+use std::cell::UnsafeCell;
+use std::fmt::{self, Display, Formatter};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use super::runtime::Runtime;
+use super::types::{StateSlot, BoxedState, AudioConfig, Lifecycle};
+
+// Opt-in marker for state types that can be saved in a snapshot.
+// Router::request_snapshot copies states on the audio thread with
+// clone_from, into ones made with Default off it, so clone_from shouldn't
+// need to allocate past what Default gives
+pub trait PersistentState: Serialize + DeserializeOwned + Clone + Default + Send + 'static {}
+
+// Type-erased serde for one state slot. Processors hand these out
+// from Processor::state_codecs()
+#[derive(Clone, Copy)]
+pub struct StateCodec {
+    save: fn(&(dyn Any + Send)) -> Result<Value, serde_json::Error>,
+    load: fn(Value) -> Result<BoxedState, serde_json::Error>,
+    create: fn() -> BoxedState,
+    copy: fn(&(dyn Any + Send), &mut (dyn Any + Send)),
+}
+
+impl StateCodec {
+    pub fn of<T: PersistentState>() -> Self {
+        Self {
+            save: |state| {
+                let state = state.downcast_ref::<T>().expect("State type mismatch");
+                serde_json::to_value(state)
+            },
+            load: |value| {
+                let state: T = serde_json::from_value(value)?;
+                Ok(Box::new(UnsafeCell::new(state)))
+            },
+            create: || Box::new(UnsafeCell::new(T::default())),
+            copy: |from, to| {
+                let from = from.downcast_ref::<T>().expect("State type mismatch");
+                to.downcast_mut::<T>().expect("State type mismatch").clone_from(from);
+            },
+        }
+    }
+}
+
+// A persistent state outside of Runtime::states: a copy on its way to be
+// encoded, a decoded one on its way in, or the one it replaced on its way
+// out to be dropped
+pub(crate) struct StateCopy {
+    // its index in Runtime::states
+    idx: usize,
+    slot: StateSlot,
+    state: BoxedState,
+}
+
+// The persistent state of every processor, keyed by instance name and the
+// index of the state within that instance
+#[derive(Clone, Debug, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+pub struct StateSnapshot {
+    pub states: Vec<SavedState>,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SavedState {
+    pub instance: String,
+    pub slot: usize,
+    pub value: Value,
+}
+
+impl<E: Clone + Copy + 'static> Runtime<E> {
+    pub(crate) fn with_state_slots(mut self, state_slots: Vec<StateSlot>) -> Self {
+        self.state_slots = state_slots;
+        self
+    }
+
+    // Saves every persistent state, encoding them here. Call it between
+    // ticks. Router::request_snapshot leaves the audio thread only copying
+    pub fn snapshot(&self) -> Result<StateSnapshot, SnapshotErr> {
+        let states = self.states.iter()
+            .zip(&self.state_slots)
+            // Safety: no processor is running outside of tick()
+            .map(|(state_cell, slot)| (slot, unsafe { &*state_cell.get() }))
+            .filter_map(|(slot, state)| Some(encode_state(slot, slot.codec?, state)))
+            .collect::<Result<_, _>>()?;
+        Ok(StateSnapshot { states })
+    }
+
+    // Restores a snapshot. Nothing changes unless every state decodes.
+    // Each instance with a saved state is released, then prepared again
+    // around the restored states
+    pub fn restore(&mut self, snapshot: &StateSnapshot) -> Result<(), SnapshotErr> {
+        let mut decoded = decode_states(&self.state_slots, snapshot)?;
+        let restored = self.replaced_by(&decoded);
+        self.swap_states(&mut decoded);
+        // the instances keep their other states, which prepare sets up again
+        let config = AudioConfig { sample_rate: self.sample_rate, max_block: self.buffer_size };
+        for lifecycle in restored {
+            (lifecycle.prepare)(self, lifecycle.context_handle, config);
+        }
+        Ok(())
+    }
+
+    // Copies the states into copies made by copy_targets, without allocating
+    pub(crate) fn copy_states(&self, copies: &mut [StateCopy]) {
+        for copy in copies {
+            let codec = copy.slot.codec.expect("only persistent states are copied");
+            // Safety: no processor is running outside of tick()
+            let state = unsafe { &*self.states[copy.idx].get() };
+            (codec.copy)(state, copy.state.get_mut());
+        }
+    }
+
+    // Swaps the states in, leaving the ones they replace in their place.
+    // The instances they belong to are released first, as the states they
+    // had are going away
+    pub(crate) fn swap_states(&mut self, states: &mut [StateCopy]) {
+        for lifecycle in self.replaced_by(states) {
+            (lifecycle.release)(self, lifecycle.context_handle);
+        }
+        for state in states {
+            std::mem::swap(&mut self.states[state.idx], &mut state.state);
+        }
+    }
+
+    // the instances that own any of the states
+    fn replaced_by(&self, states: &[StateCopy]) -> Vec<Lifecycle<E>> {
+        self.lifecycles.iter()
+            .filter(|lifecycle| states.iter().any(|state| state.slot.instance_name == lifecycle.instance_name))
+            .copied()
+            .collect()
+    }
+}
+
+impl StateCopy {
+    pub(crate) fn new(idx: usize, slot: StateSlot, state: BoxedState) -> Self {
+        Self { idx, slot, state }
+    }
+
+    pub(crate) fn into_parts(self) -> (usize, BoxedState) {
+        (self.idx, self.state)
+    }
+}
+
+fn encode_state(slot: &StateSlot, codec: StateCodec, state: &(dyn Any + Send)) -> Result<SavedState, SnapshotErr> {
+    let value = (codec.save)(state).map_err(|err| SnapshotErr::Encode {
+        instance: slot.instance_name.to_string(),
+        slot: slot.slot,
+        message: err.to_string(),
+    })?;
+    Ok(SavedState {
+        instance: slot.instance_name.to_string(),
+        slot: slot.slot,
+        value,
+    })
+}
+
+// A default state for every persistent slot, for Runtime::copy_states
+// to copy into
+pub(crate) fn copy_targets(state_slots: &[StateSlot]) -> Vec<StateCopy> {
+    state_slots.iter()
+        .enumerate()
+        .filter_map(|(idx, slot)| Some(StateCopy { idx, slot: *slot, state: (slot.codec?.create)() }))
+        .collect()
+}
+
+// Decodes every saved state, along with where it goes in Runtime::states
+pub(crate) fn decode_states(
+    state_slots: &[StateSlot],
+    snapshot: &StateSnapshot,
+) -> Result<Vec<StateCopy>, SnapshotErr> {
+    snapshot.states.iter()
+        .map(|saved| {
+            let (idx, slot, codec) = state_slots.iter()
+                .enumerate()
+                .find(|(_, slot)| slot.instance_name == saved.instance && slot.slot == saved.slot)
+                .and_then(|(idx, slot)| Some((idx, slot, slot.codec?)))
+                .ok_or_else(|| SnapshotErr::UnknownState {
+                    instance: saved.instance.clone(),
+                    slot: saved.slot,
+                })?;
+
+            let state = (codec.load)(saved.value.clone()).map_err(|err| SnapshotErr::Decode {
+                instance: saved.instance.clone(),
+                slot: saved.slot,
+                message: err.to_string(),
+            })?;
+
+            Ok(StateCopy { idx, slot: *slot, state })
+        })
+        .collect()
+}
+
+// A snapshot the runtime will take at the start of its next tick. The
+// runtime only copies the states, they're encoded by whoever takes them
+pub struct PendingSnapshot {
+    copies_rx: Receiver<Vec<StateCopy>>,
+}
+
+impl PendingSnapshot {
+    pub(crate) fn new(copies_rx: Receiver<Vec<StateCopy>>) -> Self {
+        Self { copies_rx }
+    }
+
+    fn encode(copies: Vec<StateCopy>) -> Result<StateSnapshot, SnapshotErr> {
+        let states = copies.iter()
+            .map(|copy| {
+                let codec = copy.slot.codec.expect("only persistent states are copied");
+                // Safety: the copy belongs to this thread alone now
+                encode_state(&copy.slot, codec, unsafe { &*copy.state.get() })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(StateSnapshot { states })
+    }
+
+    // None until the runtime has ticked
+    pub fn try_take(&self) -> Option<Result<StateSnapshot, SnapshotErr>> {
+        match self.copies_rx.try_recv() {
+            Ok(copies) => Some(Self::encode(copies)),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(SnapshotErr::RuntimeDisconnected)),
+        }
+    }
+
+    // Blocks until the runtime ticks
+    pub fn wait(self) -> Result<StateSnapshot, SnapshotErr> {
+        self.copies_rx.recv().map_or(Err(SnapshotErr::RuntimeDisconnected), Self::encode)
+    }
+}
+
+#[derive(Debug)]
+pub enum SnapshotErr {
+    Encode { instance: String, slot: usize, message: String },
+    Decode { instance: String, slot: usize, message: String },
+    UnknownState { instance: String, slot: usize },
+    RuntimeDisconnected,
+}
+
+impl Display for SnapshotErr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SnapshotErr::Encode { instance, slot, message } =>
+                write!(f, "Could not save state {} of \"{}\": {}", slot, instance, message),
+            SnapshotErr::Decode { instance, slot, message } =>
+                write!(f, "Could not restore state {} of \"{}\": {}", slot, instance, message),
+            SnapshotErr::UnknownState { instance, slot } =>
+                write!(f, "\"{}\" has no persistent state {}", instance, slot),
+            SnapshotErr::RuntimeDisconnected => write!(f, "The runtime has been dropped"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotErr {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::processor::output;
+    use crate::core::test_processors::*;
+    use crate::Builder;
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let (mut runtime, router) = Builder::<TestEvent>::new()
+            .add(Counter)
+            .add(Source)
+            .buffer_length(2)
+            .build();
+        router.route(counter_out(), output()).unwrap();
+
+        let mut out = [0.0; 2];
+        for _ in 0..3 {
+            runtime.process(None, &mut out);
+        }
+        assert_eq!(out, [3.0; 2]);
+
+        let snapshot = runtime.snapshot().unwrap();
+        // Source has no state, so only the counter is saved
+        assert_eq!(snapshot.states, vec![SavedState {
+            instance: std::any::type_name::<Counter>().to_string(),
+            slot: 0,
+            value: serde_json::json!({ "ticks": 3 }),
+        }]);
+
+        runtime.process(None, &mut out);
+        runtime.process(None, &mut out);
+        assert_eq!(out, [5.0; 2]);
+
+        runtime.restore(&snapshot).unwrap();
+        runtime.process(None, &mut out);
+        assert_eq!(out, [4.0; 2]);
+    }
+
+    #[test]
+    fn test_snapshot_through_router() {
+        let (mut runtime, router) = Builder::<TestEvent>::new()
+            .add(Counter)
+            .buffer_length(2)
+            .build();
+        router.route(counter_out(), output()).unwrap();
+
+        let mut out = [0.0; 2];
+        runtime.process(None, &mut out);
+
+        let pending = router.request_snapshot();
+        assert!(pending.try_take().is_none());
+        // taken before the counter runs in this tick
+        runtime.process(None, &mut out);
+        let snapshot = pending.try_take().unwrap().unwrap();
+        assert_eq!(snapshot.states[0].value, serde_json::json!({ "ticks": 1 }));
+
+        router.restore(&snapshot).unwrap();
+        runtime.process(None, &mut out);
+        assert_eq!(out, [2.0; 2]);
+
+        drop(runtime);
+        assert!(matches!(router.request_snapshot().wait(), Err(SnapshotErr::RuntimeDisconnected)));
+    }
+
+    #[test]
+    fn test_states_handed_back() {
+        let (mut runtime, router) = Builder::<TestEvent>::new()
+            .add(Counter)
+            .buffer_length(2)
+            .build();
+        router.route(counter_out(), output()).unwrap();
+        let mut out = [0.0; 2];
+        let snapshot = runtime.snapshot().unwrap();
+
+        // the state a restore replaces comes back to the control thread
+        router.restore(&snapshot).unwrap();
+        runtime.process(None, &mut out);
+        {
+            let clerk = router.clerk.lock().unwrap();
+            let retired = clerk.retired[0].try_recv().unwrap();
+            assert_eq!(retired.len(), 1);
+        }
+
+        // and so do the copies of a snapshot nobody waited for
+        drop(router.request_snapshot());
+        runtime.process(None, &mut out);
+        let clerk = router.clerk.lock().unwrap();
+        assert_eq!(clerk.retired.len(), 1);
+        assert_eq!(clerk.retired[0].try_recv().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_restore_releases() {
+        use crate::core::processor::get_state;
+        use crate::core::types::AudioConfig;
+        use std::sync::Arc;
+        use std::sync::atomic::Ordering;
+
+        let config = |runtime: &Runtime<TestEvent>| get_state::<TrackerState, _>(runtime, 0).config;
+        let prepared = Some(AudioConfig { sample_rate: 48_000.0, max_block: 2 });
+        let mut out = [0.0; 2];
+
+        // through the router, a fresh instance prepared off the audio thread
+        // takes the old one's place
+        let tracker = Tracker::default();
+        let released = Arc::clone(&tracker.released);
+        let (mut runtime, router) = Builder::<TestEvent>::new().add(tracker).buffer_length(2).build();
+        router.route(tracker_out(), output()).unwrap();
+        let snapshot = runtime.snapshot().unwrap();
+        runtime.reset();
+
+        router.restore(&snapshot).unwrap();
+        assert_eq!(released.load(Ordering::SeqCst), 0);
+        runtime.process(None, &mut out);
+        assert_eq!(released.load(Ordering::SeqCst), 1);
+        assert_eq!(out, [0.0; 2]);
+        assert_eq!(config(&runtime), prepared);
+
+        // on the runtime itself, the instance is released and prepared again
+        let tracker = Tracker::default();
+        let released = Arc::clone(&tracker.released);
+        let (mut runtime, router) = Builder::<TestEvent>::new().add(tracker).buffer_length(2).build();
+        router.route(tracker_out(), output()).unwrap();
+        runtime.reset();
+        let snapshot = runtime.snapshot().unwrap();
+        runtime.reset();
+
+        runtime.restore(&snapshot).unwrap();
+        assert_eq!(released.load(Ordering::SeqCst), 1);
+        assert_eq!(config(&runtime), prepared);
+        runtime.process(None, &mut out);
+        assert_eq!(out, [1.0; 2]);
+    }
+
+    #[test]
+    fn test_restore_errors() {
+        let (mut runtime, _router) = Builder::<TestEvent>::new().add(Counter).build();
+        let counter = std::any::type_name::<Counter>().to_string();
+
+        let bad_value = StateSnapshot {
+            states: vec![SavedState { instance: counter.clone(), slot: 0, value: serde_json::json!("three") }],
+        };
+        assert!(matches!(runtime.restore(&bad_value), Err(SnapshotErr::Decode { .. })));
+
+        let unknown = StateSnapshot {
+            states: vec![SavedState { instance: counter, slot: 1, value: serde_json::json!({ "ticks": 1 }) }],
+        };
+        let err = runtime.restore(&unknown).unwrap_err();
+        assert!(err.to_string().ends_with("has no persistent state 1"));
+    }
+}
@@ -0,0 +1,336 @@
+// small processors shared by the unit tests
+
+use super::processor::*;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TestEvent;
+
+pub(crate) struct TestHandle;
+impl ProcessorHandle for TestHandle {}
+
+// writes 1.0 to every sample
+pub(crate) struct Source;
+
+impl Processor for Source {
+    type Handle = TestHandle;
+    fn buffers_count() -> usize { 1 }
+    fn slot_count() -> usize { 0 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[PortDescriptor::output("audio_out")];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let mut audio_out = get_output(runtime, handle.buffer_ids_start);
+        audio_out.fill(1.0);
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> { Vec::new() }
+    fn get_handle() -> TestHandle { TestHandle }
+}
+
+// writes 1.0 on the sample each event lands on, 0.0 elsewhere
+#[cfg(feature = "wav")]
+pub(crate) struct Trigger;
+
+#[cfg(feature = "wav")]
+impl Processor for Trigger {
+    type Handle = TestHandle;
+    fn buffers_count() -> usize { 1 }
+    fn slot_count() -> usize { 0 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[PortDescriptor::output("audio_out")];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let ctx = runtime.get_ctx(handle);
+        let mut audio_out = get_output(runtime, handle.buffer_ids_start);
+        audio_out.fill(0.0);
+        for offset in ctx.event_offsets() {
+            if let Some(sample) = audio_out.get_mut(*offset) {
+                *sample = 1.0;
+            }
+        }
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> { Vec::new() }
+    fn get_handle() -> TestHandle { TestHandle }
+}
+
+// multiplies audio_in by gain, which is 2.0 while it's unrouted
+pub(crate) struct Gain;
+
+impl Processor for Gain {
+    type Handle = TestHandle;
+    fn buffers_count() -> usize { 3 }
+    fn slot_count() -> usize { 0 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            PortDescriptor::input("audio_in"),
+            PortDescriptor::input("gain")
+                .kind(PortKind::Control)
+                .default_value(2.0)
+                .range(0.0, 4.0)
+                .description("Multiplies audio_in"),
+            PortDescriptor::output("audio_out"),
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let audio_in = get_input(runtime, handle.buffer_ids_start);
+        let gain = get_input(runtime, handle.buffer_ids_start + 1);
+        let mut audio_out = get_output(runtime, handle.buffer_ids_start + 2);
+        let audio_in = audio_in.unwrap_or(&[]);
+        for (i, out) in audio_out.iter_mut().enumerate() {
+            let gain = gain.map_or(2.0, |gain| gain[i]);
+            *out = audio_in.get(i).copied().unwrap_or(0.0) * gain;
+        }
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> { Vec::new() }
+    fn get_handle() -> TestHandle { TestHandle }
+}
+
+pub(crate) fn named_port<P: Processor, T: Port + 'static>(instance_name: &'static str, field_idx: usize) -> PortHandle<T> {
+    PortHandle::new(instance_name, field_idx, TypeId::of::<T>(), TypeId::of::<P>())
+}
+
+// ports of instances added with Builder::add, which are named after their type
+pub(crate) fn port<P: Processor, T: Port + 'static>(field_idx: usize) -> PortHandle<T> {
+    named_port::<P, T>(std::any::type_name::<P>(), field_idx)
+}
+
+pub(crate) fn source_out() -> PortHandle<Output<'static>> { port::<Source, Output>(0) }
+pub(crate) fn gain_in() -> PortHandle<Input<'static>> { port::<Gain, Input>(0) }
+pub(crate) fn gain_amount() -> PortHandle<Input<'static>> { port::<Gain, Input>(1) }
+pub(crate) fn gain_out() -> PortHandle<Output<'static>> { port::<Gain, Output>(2) }
+
+// delays audio_in by a number of samples fixed when it's constructed
+pub(crate) struct Delay {
+    samples: usize,
+}
+
+impl Delay {
+    pub(crate) fn new(samples: usize) -> Self {
+        Self { samples }
+    }
+}
+
+// has no sensible Default, its length comes from the Delay it was built from
+pub(crate) struct DelayLine {
+    line: Vec<f32>,
+    position: usize,
+}
+
+impl Processor for Delay {
+    type Handle = TestHandle;
+    fn buffers_count() -> usize { 2 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            PortDescriptor::input("audio_in"),
+            PortDescriptor::output("audio_out"),
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let audio_in = get_input(runtime, handle.buffer_ids_start);
+        let mut audio_out = get_output(runtime, handle.buffer_ids_start + 1);
+        let mut delay = get_state::<DelayLine, E>(runtime, handle.slot_ids_start);
+        let audio_in = audio_in.unwrap_or(&[]);
+        for (i, out) in audio_out.iter_mut().enumerate() {
+            let DelayLine { line, position } = &mut *delay;
+            *out = line[*position];
+            line[*position] = audio_in.get(i).copied().unwrap_or(0.0);
+            *position = (*position + 1) % line.len();
+        }
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let mut delay = get_state::<DelayLine, E>(runtime, handle.slot_ids_start);
+        delay.line.fill(0.0);
+        delay.position = 0;
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        vec![Box::new(UnsafeCell::new(DelayLine {
+            line: vec![0.0; self.samples.max(1)],
+            position: 0,
+        }))]
+    }
+    fn get_handle() -> TestHandle { TestHandle }
+}
+
+// a Delay that reports its delay as latency, like a lookahead would
+pub(crate) struct Lookahead {
+    pub(crate) samples: usize,
+}
+
+impl Processor for Lookahead {
+    type Handle = TestHandle;
+    fn buffers_count() -> usize { Delay::buffers_count() }
+    fn slot_count() -> usize { Delay::slot_count() }
+    fn ports() -> &'static [PortDescriptor] { Delay::ports() }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        Delay::call(runtime, handle)
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        Delay::new(self.samples).create_states()
+    }
+    fn get_handle() -> TestHandle { TestHandle }
+    fn latency(&self) -> usize { self.samples }
+}
+
+// a one sample Delay whose input and output are both called audio
+pub(crate) struct Insert;
+
+impl Processor for Insert {
+    type Handle = TestHandle;
+    fn buffers_count() -> usize { Delay::buffers_count() }
+    fn slot_count() -> usize { Delay::slot_count() }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            PortDescriptor::input("audio"),
+            PortDescriptor::output("audio"),
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        Delay::call(runtime, handle)
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        Delay::new(1).create_states()
+    }
+    fn get_handle() -> TestHandle { TestHandle }
+}
+
+// adds its two inputs
+pub(crate) struct Mix;
+
+impl Processor for Mix {
+    type Handle = TestHandle;
+    fn buffers_count() -> usize { 3 }
+    fn slot_count() -> usize { 0 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            PortDescriptor::input("a"),
+            PortDescriptor::input("b"),
+            PortDescriptor::output("audio_out"),
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let a = get_input(runtime, handle.buffer_ids_start).unwrap_or(&[]);
+        let b = get_input(runtime, handle.buffer_ids_start + 1).unwrap_or(&[]);
+        let mut audio_out = get_output(runtime, handle.buffer_ids_start + 2);
+        for (i, out) in audio_out.iter_mut().enumerate() {
+            *out = a.get(i).copied().unwrap_or(0.0) + b.get(i).copied().unwrap_or(0.0);
+        }
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> { Vec::new() }
+    fn get_handle() -> TestHandle { TestHandle }
+}
+
+// records its lifecycle hooks, and writes how often it was reset
+#[derive(Default)]
+pub(crate) struct Tracker {
+    // counts its releases, which are only observable once its states are gone
+    pub(crate) released: Arc<AtomicUsize>,
+}
+
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct TrackerState {
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) config: Option<AudioConfig>,
+    pub(crate) resets: u32,
+    // the Tracker's count. A restored state has one of its own
+    #[cfg_attr(feature = "serde", serde(skip))]
+    released: Arc<AtomicUsize>,
+}
+
+#[cfg(feature = "serde")]
+impl PersistentState for TrackerState {}
+
+impl Processor for Tracker {
+    type Handle = TestHandle;
+    fn buffers_count() -> usize { 1 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[PortDescriptor::output("audio_out")];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let state = get_state::<TrackerState, E>(runtime, handle.slot_ids_start);
+        let mut audio_out = get_output(runtime, handle.buffer_ids_start);
+        audio_out.fill(state.resets as f32);
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        let state = TrackerState { released: Arc::clone(&self.released), ..TrackerState::default() };
+        vec![Box::new(UnsafeCell::new(state))]
+    }
+    fn get_handle() -> TestHandle { TestHandle }
+
+    fn prepare<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle, config: AudioConfig) {
+        get_state::<TrackerState, E>(runtime, handle.slot_ids_start).config = Some(config);
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        get_state::<TrackerState, E>(runtime, handle.slot_ids_start).resets += 1;
+    }
+    fn release<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        get_state::<TrackerState, E>(runtime, handle.slot_ids_start).released.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[cfg(feature = "serde")]
+    fn state_codecs() -> Vec<Option<StateCodec>> {
+        vec![Some(StateCodec::of::<TrackerState>())]
+    }
+}
+
+pub(crate) fn tracker_out() -> PortHandle<Output<'static>> { port::<Tracker, Output>(0) }
+
+#[cfg(feature = "serde")]
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+pub(crate) struct CounterState {
+    pub(crate) ticks: u32,
+}
+
+#[cfg(feature = "serde")]
+impl PersistentState for CounterState {}
+
+// counts its ticks, and writes the count to every sample
+#[cfg(feature = "serde")]
+pub(crate) struct Counter;
+
+#[cfg(feature = "serde")]
+impl Processor for Counter {
+    type Handle = TestHandle;
+    fn buffers_count() -> usize { 1 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[PortDescriptor::output("audio_out")];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let mut state = get_state::<CounterState, E>(runtime, handle.slot_ids_start);
+        state.ticks += 1;
+        let mut audio_out = get_output(runtime, handle.buffer_ids_start);
+        audio_out.fill(state.ticks as f32);
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        vec![Box::new(UnsafeCell::new(CounterState::default()))]
+    }
+    fn get_handle() -> TestHandle { TestHandle }
+
+    fn state_codecs() -> Vec<Option<StateCodec>> {
+        vec![Some(StateCodec::of::<CounterState>())]
+    }
+}
+
+#[cfg(feature = "serde")]
+pub(crate) fn counter_out() -> PortHandle<Output<'static>> { port::<Counter, Output>(0) }
+
+// A fresh directory for one test's files, so tests running at once, or
+// two runs at once, don't write over each other. Remove it when done
+pub(crate) fn test_dir(test_name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lyris_{}_{}", test_name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,431 @@
+// Helpers for testing processors and graphs: run a processor on its own
+// with given inputs, capture what comes out of any port, and compare it
+// against golden CSV or WAV files
+//
+//     let capture = ProcessorTest::new(MyFilter::new())
+//         .input("audio_in", &impulse)
+//         .parameter("cutoff", 1000.0)
+//         .run(4);
+//     capture.compare_csv("tests/golden/filter.csv", 1e-6)?;
+
+use std::fmt::{self, Debug, Display, Formatter};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use super::builder::Builder;
+use super::patch::intern;
+use super::processor::*;
+use super::router::RoutingErr;
+#[cfg(feature = "wav")]
+use super::wav::{read_wav, write_wav, WavFormat};
+
+// Captures whatever reaches its input. Add one to any graph and route an
+// output to it to see that port. It allocates on the audio thread, so it's
+// only meant for tests
+#[derive(Clone, Default)]
+pub struct Probe {
+    samples: Arc<Mutex<Vec<f32>>>,
+}
+
+pub struct ProbeHandle;
+impl ProcessorHandle for ProbeHandle {}
+
+impl Probe {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The input of the probe added under this instance name
+    pub fn input(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 0, TypeId::of::<Input>(), TypeId::of::<Probe>())
+    }
+
+    // Everything captured so far
+    pub fn samples(&self) -> Vec<f32> {
+        self.samples.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        self.samples.lock().unwrap().clear();
+    }
+}
+
+impl Processor for Probe {
+    type Handle = ProbeHandle;
+    fn buffers_count() -> usize { 1 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[PortDescriptor::input("audio_in")];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let audio_in = get_input(runtime, handle.buffer_ids_start);
+        let samples = get_state::<Arc<Mutex<Vec<f32>>>, E>(runtime, handle.slot_ids_start);
+        let mut samples = samples.lock().unwrap();
+        match *audio_in {
+            Some(audio_in) => samples.extend_from_slice(audio_in),
+            None => {
+                let len = samples.len();
+                samples.resize(len + runtime.buffer_size, 0.0);
+            },
+        }
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        vec![Box::new(UnsafeCell::new(Arc::clone(&self.samples)))]
+    }
+    fn get_handle() -> ProbeHandle { ProbeHandle }
+}
+
+// Plays a fixed signal, then silence
+struct Feed {
+    samples: Vec<f32>,
+}
+
+struct FeedHandle;
+impl ProcessorHandle for FeedHandle {}
+
+struct FeedState {
+    samples: Vec<f32>,
+    position: usize,
+}
+
+impl Processor for Feed {
+    type Handle = FeedHandle;
+    fn buffers_count() -> usize { 1 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[PortDescriptor::output("audio_out")];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let mut audio_out = get_output(runtime, handle.buffer_ids_start);
+        let mut feed = get_state::<FeedState, E>(runtime, handle.slot_ids_start);
+        let FeedState { samples, position } = &mut *feed;
+        for out in audio_out.iter_mut() {
+            *out = samples.get(*position).copied().unwrap_or(0.0);
+            *position += 1;
+        }
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        vec![Box::new(UnsafeCell::new(FeedState { samples: self.samples.clone(), position: 0 }))]
+    }
+    fn get_handle() -> FeedHandle { FeedHandle }
+}
+
+// The instance name the processor under test gets. Its feeds and probes
+// are named after the ports they're routed to, as in:audio_in and
+// out:audio_out, so an input and an output may share a name
+const UNDER_TEST: &str = "__under_test__";
+
+// Runs a single processor. Inputs are fed a signal, or a constant
+// parameter, or nothing, and every output is captured
+pub struct ProcessorTest<P: Processor> {
+    processor: P,
+    buffer_size: usize,
+    sample_rate: f32,
+    seed: u64,
+    inputs: Vec<(&'static str, Vec<f32>)>,
+    parameters: Vec<(&'static str, f32)>,
+}
+
+impl<P: Processor> ProcessorTest<P> {
+    pub fn new(processor: P) -> Self {
+        Self {
+            processor,
+            buffer_size: 64,
+            sample_rate: 48_000.0,
+            seed: 0,
+            inputs: Vec::new(),
+            parameters: Vec::new(),
+        }
+    }
+
+    pub fn buffer_length(mut self, length: usize) -> Self {
+        self.buffer_size = length;
+        self
+    }
+
+    pub fn sample_rate(mut self, sample_rate: f32) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    // The graph seed, see Builder::seed
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // Plays samples into an input, followed by silence
+    pub fn input(mut self, port: &'static str, samples: &[f32]) -> Self {
+        self.inputs.push((port, samples.to_vec()));
+        self
+    }
+
+    pub fn parameter(mut self, port: &'static str, value: f32) -> Self {
+        self.parameters.push((port, value));
+        self
+    }
+
+    // Runs the processor for a number of blocks. Panics if a port named
+    // in input() or parameter() isn't one of the processor's inputs
+    pub fn run(self, blocks: usize) -> Capture {
+        self.try_run(blocks).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_run(self, blocks: usize) -> Result<Capture, RoutingErr> {
+        let ports = P::ports();
+        let field_idx = |port: &str| ports.iter()
+            .position(|descriptor| descriptor.name == port && descriptor.port_type.is_input())
+            .ok_or_else(|| RoutingErr::PortNotFound { instance: UNDER_TEST.to_string(), port: port.to_string() });
+        let input = |field_idx| PortHandle::<Input>::new(UNDER_TEST, field_idx, TypeId::of::<Input>(), TypeId::of::<P>());
+        let output = |field_idx| PortHandle::<Output>::new(UNDER_TEST, field_idx, TypeId::of::<Output>(), TypeId::of::<P>());
+
+        let fed_inputs = self.inputs.iter()
+            .map(|(port, _)| field_idx(port))
+            .collect::<Result<Vec<_>, _>>()?;
+        let parameter_inputs = self.parameters.iter()
+            .map(|(port, _)| field_idx(port))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut builder = Builder::<()>::new()
+            .add_processor(self.processor, UNDER_TEST)
+            .buffer_length(self.buffer_size)
+            .sample_rate(self.sample_rate)
+            .seed(self.seed);
+        let feeds: Vec<&'static str> = self.inputs.iter().map(|(port, _)| intern(&format!("in:{}", port))).collect();
+        for (feed, (_, samples)) in feeds.iter().zip(&self.inputs) {
+            builder = builder.add_processor(Feed { samples: samples.clone() }, feed);
+        }
+        let probes: Vec<(&'static str, &'static str, usize, Probe)> = ports.iter()
+            .enumerate()
+            .filter(|(_, descriptor)| descriptor.port_type.is_output())
+            .map(|(field_idx, descriptor)| {
+                (descriptor.name, intern(&format!("out:{}", descriptor.name)), field_idx, Probe::new())
+            })
+            .collect();
+        for (_, probe_name, _, probe) in &probes {
+            builder = builder.add_processor(probe.clone(), probe_name);
+        }
+        let (mut runtime, router) = builder.try_build()?;
+
+        for (feed, field_idx) in feeds.into_iter().zip(fed_inputs) {
+            let feed_out = PortHandle::<Output>::new(feed, 0, TypeId::of::<Output>(), TypeId::of::<Feed>());
+            router.route(feed_out, input(field_idx))?;
+        }
+        for ((_, value), field_idx) in self.parameters.iter().zip(parameter_inputs) {
+            router.set_parameter(input(field_idx), *value)?;
+        }
+        for (_, probe_name, field_idx, _) in &probes {
+            router.route(output(*field_idx), Probe::input(probe_name))?;
+        }
+
+        let mut silence = vec![0.0; self.buffer_size];
+        for _ in 0..blocks {
+            runtime.process(None, &mut silence);
+        }
+
+        Ok(Capture {
+            sample_rate: self.sample_rate,
+            ports: probes.into_iter().map(|(name, _, _, probe)| (name, probe.samples())).collect(),
+        })
+    }
+}
+
+// What came out of each output port, in port order
+#[derive(Clone, Debug, PartialEq)]
+pub struct Capture {
+    pub sample_rate: f32,
+    pub ports: Vec<(&'static str, Vec<f32>)>,
+}
+
+impl Capture {
+    pub fn port(&self, name: &str) -> Option<&[f32]> {
+        self.ports.iter()
+            .find(|(port, _)| *port == name)
+            .map(|(_, samples)| samples.as_slice())
+    }
+
+    // One column per port, headed by its name
+    pub fn to_csv(&self) -> String {
+        let mut csv = self.ports.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(",");
+        csv.push('\n');
+        let rows = self.ports.iter().map(|(_, samples)| samples.len()).max().unwrap_or(0);
+        for row in 0..rows {
+            let values: Vec<String> = self.ports.iter()
+                .map(|(_, samples)| samples.get(row).map_or(String::new(), |sample| sample.to_string()))
+                .collect();
+            csv.push_str(&values.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    pub fn write_csv(&self, path: impl AsRef<Path>) -> Result<(), GoldenErr> {
+        std::fs::write(path, self.to_csv()).map_err(GoldenErr::Io)
+    }
+
+    // Checks every column of a golden CSV against the port it's named after.
+    // Ports the file leaves out aren't checked
+    pub fn compare_csv(&self, path: impl AsRef<Path>, tolerance: f32) -> Result<(), GoldenErr> {
+        let csv = std::fs::read_to_string(path).map_err(GoldenErr::Io)?;
+        let mut lines = csv.lines();
+        let header: Vec<&str> = lines.next().unwrap_or_default().split(',').collect();
+        let mut columns = vec![Vec::new(); header.len()];
+        for (line_idx, line) in lines.enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            for (column, value) in line.split(',').enumerate().filter(|(_, value)| !value.trim().is_empty()) {
+                let value = value.trim().parse()
+                    .map_err(|_| GoldenErr::Parse { line: line_idx + 2, value: value.to_string() })?;
+                columns.get_mut(column)
+                    .ok_or_else(|| GoldenErr::Parse { line: line_idx + 2, value: line.to_string() })?
+                    .push(value);
+            }
+        }
+
+        for (name, expected) in header.iter().zip(&columns) {
+            self.compare(name.trim(), expected, tolerance)?;
+        }
+        Ok(())
+    }
+
+    // Writes the ports as the channels of a WAV file
+    #[cfg(feature = "wav")]
+    pub fn write_wav(&self, path: impl AsRef<Path>, ports: &[&str], format: WavFormat) -> Result<(), GoldenErr> {
+        let channels = ports.iter()
+            .map(|port| self.port(port).ok_or_else(|| GoldenErr::PortMissing { port: port.to_string() }))
+            .collect::<Result<Vec<_>, _>>()?;
+        write_wav(path.as_ref(), &channels, self.sample_rate as u32, format).map_err(GoldenErr::Wav)
+    }
+
+    // Checks each channel of a golden WAV file against a port, in order
+    #[cfg(feature = "wav")]
+    pub fn compare_wav(&self, path: impl AsRef<Path>, ports: &[&str], tolerance: f32) -> Result<(), GoldenErr> {
+        let (channels, _) = read_wav(path.as_ref()).map_err(GoldenErr::Wav)?;
+        if channels.len() != ports.len() {
+            return Err(GoldenErr::ChannelCount { expected: ports.len(), found: channels.len() });
+        }
+        for (port, expected) in ports.iter().zip(&channels) {
+            self.compare(port, expected, tolerance)?;
+        }
+        Ok(())
+    }
+
+    fn compare(&self, port: &str, expected: &[f32], tolerance: f32) -> Result<(), GoldenErr> {
+        let found = self.port(port).ok_or_else(|| GoldenErr::PortMissing { port: port.to_string() })?;
+        if found.len() != expected.len() {
+            return Err(GoldenErr::Length { port: port.to_string(), expected: expected.len(), found: found.len() });
+        }
+        match expected.iter().zip(found).position(|(expected, found)| (expected - found).abs() > tolerance) {
+            Some(sample) => Err(GoldenErr::Mismatch {
+                port: port.to_string(),
+                sample,
+                expected: expected[sample],
+                found: found[sample],
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum GoldenErr {
+    Io(std::io::Error),
+    #[cfg(feature = "wav")]
+    Wav(hound::Error),
+    Parse { line: usize, value: String },
+    PortMissing { port: String },
+    ChannelCount { expected: usize, found: usize },
+    Length { port: String, expected: usize, found: usize },
+    Mismatch { port: String, sample: usize, expected: f32, found: f32 },
+}
+
+impl Display for GoldenErr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            GoldenErr::Io(err) => write!(f, "Could not access the golden file: {}", err),
+            #[cfg(feature = "wav")]
+            GoldenErr::Wav(err) => write!(f, "Could not access the golden WAV file: {}", err),
+            GoldenErr::Parse { line, value } => write!(f, "Line {} of the golden file has a bad value: {}", line, value),
+            GoldenErr::PortMissing { port } => write!(f, "Nothing was captured from port \"{}\"", port),
+            GoldenErr::ChannelCount { expected, found } =>
+                write!(f, "Expected a golden file with {} channels, found {}", expected, found),
+            GoldenErr::Length { port, expected, found } =>
+                write!(f, "Port \"{}\" captured {} samples, the golden file has {}", port, found, expected),
+            GoldenErr::Mismatch { port, sample, expected, found } =>
+                write!(f, "Port \"{}\" differs at sample {}: expected {}, found {}", port, sample, expected, found),
+        }
+    }
+}
+
+impl std::error::Error for GoldenErr {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_processors::*;
+
+    #[test]
+    fn test_single_processor() {
+        let capture = ProcessorTest::new(Gain)
+            .input("audio_in", &[1.0, 2.0, 3.0])
+            .parameter("gain", 0.5)
+            .buffer_length(2)
+            .run(2);
+        assert_eq!(capture.port("audio_out"), Some(&[0.5, 1.0, 1.5, 0.0][..]));
+        assert_eq!(capture.to_csv(), "audio_out\n0.5\n1\n1.5\n0\n");
+
+        let err = ProcessorTest::new(Gain).input("audio_out", &[]).try_run(1);
+        assert!(matches!(err, Err(RoutingErr::PortNotFound { .. })));
+    }
+
+    #[test]
+    fn test_shared_port_name() {
+        let capture = ProcessorTest::new(Insert).input("audio", &[1.0, 2.0]).buffer_length(3).run(1);
+        assert_eq!(capture.port("audio"), Some(&[0.0, 1.0, 2.0][..]));
+    }
+
+    #[test]
+    fn test_golden_csv() {
+        let dir = test_dir("golden_csv");
+        let path = dir.join("golden.csv");
+        let capture = ProcessorTest::new(Delay::new(1)).input("audio_in", &[1.0]).buffer_length(3).run(1);
+        capture.write_csv(&path).unwrap();
+        capture.compare_csv(&path, 0.0).unwrap();
+
+        std::fs::write(&path, "audio_out\n0\n1.01\n0\n").unwrap();
+        capture.compare_csv(&path, 0.1).unwrap();
+        let err = capture.compare_csv(&path, 0.001).unwrap_err();
+        assert!(matches!(err, GoldenErr::Mismatch { sample: 1, .. }));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn test_golden_wav() {
+        let dir = test_dir("golden_wav");
+        let path = dir.join("golden.wav");
+        let capture = ProcessorTest::new(Gain).input("audio_in", &[0.25, -0.25]).buffer_length(2).run(1);
+        capture.write_wav(&path, &["audio_out"], WavFormat::Int16).unwrap();
+        capture.compare_wav(&path, &["audio_out"], 1e-4).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_probe_in_graph() {
+        let probe = Probe::new();
+        let (mut runtime, router) = crate::Builder::<TestEvent>::new()
+            .add(Source)
+            .add(Gain)
+            .add_processor(probe.clone(), "probe")
+            .buffer_length(2)
+            .build();
+        router.route(source_out(), gain_in()).unwrap();
+        router.route(source_out(), Probe::input("probe")).unwrap();
+        router.route(gain_out(), output()).unwrap();
+
+        let mut out = [0.0; 2];
+        runtime.process(None, &mut out);
+        runtime.process(None, &mut out);
+        assert_eq!(probe.samples(), [1.0; 4]);
+        assert_eq!(out, [2.0; 2]);
+    }
+}
@@ -1,11 +1,17 @@
 // types that are referenced throughout core
 
-use std::any::TypeId;
+use std::any::{Any, TypeId};
+use std::cell::UnsafeCell;
 use std::marker::PhantomData;
 use super::runtime::Runtime;
+use super::processor::{PortDescriptor, SYSTEM_INPUT_PORTS, SYSTEM_OUTPUT_PORTS};
+#[cfg(feature = "serde")]
+use super::snapshot::StateCodec;
 use std::fmt;
 use std::ops::Add;
 
+pub(crate) type BoxedState = Box<UnsafeCell<dyn Any + Send + 'static>>;
+
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub(crate) struct PhysicalBuffer(pub(crate) usize);
 
@@ -19,6 +25,26 @@ pub struct ContextHandle{
     pub slot_ids_start: usize,
 }
 
+// What prepare() tells a processor about the stream it will run in.
+// max_block is the runtime's buffer length, no tick is ever longer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AudioConfig {
+    pub sample_rate: f32,
+    pub max_block: usize,
+}
+
+// The lifecycle hooks of one processor instance. The runtime keeps these
+// for every instance, scheduled or not
+#[derive(Clone, Copy)]
+pub(crate) struct Lifecycle<E: Clone + Copy + 'static> {
+    pub(crate) context_handle: ContextHandle,
+    // what instance_seed is worked out from
+    pub(crate) instance_name: &'static str,
+    pub(crate) prepare: fn(&Runtime<E>, ContextHandle, AudioConfig),
+    pub(crate) reset: fn(&Runtime<E>, ContextHandle),
+    pub(crate) release: fn(&Runtime<E>, ContextHandle),
+}
+
 #[derive(Clone, Copy)]
 pub(crate) struct SystemBuffers{
     pub(crate) input: Option<SystemComponent>,
@@ -47,6 +73,11 @@ pub struct UserComponent<E: Clone + Copy + 'static> {
     pub(crate) field_count: usize,
     pub(crate) instance_name: &'static str,
     pub(crate) processor_type: TypeId,
+    pub(crate) ports: &'static [PortDescriptor],
+    // what a bypass connects, see control::main_ports
+    pub(crate) main_input: Option<usize>,
+    pub(crate) main_output: Option<usize>,
+    pub(crate) latency: usize,
 }
 
 #[derive(Clone, Copy)]
@@ -62,6 +93,44 @@ pub enum StoredComponent<E: Clone + Copy + 'static>{
     System(SystemComponent)
 }
 
+impl<E: Clone + Copy + 'static> StoredComponent<E> {
+    pub(crate) fn component_id(&self) -> ComponentId {
+        match self {
+            StoredComponent::User(user_comp) => user_comp.context_handle.component_id,
+            StoredComponent::System(sys_comp) => sys_comp.component_id,
+        }
+    }
+
+    pub(crate) fn instance_name(&self) -> &'static str {
+        match self {
+            StoredComponent::User(user_comp) => user_comp.instance_name,
+            StoredComponent::System(sys_comp) => sys_comp.instance_name,
+        }
+    }
+
+    pub(crate) fn ports(&self) -> &'static [PortDescriptor] {
+        match self {
+            StoredComponent::User(user_comp) => user_comp.ports,
+            StoredComponent::System(sys_comp) => match sys_comp.instance_name {
+                "__system_input__" => SYSTEM_INPUT_PORTS,
+                "__system_output__" => SYSTEM_OUTPUT_PORTS,
+                _ => unreachable!("This error should be unreachable. Unknown system component type"),
+            },
+        }
+    }
+}
+
+// Describes one entry of Runtime::states, for snapshots
+#[cfg(feature = "serde")]
+#[derive(Clone, Copy)]
+pub(crate) struct StateSlot {
+    pub(crate) instance_name: &'static str,
+    // index among the instance's own states
+    pub(crate) slot: usize,
+    // None unless the processor opted this state into snapshots
+    pub(crate) codec: Option<StateCodec>,
+}
+
 // Context provides safe wrapper around unsafe runtime access
 pub struct Context<'a, E: Clone + Copy + 'static> {
     pub runtime: &'a Runtime<E>,
@@ -70,14 +139,25 @@ pub struct Context<'a, E: Clone + Copy + 'static> {
 }
 
 impl<'a, E: Clone + Copy> Context<'a, E> {
-    pub fn buffer_size(&self) -> usize { todo!(); }
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
 
-    pub fn sample_rate(){ todo!(); }
+    pub fn sample_rate(&self) -> f32 {
+        self.runtime.sample_rate
+    }
 
+    // the events that arrived for this tick
     pub fn get_events(&self) -> &[E] {
         &self.runtime.current_events
     }
 
+    // The sample within this tick each of get_events() lands on. Events
+    // sent through the Router land on 0, rendered ones where they were scheduled
+    pub fn event_offsets(&self) -> &[usize] {
+        &self.runtime.event_offsets
+    }
+
 }
 
 // newtype for impl debug/display
@@ -0,0 +1,108 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+// The sample formats WAV files are written in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WavFormat {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl WavFormat {
+    fn spec(self, channels: u16, sample_rate: u32) -> hound::WavSpec {
+        let (bits_per_sample, sample_format) = match self {
+            WavFormat::Int16 => (16, hound::SampleFormat::Int),
+            WavFormat::Int24 => (24, hound::SampleFormat::Int),
+            WavFormat::Float32 => (32, hound::SampleFormat::Float),
+        };
+        hound::WavSpec { channels, sample_rate, bits_per_sample, sample_format }
+    }
+}
+
+// Writes interleaved samples one at a time. Integer formats clip at full
+// scale, floats are written as they are
+pub(crate) struct WavWriter {
+    inner: hound::WavWriter<BufWriter<File>>,
+    format: WavFormat,
+}
+
+impl WavWriter {
+    pub(crate) fn create(path: &Path, channels: u16, sample_rate: u32, format: WavFormat) -> Result<Self, hound::Error> {
+        let inner = hound::WavWriter::create(path, format.spec(channels, sample_rate))?;
+        Ok(Self { inner, format })
+    }
+
+    pub(crate) fn write(&mut self, sample: f32) -> Result<(), hound::Error> {
+        match self.format {
+            WavFormat::Int16 => self.inner.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16),
+            WavFormat::Int24 => self.inner.write_sample((sample.clamp(-1.0, 1.0) * 8_388_607.0).round() as i32),
+            WavFormat::Float32 => self.inner.write_sample(sample),
+        }
+    }
+
+    pub(crate) fn finalize(self) -> Result<(), hound::Error> {
+        self.inner.finalize()
+    }
+}
+
+// Writes one channel per slice. Shorter channels are padded with silence
+pub(crate) fn write_wav(path: &Path, channels: &[&[f32]], sample_rate: u32, format: WavFormat) -> Result<(), hound::Error> {
+    let mut writer = WavWriter::create(path, channels.len() as u16, sample_rate, format)?;
+    let frames = channels.iter().map(|channel| channel.len()).max().unwrap_or(0);
+    for frame in 0..frames {
+        for channel in channels {
+            writer.write(channel.get(frame).copied().unwrap_or(0.0))?;
+        }
+    }
+    writer.finalize()
+}
+
+// Every channel of a WAV file, scaled to -1..1, and its sample rate
+pub(crate) fn read_wav(path: &Path) -> Result<(Vec<Vec<f32>>, u32), hound::Error> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let full_scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader.samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 / full_scale))
+                .collect::<Result<_, _>>()?
+        },
+    };
+
+    let channel_count = spec.channels.max(1) as usize;
+    let mut channels = vec![Vec::with_capacity(interleaved.len() / channel_count); channel_count];
+    for (i, sample) in interleaved.into_iter().enumerate() {
+        channels[i % channel_count].push(sample);
+    }
+    Ok((channels, spec.sample_rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_processors::test_dir;
+
+    #[test]
+    fn test_wav_round_trip() {
+        let dir = test_dir("wav_round_trip");
+        let path = dir.join("round_trip.wav");
+        let left = [0.0, 0.5, -0.5, 1.0];
+        let right = [0.25, -1.0];
+
+        for (format, tolerance) in [(WavFormat::Int16, 1e-4), (WavFormat::Int24, 1e-6), (WavFormat::Float32, 0.0)] {
+            write_wav(&path, &[&left, &right], 44_100, format).unwrap();
+            let (channels, sample_rate) = read_wav(&path).unwrap();
+            assert_eq!(sample_rate, 44_100);
+            assert_eq!(channels.len(), 2);
+            for (read, written) in channels[0].iter().zip(left) {
+                assert!((read - written).abs() <= tolerance, "{:?}: {} != {}", format, read, written);
+            }
+            assert_eq!(channels[1][2..], [0.0, 0.0]);
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
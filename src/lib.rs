@@ -1,17 +1,45 @@
 mod core;
+pub mod processors;
 
 pub use {
     // Core building blocks
     core::Builder,
     core::Runtime,
     core::Router,
+    core::Registry,
+
+    // Saving and loading graphs
+    core::Patch,
+    core::patch::PatchErr,
+    core::patch::PatchInstance,
+    core::patch::PatchConnection,
+    core::patch::PatchParameter,
+    core::patch::PatchPort,
+    core::patch::PATCH_VERSION,
+    core::patch::NAME_BYTES,
+
+    // Presets and morphing
+    core::preset::Preset,
+    core::preset::MorphCurve,
+    core::preset::MorphOptions,
 
     // the whole processor module
     core::processor,
 
+    // Harness for testing processors and graphs
+    core::testing,
+
     
     // Routing helpers
     core::router::PortHandle,
+    core::router::RoutingErr,
+
+    // Graph introspection
+    core::graph::ProcessorInfo,
+    core::graph::PortInfo,
+    core::graph::PortRef,
+    core::graph::Connection,
+    core::graph::GraphDescription,
     core::processor::input,
     core::processor::output, 
     
@@ -19,4 +47,24 @@ pub use {
     core::processor::Processor,
     core::processor::Port,
     core::processor::PortType,
-};
\ No newline at end of file
+    core::processor::PortDescriptor,
+    core::processor::PortKind,
+    core::processor::AudioConfig,
+};
+
+// Processor state snapshots
+#[cfg(feature = "serde")]
+pub use core::snapshot::{
+    PersistentState,
+    StateCodec,
+    StateSnapshot,
+    SavedState,
+    PendingSnapshot,
+    SnapshotErr,
+};
+
+// Audio files
+#[cfg(feature = "wav")]
+pub use core::wav::WavFormat;
+#[cfg(feature = "wav")]
+pub use core::render::{Render, RenderLength};
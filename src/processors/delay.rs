@@ -0,0 +1,486 @@
+// Delays. Lines are sized from a maximum delay in prepare, off the audio
+// thread, and read at fractional positions so their time can be modulated
+// at audio rate. DelayLine is public, for chorus, flangers and physical
+// models to build on
+//
+// Their inputs after audio_in are:
+//   time      seconds, or beats of the tempo input once tempo-synced
+//   feedback  how much of the delayed signal goes back in
+//   mix       from 0 for only the dry signal to 1 for only the delayed one
+//   tempo     in BPM, only read when tempo-synced
+//
+//     let (runtime, router) = Builder::<()>::new()
+//         .add_processor(PingPong::new(2.0).tempo_sync(true), "echo")
+//         .build();
+//     router.set_parameter(PingPong::time_in("echo"), 0.75)?;
+//     router.set_parameter(PingPong::tempo_in("echo"), 128.0)?;
+
+use crate::core::processor::*;
+use super::value_at;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    // 3rd order, flatter than linear at high frequencies
+    Lagrange,
+    // 1st order allpass. No high frequency loss, so it suits feedback loops
+    // and physical models, but it colours fast modulation
+    Allpass,
+}
+
+// A circular buffer read some fractional number of samples back
+pub struct DelayLine {
+    buffer: Vec<f32>,
+    // where the next sample is written
+    position: usize,
+    interpolation: Interpolation,
+    // the allpass' last output
+    allpass: f32,
+}
+
+impl DelayLine {
+    // Allocates for delays of up to max_samples
+    pub fn new(max_samples: usize, interpolation: Interpolation) -> Self {
+        Self {
+            // room for the points interpolation reads around the longest delay
+            buffer: vec![0.0; max_samples.max(1) + 3],
+            position: 0,
+            interpolation,
+            allpass: 0.0,
+        }
+    }
+
+    pub fn max_delay(&self) -> f32 {
+        (self.buffer.len() - 3) as f32
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.fill(0.0);
+        self.allpass = 0.0;
+    }
+
+    // The sample written count writes ago
+    fn at(&self, count: usize) -> f32 {
+        let len = self.buffer.len();
+        self.buffer[(self.position + len - count % len) % len]
+    }
+
+    // Reads delay samples back from the next write, so a delay of 1 is the
+    // last sample written. Delays are kept within 1 and max_delay
+    pub fn read(&mut self, delay: f32) -> f32 {
+        let delay = delay.clamp(1.0, self.max_delay());
+        let whole = delay.floor() as usize;
+        let f = delay - whole as f32;
+        match self.interpolation {
+            Interpolation::Linear => self.at(whole) * (1.0 - f) + self.at(whole + 1) * f,
+            Interpolation::Lagrange => {
+                // the point before whole isn't written yet for delays under 2
+                let y0 = self.at((whole - 1).max(1));
+                let (y1, y2, y3) = (self.at(whole), self.at(whole + 1), self.at(whole + 2));
+                -f * (f - 1.0) * (f - 2.0) / 6.0 * y0
+                    + (f + 1.0) * (f - 1.0) * (f - 2.0) / 2.0 * y1
+                    - (f + 1.0) * f * (f - 2.0) / 2.0 * y2
+                    + (f + 1.0) * f * (f - 1.0) / 6.0 * y3
+            },
+            Interpolation::Allpass => {
+                let eta = (1.0 - f) / (1.0 + f);
+                self.allpass = eta * self.at(whole) + self.at(whole + 1) - eta * self.allpass;
+                self.allpass
+            },
+        }
+    }
+
+    pub fn write(&mut self, sample: f32) {
+        self.buffer[self.position] = sample;
+        self.position = (self.position + 1) % self.buffer.len();
+    }
+}
+
+const AUDIO_IN: PortDescriptor = PortDescriptor::input("audio_in");
+const TIME: PortDescriptor = PortDescriptor::input("time")
+    .kind(PortKind::Control)
+    .default_value(0.25)
+    .range(0.0, 10.0)
+    .description("Seconds, or beats of the tempo input once tempo-synced");
+const FEEDBACK: PortDescriptor = PortDescriptor::input("feedback")
+    .kind(PortKind::Control)
+    .range(0.0, 1.0);
+const MIX: PortDescriptor = PortDescriptor::input("mix")
+    .kind(PortKind::Control)
+    .default_value(0.5)
+    .range(0.0, 1.0)
+    .description("From only the dry signal at 0 to only the delayed one at 1");
+const TEMPO: PortDescriptor = PortDescriptor::input("tempo")
+    .kind(PortKind::Control)
+    .default_value(120.0)
+    .range(20.0, 300.0)
+    .unit("BPM");
+
+// Port handles every delay has
+pub trait DelayEffect: Processor {
+    fn audio_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 0, TypeId::of::<Input>(), TypeId::of::<Self>())
+    }
+
+    fn time_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 1, TypeId::of::<Input>(), TypeId::of::<Self>())
+    }
+
+    fn feedback_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 2, TypeId::of::<Input>(), TypeId::of::<Self>())
+    }
+
+    fn mix_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 3, TypeId::of::<Input>(), TypeId::of::<Self>())
+    }
+
+    fn tempo_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 4, TypeId::of::<Input>(), TypeId::of::<Self>())
+    }
+}
+
+pub struct DelayHandle;
+impl ProcessorHandle for DelayHandle {}
+
+// What every delay is set up with
+#[derive(Clone, Copy)]
+struct Settings {
+    max_seconds: f32,
+    interpolation: Interpolation,
+    tempo_sync: bool,
+}
+
+impl Settings {
+    fn line(&self, sample_rate: f32) -> DelayLine {
+        DelayLine::new((self.max_seconds * sample_rate).ceil() as usize, self.interpolation)
+    }
+}
+
+// The time, feedback and mix every delay reads each sample
+struct Controls<'a> {
+    time: Input<'a>,
+    feedback: Input<'a>,
+    mix: Input<'a>,
+    tempo: Input<'a>,
+}
+
+impl Controls<'_> {
+    fn read<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) -> Controls<'_> {
+        Controls {
+            time: get_input(runtime, handle.buffer_ids_start + 1),
+            feedback: get_input(runtime, handle.buffer_ids_start + 2),
+            mix: get_input(runtime, handle.buffer_ids_start + 3),
+            tempo: get_input(runtime, handle.buffer_ids_start + 4),
+        }
+    }
+
+    // delay in samples, feedback and mix at sample i
+    fn at(&self, i: usize, settings: &Settings, sample_rate: f32) -> (f32, f32, f32) {
+        let mut seconds = value_at(*self.time, i, 0.25);
+        if settings.tempo_sync {
+            seconds *= 60.0 / value_at(*self.tempo, i, 120.0).max(1.0);
+        }
+        let feedback = value_at(*self.feedback, i, 0.0).clamp(-1.0, 1.0);
+        let mix = value_at(*self.mix, i, 0.5).clamp(0.0, 1.0);
+        (seconds * sample_rate, feedback, mix)
+    }
+}
+
+// A mono delay with feedback
+pub struct Delay {
+    settings: Settings,
+}
+
+impl Delay {
+    // Delays of up to max_seconds
+    pub fn new(max_seconds: f32) -> Self {
+        Self {
+            settings: Settings {
+                max_seconds,
+                interpolation: Interpolation::Linear,
+                tempo_sync: false,
+            },
+        }
+    }
+
+    pub fn interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.settings.interpolation = interpolation;
+        self
+    }
+
+    // Reads the time input in beats of the tempo input instead of seconds
+    pub fn tempo_sync(mut self, tempo_sync: bool) -> Self {
+        self.settings.tempo_sync = tempo_sync;
+        self
+    }
+
+    pub fn audio_out(instance_name: &'static str) -> PortHandle<Output<'static>> {
+        PortHandle::new(instance_name, 5, TypeId::of::<Output>(), TypeId::of::<Delay>())
+    }
+}
+
+impl DelayEffect for Delay {}
+
+struct DelayState {
+    settings: Settings,
+    line: DelayLine,
+}
+
+impl Processor for Delay {
+    type Handle = DelayHandle;
+    fn buffers_count() -> usize { 6 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[AUDIO_IN, TIME, FEEDBACK, MIX, TEMPO, PortDescriptor::output("audio_out")];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let audio_in = get_input(runtime, handle.buffer_ids_start);
+        let controls = Controls::read(runtime, handle);
+        let mut audio_out = get_output(runtime, handle.buffer_ids_start + 5);
+        let mut state = get_state::<DelayState, E>(runtime, handle.slot_ids_start);
+        let DelayState { settings, line } = &mut *state;
+        let sample_rate = runtime.sample_rate();
+
+        for (i, out) in audio_out.iter_mut().enumerate() {
+            let (delay, feedback, mix) = controls.at(i, settings, sample_rate);
+            let dry = value_at(*audio_in, i, 0.0);
+            let wet = line.read(delay);
+            line.write(dry + feedback * wet);
+            *out = dry * (1.0 - mix) + wet * mix;
+        }
+    }
+    fn prepare<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle, config: AudioConfig) {
+        let mut state = get_state::<DelayState, E>(runtime, handle.slot_ids_start);
+        state.line = state.settings.line(config.sample_rate);
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        get_state::<DelayState, E>(runtime, handle.slot_ids_start).line.clear();
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        // sized for real in prepare, once the sample rate is known
+        vec![Box::new(UnsafeCell::new(DelayState {
+            settings: self.settings,
+            line: DelayLine::new(0, self.settings.interpolation),
+        }))]
+    }
+    fn get_handle() -> DelayHandle { DelayHandle }
+}
+
+// A stereo delay whose repeats bounce between left and right. The input
+// is delayed on the left first, and feedback carries each repeat across
+pub struct PingPong {
+    settings: Settings,
+}
+
+impl PingPong {
+    // Delays of up to max_seconds
+    pub fn new(max_seconds: f32) -> Self {
+        Self {
+            settings: Settings {
+                max_seconds,
+                interpolation: Interpolation::Linear,
+                tempo_sync: false,
+            },
+        }
+    }
+
+    pub fn interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.settings.interpolation = interpolation;
+        self
+    }
+
+    // Reads the time input in beats of the tempo input instead of seconds
+    pub fn tempo_sync(mut self, tempo_sync: bool) -> Self {
+        self.settings.tempo_sync = tempo_sync;
+        self
+    }
+
+    pub fn left_out(instance_name: &'static str) -> PortHandle<Output<'static>> {
+        PortHandle::new(instance_name, 5, TypeId::of::<Output>(), TypeId::of::<PingPong>())
+    }
+
+    pub fn right_out(instance_name: &'static str) -> PortHandle<Output<'static>> {
+        PortHandle::new(instance_name, 6, TypeId::of::<Output>(), TypeId::of::<PingPong>())
+    }
+}
+
+impl DelayEffect for PingPong {}
+
+struct PingPongState {
+    settings: Settings,
+    left: DelayLine,
+    right: DelayLine,
+}
+
+impl Processor for PingPong {
+    type Handle = DelayHandle;
+    fn buffers_count() -> usize { 7 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            AUDIO_IN,
+            TIME,
+            FEEDBACK,
+            MIX,
+            TEMPO,
+            PortDescriptor::output("left"),
+            PortDescriptor::output("right"),
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let audio_in = get_input(runtime, handle.buffer_ids_start);
+        let controls = Controls::read(runtime, handle);
+        let mut left_out = get_output(runtime, handle.buffer_ids_start + 5);
+        let mut right_out = get_output(runtime, handle.buffer_ids_start + 6);
+        let mut state = get_state::<PingPongState, E>(runtime, handle.slot_ids_start);
+        let PingPongState { settings, left, right } = &mut *state;
+        let sample_rate = runtime.sample_rate();
+
+        for i in 0..left_out.len() {
+            let (delay, feedback, mix) = controls.at(i, settings, sample_rate);
+            let dry = value_at(*audio_in, i, 0.0);
+            let (wet_left, wet_right) = (left.read(delay), right.read(delay));
+            left.write(dry + feedback * wet_right);
+            right.write(feedback * wet_left);
+            left_out[i] = dry * (1.0 - mix) + wet_left * mix;
+            right_out[i] = dry * (1.0 - mix) + wet_right * mix;
+        }
+    }
+    fn prepare<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle, config: AudioConfig) {
+        let mut state = get_state::<PingPongState, E>(runtime, handle.slot_ids_start);
+        state.left = state.settings.line(config.sample_rate);
+        state.right = state.settings.line(config.sample_rate);
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let mut state = get_state::<PingPongState, E>(runtime, handle.slot_ids_start);
+        state.left.clear();
+        state.right.clear();
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        // sized for real in prepare, once the sample rate is known
+        vec![Box::new(UnsafeCell::new(PingPongState {
+            settings: self.settings,
+            left: DelayLine::new(0, self.settings.interpolation),
+            right: DelayLine::new(0, self.settings.interpolation),
+        }))]
+    }
+    fn get_handle() -> DelayHandle { DelayHandle }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::ProcessorTest;
+    use std::f32::consts::TAU;
+
+    // At 1kHz, so a millisecond is a sample
+    fn delay(delay: Delay) -> ProcessorTest<Delay> {
+        ProcessorTest::new(delay).sample_rate(1000.0).parameter("mix", 1.0)
+    }
+
+    fn peaks(samples: &[f32]) -> Vec<(usize, f32)> {
+        samples.iter().copied().enumerate().filter(|(_, sample)| sample.abs() > 1e-6).collect()
+    }
+
+    #[test]
+    fn test_whole_sample_delays() {
+        for interpolation in [Interpolation::Linear, Interpolation::Lagrange, Interpolation::Allpass] {
+            let capture = delay(Delay::new(0.1).interpolation(interpolation))
+                .parameter("time", 0.01)
+                .input("audio_in", &[1.0])
+                .run(1);
+            assert_eq!(peaks(capture.port("audio_out").unwrap()), vec![(10, 1.0)], "{:?}", interpolation);
+        }
+    }
+
+    #[test]
+    fn test_fractional_delays() {
+        let capture = delay(Delay::new(0.1))
+            .parameter("time", 0.0105)
+            .input("audio_in", &[1.0])
+            .run(1);
+        assert_eq!(peaks(capture.port("audio_out").unwrap()), vec![(10, 0.5), (11, 0.5)]);
+
+        // a 1kHz sine at 48kHz comes out shifted by 10.3 samples
+        let sine: Vec<f32> = (0..1024).map(|n| (TAU * n as f32 / 48.0).sin()).collect();
+        for (interpolation, tolerance) in [(Interpolation::Linear, 0.01), (Interpolation::Lagrange, 0.001), (Interpolation::Allpass, 0.005)] {
+            let capture = ProcessorTest::new(Delay::new(0.01).interpolation(interpolation))
+                .parameter("time", 10.3 / 48_000.0)
+                .parameter("mix", 1.0)
+                .input("audio_in", &sine)
+                .run(16);
+            let out = capture.port("audio_out").unwrap();
+            for (n, out) in out.iter().enumerate().skip(512) {
+                let expected = (TAU * (n as f32 - 10.3) / 48.0).sin();
+                assert!((out - expected).abs() < tolerance, "{:?} at {}: {} != {}", interpolation, n, out, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_feedback() {
+        let capture = delay(Delay::new(0.1))
+            .parameter("time", 0.02)
+            .parameter("feedback", 0.5)
+            .input("audio_in", &[1.0])
+            .run(1);
+        assert_eq!(peaks(capture.port("audio_out").unwrap()), vec![(20, 1.0), (40, 0.5), (60, 0.25)]);
+
+        // half mixed, the dry signal's there too
+        let capture = delay(Delay::new(0.1))
+            .parameter("time", 0.02)
+            .parameter("mix", 0.5)
+            .input("audio_in", &[1.0])
+            .run(1);
+        assert_eq!(peaks(capture.port("audio_out").unwrap()), vec![(0, 0.5), (20, 0.5)]);
+    }
+
+    #[test]
+    fn test_ping_pong() {
+        let capture = ProcessorTest::new(PingPong::new(0.1))
+            .sample_rate(1000.0)
+            .parameter("time", 0.01)
+            .parameter("feedback", 0.5)
+            .parameter("mix", 1.0)
+            .input("audio_in", &[1.0])
+            .run(1);
+        assert_eq!(peaks(capture.port("left").unwrap()), vec![(10, 1.0), (30, 0.25), (50, 0.0625)]);
+        assert_eq!(peaks(capture.port("right").unwrap()), vec![(20, 0.5), (40, 0.125), (60, 0.03125)]);
+    }
+
+    #[test]
+    fn test_tempo_sync() {
+        // an eighth note at 150BPM is 200ms
+        let capture = delay(Delay::new(1.0).tempo_sync(true))
+            .parameter("time", 0.5)
+            .parameter("tempo", 150.0)
+            .input("audio_in", &[1.0])
+            .run(4);
+        assert_eq!(peaks(capture.port("audio_out").unwrap()), vec![(200, 1.0)]);
+    }
+
+    #[test]
+    fn test_modulated_time() {
+        // times past the maximum are held to it
+        let capture = delay(Delay::new(0.05))
+            .parameter("time", 5.0)
+            .input("audio_in", &[1.0])
+            .run(1);
+        assert_eq!(peaks(capture.port("audio_out").unwrap()), vec![(50, 1.0)]);
+
+        // sweeping the time at audio rate, as a flanger would
+        let sine: Vec<f32> = (0..4096).map(|n| (TAU * n as f32 / 48.0).sin()).collect();
+        let sweep: Vec<f32> = (0..4096).map(|n| 0.002 + 0.0019 * (n as f32 * 0.01).sin()).collect();
+        for interpolation in [Interpolation::Linear, Interpolation::Lagrange, Interpolation::Allpass] {
+            let capture = ProcessorTest::new(Delay::new(0.005).interpolation(interpolation))
+                .parameter("feedback", 0.9)
+                .input("audio_in", &sine)
+                .input("time", &sweep)
+                .run(64);
+            assert!(capture.port("audio_out").unwrap().iter().all(|sample| sample.is_finite() && sample.abs() < 20.0));
+        }
+    }
+}
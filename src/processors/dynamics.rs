@@ -0,0 +1,774 @@
+// Dynamics. Each one measures the peak level of its sidechain input, or of
+// audio_in while the sidechain isn't routed, works out a gain in dB from
+// it, and smooths that gain with its attack and release times
+//
+// They all start with the same three inputs:
+//   audio_in   the signal whose gain changes
+//   sidechain  what's measured instead of audio_in, e.g. a kick for ducking
+//   threshold  in dB
+//
+// and end with audio_out and gain_reduction, the dB taken off, for meters.
+// Lookahead delays audio_in behind the measuring so gain changes land
+// before what caused them, and is reported as latency
+//
+//     let (runtime, router) = Builder::<()>::new()
+//         .add_processor(Compressor::new(), "duck")
+//         .build();
+//     router.route(SawOsc::audio_out("pad"), Compressor::audio_in("duck"))?;
+//     router.route(Sampler::left_out("kick"), Compressor::sidechain_in("duck"))?;
+
+use std::collections::VecDeque;
+use crate::core::processor::*;
+use super::delay::{DelayLine, Interpolation};
+use super::value_at;
+
+const AUDIO_IN: PortDescriptor = PortDescriptor::input("audio_in");
+const SIDECHAIN: PortDescriptor = PortDescriptor::input("sidechain")
+    .description("Measured instead of audio_in while it's routed");
+const AUDIO_OUT: PortDescriptor = PortDescriptor::output("audio_out");
+const GAIN_REDUCTION: PortDescriptor = PortDescriptor::output("gain_reduction")
+    .kind(PortKind::Control)
+    .unit("dB")
+    .description("How much the gain is turned down");
+
+const fn threshold(default: f32) -> PortDescriptor {
+    PortDescriptor::input("threshold")
+        .kind(PortKind::Control)
+        .default_value(default)
+        .range(-80.0, 0.0)
+        .unit("dB")
+}
+
+const fn ratio(default: f32) -> PortDescriptor {
+    PortDescriptor::input("ratio")
+        .kind(PortKind::Control)
+        .default_value(default)
+        .range(1.0, 20.0)
+}
+
+const KNEE: PortDescriptor = PortDescriptor::input("knee")
+    .kind(PortKind::Control)
+    .default_value(6.0)
+    .range(0.0, 24.0)
+    .unit("dB")
+    .description("Width of the bend around the threshold");
+
+const fn seconds(name: &'static str, default: f32) -> PortDescriptor {
+    PortDescriptor::input(name)
+        .kind(PortKind::Control)
+        .default_value(default)
+        .range(0.0, 2.0)
+        .unit("s")
+}
+
+const RANGE: PortDescriptor = PortDescriptor::input("range")
+    .kind(PortKind::Control)
+    .default_value(-80.0)
+    .range(-96.0, 0.0)
+    .unit("dB")
+    .description("The most the gain is turned down");
+
+// Port handles every dynamics processor has. audio_out and gain_reduction
+// are always the last two ports
+pub trait Dynamics: Processor {
+    fn audio_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 0, TypeId::of::<Input>(), TypeId::of::<Self>())
+    }
+
+    fn sidechain_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 1, TypeId::of::<Input>(), TypeId::of::<Self>())
+    }
+
+    fn threshold_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 2, TypeId::of::<Input>(), TypeId::of::<Self>())
+    }
+
+    fn audio_out(instance_name: &'static str) -> PortHandle<Output<'static>> {
+        PortHandle::new(instance_name, Self::ports().len() - 2, TypeId::of::<Output>(), TypeId::of::<Self>())
+    }
+
+    fn gain_reduction_out(instance_name: &'static str) -> PortHandle<Output<'static>> {
+        PortHandle::new(instance_name, Self::ports().len() - 1, TypeId::of::<Output>(), TypeId::of::<Self>())
+    }
+}
+
+pub struct DynamicsHandle;
+impl ProcessorHandle for DynamicsHandle {}
+
+fn level_db(sample: f32) -> f32 {
+    20.0 * sample.abs().max(1e-6).log10()
+}
+
+fn gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+// For one-pole smoothing that gets 63% of the way in seconds
+fn coefficient(seconds: f32, sample_rate: f32) -> f32 {
+    if seconds > 0.0 { (-1.0 / (seconds * sample_rate)).exp() } else { 0.0 }
+}
+
+// Moves current towards target, with one coefficient for going down and
+// another for going up
+fn smooth(current: f32, target: f32, down: f32, up: f32) -> f32 {
+    let coefficient = if target < current { down } else { up };
+    target + coefficient * (current - target)
+}
+
+// The gain change, never positive, that brings a level over the threshold
+// down by the ratio, bending into it across the knee
+fn compression(level: f32, threshold: f32, ratio: f32, knee: f32) -> f32 {
+    let over = level - threshold;
+    let slope = 1.0 - 1.0 / ratio.max(1.0);
+    if 2.0 * over <= -knee {
+        0.0
+    } else if 2.0 * over < knee {
+        -slope * (over + knee / 2.0).powi(2) / (2.0 * knee)
+    } else {
+        -slope * over
+    }
+}
+
+// The gain change that pushes a level under the threshold further down by
+// the ratio
+fn expansion(level: f32, threshold: f32, ratio: f32, knee: f32) -> f32 {
+    let under = level - threshold;
+    let slope = ratio.max(1.0) - 1.0;
+    if 2.0 * under >= knee {
+        0.0
+    } else if 2.0 * under > -knee {
+        -slope * (under - knee / 2.0).powi(2) / (2.0 * knee)
+    } else {
+        slope * under
+    }
+}
+
+// Holds audio back by the lookahead
+struct Lookahead {
+    line: Option<DelayLine>,
+    samples: usize,
+}
+
+impl Lookahead {
+    fn new(samples: usize) -> Self {
+        Self {
+            line: (samples > 0).then(|| DelayLine::new(samples + 1, Interpolation::Linear)),
+            samples,
+        }
+    }
+
+    fn delay(&mut self, sample: f32) -> f32 {
+        match &mut self.line {
+            Some(line) => {
+                line.write(sample);
+                line.read(self.samples as f32 + 1.0)
+            },
+            None => sample,
+        }
+    }
+
+    fn clear(&mut self) {
+        if let Some(line) = &mut self.line {
+            line.clear();
+        }
+    }
+}
+
+// What compressors, expanders and noise gates keep between samples
+struct DynamicsState {
+    // the smoothed gain change, in dB
+    gain: f32,
+    lookahead: Lookahead,
+    // noise gates only, samples left before closing. Negative once closed
+    hold: f32,
+}
+
+impl DynamicsState {
+    fn new(lookahead: usize) -> Self {
+        Self { gain: 0.0, lookahead: Lookahead::new(lookahead), hold: -1.0 }
+    }
+
+    fn clear(&mut self) {
+        self.gain = 0.0;
+        self.lookahead.clear();
+        self.hold = -1.0;
+    }
+}
+
+// Runs the measuring, smoothing and gain every processor but the limiter
+// shares. target gives the gain change for a level at sample i, and the
+// smoothing coefficients for going down and up. audio_out is at out_idx,
+// with gain_reduction after it
+fn apply<E: Clone + Copy>(
+    runtime: &Runtime<E>,
+    handle: ContextHandle,
+    out_idx: usize,
+    state: &mut DynamicsState,
+    mut target: impl FnMut(usize, f32, &mut DynamicsState) -> (f32, f32, f32),
+    makeup: impl Fn(usize) -> f32,
+) {
+    let audio_in = get_input(runtime, handle.buffer_ids_start);
+    let sidechain = get_input(runtime, handle.buffer_ids_start + 1);
+    let mut audio_out = get_output(runtime, handle.buffer_ids_start + out_idx);
+    let mut gain_reduction = get_output(runtime, handle.buffer_ids_start + out_idx + 1);
+
+    for i in 0..audio_out.len() {
+        let audio = value_at(*audio_in, i, 0.0);
+        let level = level_db(sidechain.map_or(audio, |sidechain| sidechain[i]));
+        let (change, down, up) = target(i, level, state);
+        state.gain = smooth(state.gain, change, down, up);
+        audio_out[i] = state.lookahead.delay(audio) * gain(state.gain + makeup(i));
+        gain_reduction[i] = -state.gain;
+    }
+}
+
+// A downward compressor
+pub struct Compressor {
+    lookahead: usize,
+}
+
+impl Default for Compressor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compressor {
+    pub fn new() -> Self {
+        Self { lookahead: 0 }
+    }
+
+    // Delays audio_in by this many samples behind the sidechain
+    pub fn lookahead(mut self, samples: usize) -> Self {
+        self.lookahead = samples;
+        self
+    }
+
+    pub fn ratio_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 3, TypeId::of::<Input>(), TypeId::of::<Compressor>())
+    }
+
+    pub fn knee_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 4, TypeId::of::<Input>(), TypeId::of::<Compressor>())
+    }
+
+    pub fn attack_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 5, TypeId::of::<Input>(), TypeId::of::<Compressor>())
+    }
+
+    pub fn release_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 6, TypeId::of::<Input>(), TypeId::of::<Compressor>())
+    }
+
+    pub fn makeup_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 7, TypeId::of::<Input>(), TypeId::of::<Compressor>())
+    }
+}
+
+impl Dynamics for Compressor {}
+
+impl Processor for Compressor {
+    type Handle = DynamicsHandle;
+    fn buffers_count() -> usize { 10 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            AUDIO_IN,
+            SIDECHAIN,
+            threshold(-20.0),
+            ratio(4.0),
+            KNEE,
+            seconds("attack", 0.01),
+            seconds("release", 0.1),
+            PortDescriptor::input("makeup")
+                .kind(PortKind::Control)
+                .range(0.0, 24.0)
+                .unit("dB"),
+            AUDIO_OUT,
+            GAIN_REDUCTION,
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let threshold = get_input(runtime, handle.buffer_ids_start + 2);
+        let ratio = get_input(runtime, handle.buffer_ids_start + 3);
+        let knee = get_input(runtime, handle.buffer_ids_start + 4);
+        let attack = get_input(runtime, handle.buffer_ids_start + 5);
+        let release = get_input(runtime, handle.buffer_ids_start + 6);
+        let makeup = get_input(runtime, handle.buffer_ids_start + 7);
+        let mut state = get_state::<DynamicsState, E>(runtime, handle.slot_ids_start);
+        let sample_rate = runtime.sample_rate();
+
+        apply(runtime, handle, 8, &mut state, |i, level, _| (
+            compression(level, value_at(*threshold, i, -20.0), value_at(*ratio, i, 4.0), value_at(*knee, i, 6.0)),
+            coefficient(value_at(*attack, i, 0.01), sample_rate),
+            coefficient(value_at(*release, i, 0.1), sample_rate),
+        ), |i| value_at(*makeup, i, 0.0));
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        get_state::<DynamicsState, E>(runtime, handle.slot_ids_start).clear();
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        vec![Box::new(UnsafeCell::new(DynamicsState::new(self.lookahead)))]
+    }
+    fn get_handle() -> DynamicsHandle { DynamicsHandle }
+    fn latency(&self) -> usize { self.lookahead }
+}
+
+// A downward expander, which turns what's under the threshold down further
+pub struct Expander {
+    lookahead: usize,
+}
+
+impl Default for Expander {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Expander {
+    pub fn new() -> Self {
+        Self { lookahead: 0 }
+    }
+
+    // Delays audio_in by this many samples behind the sidechain
+    pub fn lookahead(mut self, samples: usize) -> Self {
+        self.lookahead = samples;
+        self
+    }
+
+    pub fn ratio_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 3, TypeId::of::<Input>(), TypeId::of::<Expander>())
+    }
+
+    pub fn knee_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 4, TypeId::of::<Input>(), TypeId::of::<Expander>())
+    }
+
+    pub fn attack_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 5, TypeId::of::<Input>(), TypeId::of::<Expander>())
+    }
+
+    pub fn release_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 6, TypeId::of::<Input>(), TypeId::of::<Expander>())
+    }
+
+    pub fn range_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 7, TypeId::of::<Input>(), TypeId::of::<Expander>())
+    }
+}
+
+impl Dynamics for Expander {}
+
+impl Processor for Expander {
+    type Handle = DynamicsHandle;
+    fn buffers_count() -> usize { 10 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            AUDIO_IN,
+            SIDECHAIN,
+            threshold(-40.0),
+            ratio(2.0),
+            KNEE,
+            seconds("attack", 0.001),
+            seconds("release", 0.1),
+            RANGE,
+            AUDIO_OUT,
+            GAIN_REDUCTION,
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let threshold = get_input(runtime, handle.buffer_ids_start + 2);
+        let ratio = get_input(runtime, handle.buffer_ids_start + 3);
+        let knee = get_input(runtime, handle.buffer_ids_start + 4);
+        let attack = get_input(runtime, handle.buffer_ids_start + 5);
+        let release = get_input(runtime, handle.buffer_ids_start + 6);
+        let range = get_input(runtime, handle.buffer_ids_start + 7);
+        let mut state = get_state::<DynamicsState, E>(runtime, handle.slot_ids_start);
+        let sample_rate = runtime.sample_rate();
+
+        // attack opens it back up, release turns it down
+        apply(runtime, handle, 8, &mut state, |i, level, _| (
+            expansion(level, value_at(*threshold, i, -40.0), value_at(*ratio, i, 2.0), value_at(*knee, i, 6.0))
+                .max(value_at(*range, i, -80.0)),
+            coefficient(value_at(*release, i, 0.1), sample_rate),
+            coefficient(value_at(*attack, i, 0.001), sample_rate),
+        ), |_| 0.0);
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        get_state::<DynamicsState, E>(runtime, handle.slot_ids_start).clear();
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        vec![Box::new(UnsafeCell::new(DynamicsState::new(self.lookahead)))]
+    }
+    fn get_handle() -> DynamicsHandle { DynamicsHandle }
+    fn latency(&self) -> usize { self.lookahead }
+}
+
+// A noise gate. Open while the level's over the threshold and for the hold
+// time after, turned down by range otherwise
+pub struct NoiseGate {
+    lookahead: usize,
+}
+
+impl Default for NoiseGate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NoiseGate {
+    pub fn new() -> Self {
+        Self { lookahead: 0 }
+    }
+
+    // Delays audio_in by this many samples behind the sidechain, so the
+    // gate is open before a drum hit lands
+    pub fn lookahead(mut self, samples: usize) -> Self {
+        self.lookahead = samples;
+        self
+    }
+
+    pub fn attack_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 3, TypeId::of::<Input>(), TypeId::of::<NoiseGate>())
+    }
+
+    pub fn hold_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 4, TypeId::of::<Input>(), TypeId::of::<NoiseGate>())
+    }
+
+    pub fn release_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 5, TypeId::of::<Input>(), TypeId::of::<NoiseGate>())
+    }
+
+    pub fn range_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 6, TypeId::of::<Input>(), TypeId::of::<NoiseGate>())
+    }
+}
+
+impl Dynamics for NoiseGate {}
+
+impl Processor for NoiseGate {
+    type Handle = DynamicsHandle;
+    fn buffers_count() -> usize { 9 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            AUDIO_IN,
+            SIDECHAIN,
+            threshold(-40.0),
+            seconds("attack", 0.0005),
+            seconds("hold", 0.02),
+            seconds("release", 0.05),
+            RANGE,
+            AUDIO_OUT,
+            GAIN_REDUCTION,
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let threshold = get_input(runtime, handle.buffer_ids_start + 2);
+        let attack = get_input(runtime, handle.buffer_ids_start + 3);
+        let hold = get_input(runtime, handle.buffer_ids_start + 4);
+        let release = get_input(runtime, handle.buffer_ids_start + 5);
+        let range = get_input(runtime, handle.buffer_ids_start + 6);
+        let mut state = get_state::<DynamicsState, E>(runtime, handle.slot_ids_start);
+        let sample_rate = runtime.sample_rate();
+
+        apply(runtime, handle, 7, &mut state, |i, level, state| {
+            if level >= value_at(*threshold, i, -40.0) {
+                state.hold = value_at(*hold, i, 0.02) * sample_rate;
+            } else {
+                state.hold -= 1.0;
+            }
+            let target = if state.hold >= 0.0 { 0.0 } else { value_at(*range, i, -80.0) };
+            (target, coefficient(value_at(*release, i, 0.05), sample_rate), coefficient(value_at(*attack, i, 0.0005), sample_rate))
+        }, |_| 0.0);
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        get_state::<DynamicsState, E>(runtime, handle.slot_ids_start).clear();
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        vec![Box::new(UnsafeCell::new(DynamicsState::new(self.lookahead)))]
+    }
+    fn get_handle() -> DynamicsHandle { DynamicsHandle }
+    fn latency(&self) -> usize { self.lookahead }
+}
+
+// A brickwall limiter. Without lookahead it clamps down on the sample a
+// peak arrives; with it, the gain ramps down across the lookahead so no
+// sample comes out over the threshold
+pub struct Limiter {
+    lookahead: usize,
+}
+
+impl Default for Limiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Limiter {
+    pub fn new() -> Self {
+        Self { lookahead: 0 }
+    }
+
+    // Delays audio_in by this many samples, the length of the ramp down
+    pub fn lookahead(mut self, samples: usize) -> Self {
+        self.lookahead = samples;
+        self
+    }
+
+    pub fn release_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 3, TypeId::of::<Input>(), TypeId::of::<Limiter>())
+    }
+}
+
+impl Dynamics for Limiter {}
+
+struct LimiterState {
+    lookahead: Lookahead,
+    // the gain each of the last lookahead + 1 samples needs, in increasing
+    // order from the front, with the sample it was needed at
+    needed: VecDeque<(usize, f32)>,
+    sample: usize,
+    // the lowest needed gain, released back up slowly
+    released: f32,
+    // the last lookahead released gains and their sum, averaged into a ramp
+    ramp: Vec<f32>,
+    ramp_position: usize,
+    ramp_sum: f64,
+}
+
+impl LimiterState {
+    fn new(lookahead: usize) -> Self {
+        Self {
+            lookahead: Lookahead::new(lookahead),
+            needed: VecDeque::with_capacity(lookahead + 1),
+            sample: 0,
+            released: 0.0,
+            ramp: vec![0.0; lookahead],
+            ramp_position: 0,
+            ramp_sum: 0.0,
+        }
+    }
+
+    // The gain for a sample that needs at most needed, lookahead samples
+    // early
+    fn next(&mut self, needed: f32, release: f32) -> f32 {
+        let window = self.ramp.len() + 1;
+        while self.needed.back().is_some_and(|(_, gain)| *gain >= needed) {
+            self.needed.pop_back();
+        }
+        self.needed.push_back((self.sample, needed));
+        while self.needed.front().is_some_and(|(sample, _)| sample + window <= self.sample) {
+            self.needed.pop_front();
+        }
+        self.sample += 1;
+
+        let lowest = self.needed.front().map_or(0.0, |(_, gain)| *gain);
+        self.released = smooth(self.released, lowest, 0.0, release);
+        if self.ramp.is_empty() {
+            return self.released;
+        }
+        self.ramp_sum += (self.released - self.ramp[self.ramp_position]) as f64;
+        self.ramp[self.ramp_position] = self.released;
+        self.ramp_position = (self.ramp_position + 1) % self.ramp.len();
+        (self.ramp_sum / self.ramp.len() as f64) as f32
+    }
+
+    fn clear(&mut self) {
+        self.lookahead.clear();
+        self.needed.clear();
+        self.released = 0.0;
+        self.ramp.fill(0.0);
+        self.ramp_sum = 0.0;
+    }
+}
+
+impl Processor for Limiter {
+    type Handle = DynamicsHandle;
+    fn buffers_count() -> usize { 6 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            AUDIO_IN,
+            SIDECHAIN,
+            threshold(-1.0),
+            seconds("release", 0.05),
+            AUDIO_OUT,
+            GAIN_REDUCTION,
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let audio_in = get_input(runtime, handle.buffer_ids_start);
+        let sidechain = get_input(runtime, handle.buffer_ids_start + 1);
+        let threshold = get_input(runtime, handle.buffer_ids_start + 2);
+        let release = get_input(runtime, handle.buffer_ids_start + 3);
+        let mut audio_out = get_output(runtime, handle.buffer_ids_start + 4);
+        let mut gain_reduction = get_output(runtime, handle.buffer_ids_start + 5);
+        let mut state = get_state::<LimiterState, E>(runtime, handle.slot_ids_start);
+        let sample_rate = runtime.sample_rate();
+
+        for i in 0..audio_out.len() {
+            let audio = value_at(*audio_in, i, 0.0);
+            let level = level_db(sidechain.map_or(audio, |sidechain| sidechain[i]));
+            let needed = (value_at(*threshold, i, -1.0) - level).min(0.0);
+            let change = state.next(needed, coefficient(value_at(*release, i, 0.05), sample_rate));
+            audio_out[i] = state.lookahead.delay(audio) * gain(change);
+            gain_reduction[i] = -change;
+        }
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        get_state::<LimiterState, E>(runtime, handle.slot_ids_start).clear();
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        vec![Box::new(UnsafeCell::new(LimiterState::new(self.lookahead)))]
+    }
+    fn get_handle() -> DynamicsHandle { DynamicsHandle }
+    fn latency(&self) -> usize { self.lookahead }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Builder;
+    use crate::testing::ProcessorTest;
+
+    fn db(sample: f32) -> f32 {
+        20.0 * sample.abs().log10()
+    }
+
+    #[test]
+    fn test_compressor_curve() {
+        // 0dB in, 20dB over a -20dB threshold at 4:1 comes out 15dB down
+        let capture = ProcessorTest::new(Compressor::new())
+            .parameter("knee", 0.0)
+            .parameter("attack", 0.0)
+            .input("audio_in", &[1.0; 64])
+            .run(1);
+        assert!((db(capture.port("audio_out").unwrap()[63]) + 15.0).abs() < 1e-3);
+        assert!((capture.port("gain_reduction").unwrap()[63] - 15.0).abs() < 1e-3);
+
+        // at the threshold the knee's already bending
+        let capture = ProcessorTest::new(Compressor::new())
+            .parameter("threshold", 0.0)
+            .parameter("attack", 0.0)
+            .parameter("makeup", 2.0)
+            .input("audio_in", &[1.0; 64])
+            .run(1);
+        assert!((capture.port("gain_reduction").unwrap()[63] - 0.5625).abs() < 1e-4);
+        assert!((db(capture.port("audio_out").unwrap()[63]) - (2.0 - 0.5625)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_attack_and_release() {
+        // 480 samples of 0dB, then silence, at 48kHz
+        let mut step = vec![1.0; 480];
+        step.extend_from_slice(&[0.0; 480]);
+        let capture = ProcessorTest::new(Compressor::new())
+            .parameter("knee", 0.0)
+            .parameter("attack", 0.001)
+            .parameter("release", 0.002)
+            .input("audio_in", &step)
+            .run(15);
+        let reduction = capture.port("gain_reduction").unwrap();
+        // 63% of the way to 15dB after the attack time, and back after the release
+        assert!((reduction[47] - 15.0 * (1.0 - (-1.0f32).exp())).abs() < 0.05, "{}", reduction[47]);
+        assert!((reduction[480 + 95] - 15.0 * (-1.0f32).exp()).abs() < 0.5, "{}", reduction[575]);
+    }
+
+    #[test]
+    fn test_sidechain_ducking() {
+        let mut kick = vec![0.0; 64];
+        kick.extend_from_slice(&[1.0; 64]);
+        let capture = ProcessorTest::new(Compressor::new())
+            .parameter("attack", 0.0)
+            .parameter("ratio", 20.0)
+            .parameter("knee", 0.0)
+            .input("audio_in", &[0.5; 128])
+            .input("sidechain", &kick)
+            .run(2);
+        let out = capture.port("audio_out").unwrap();
+        // untouched while the sidechain's quiet, ducked once it's loud, even
+        // though audio_in is under the threshold of its own accord
+        assert_eq!(out[63], 0.5);
+        assert!(db(out[127] / 0.5) < -18.0);
+    }
+
+    #[test]
+    fn test_expander_and_gate() {
+        // 20dB under the threshold at 2:1 is turned down another 20dB
+        let capture = ProcessorTest::new(Expander::new())
+            .parameter("knee", 0.0)
+            .parameter("release", 0.0)
+            .input("audio_in", &[0.001; 64])
+            .run(1);
+        assert!((capture.port("gain_reduction").unwrap()[63] - 20.0).abs() < 1e-3);
+
+        // but no further than range
+        let capture = ProcessorTest::new(Expander::new())
+            .parameter("ratio", 10.0)
+            .parameter("release", 0.0)
+            .parameter("range", -30.0)
+            .input("audio_in", &[0.001; 64])
+            .run(1);
+        assert!((capture.port("gain_reduction").unwrap()[63] - 30.0).abs() < 1e-3);
+
+        // a gate opens on a hit, holds, then closes
+        let mut hit = vec![0.0; 64];
+        hit[8] = 1.0;
+        let capture = ProcessorTest::new(NoiseGate::new())
+            .sample_rate(1000.0)
+            .parameter("attack", 0.0)
+            .parameter("release", 0.0)
+            .parameter("hold", 0.01)
+            .input("audio_in", &hit)
+            .input("sidechain", &hit)
+            .run(1);
+        let reduction = capture.port("gain_reduction").unwrap();
+        assert_eq!(reduction[7], 80.0);
+        assert_eq!(capture.port("audio_out").unwrap()[8], 1.0);
+        assert!(reduction[8..19].iter().all(|reduction| *reduction == 0.0));
+        assert_eq!(reduction[19], 80.0);
+    }
+
+    #[test]
+    fn test_limiter_lookahead() {
+        // noise under the threshold, with peaks well over it after 300 samples
+        let peaks: Vec<f32> = (0..1024u32)
+            .map(|n| ((n.wrapping_mul(2_654_435_761) >> 16) as f32 / 65_536.0 - 0.5) * if n >= 300 && n % 97 == 0 { 8.0 } else { 0.5 })
+            .collect();
+        let capture = ProcessorTest::new(Limiter::new().lookahead(32))
+            .parameter("threshold", -6.0)
+            .input("audio_in", &peaks)
+            .run(16);
+        let out = capture.port("audio_out").unwrap();
+        let ceiling = gain(-6.0) * 1.0001;
+        assert!(out.iter().all(|sample| sample.abs() <= ceiling));
+        // the audio comes out delayed by the lookahead, untouched while it's
+        // quiet enough
+        assert_eq!(out[..32], [0.0; 32]);
+        assert_eq!(out[32..300], peaks[..268]);
+
+        // without it, only the peaks' samples are caught
+        let capture = ProcessorTest::new(Limiter::new())
+            .parameter("threshold", -6.0)
+            .input("audio_in", &peaks)
+            .run(16);
+        assert!(capture.port("audio_out").unwrap().iter().all(|sample| sample.abs() <= ceiling));
+    }
+
+    #[test]
+    fn test_lookahead_latency() {
+        // lookahead is reported, so the Clerk can line parallel paths up with it
+        let (_runtime, router) = Builder::<()>::new()
+            .add_processor(Compressor::new().lookahead(16), "compressor")
+            .build();
+        router.route(Compressor::audio_out("compressor"), output()).unwrap();
+        assert_eq!(router.latency(), 16);
+    }
+}
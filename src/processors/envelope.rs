@@ -0,0 +1,798 @@
+// Envelope generators. Each is a list of stages that move the output to a
+// level over a time, with one stage optionally held while the gate is open
+//
+// They all start with the same two inputs:
+//   gate      triggers when it rises past 0.5 and releases when it falls
+//   velocity  how hard gate triggers are, 1 while unrouted
+//
+// and can be triggered by the runtime's events instead, sample-accurately,
+// through a GateEvent impl
+//
+//     let (runtime, router) = Builder::<Gate>::new()
+//         .add_processor(Adsr::new().curve(Curve::Exponential), "amp")
+//         .add_processor(MultiStage::new(50.0)
+//             .stage(400.0, 0.0, Curve::Linear)
+//             .stage(50.0, 0.08, Curve::Exponential), "pitch")
+//         .build();
+//     router.route(MultiStage::envelope_out("pitch"), SineOsc::frequency_in("kick"))?;
+//     router.send_event(Gate::On { velocity: 0.8 });
+
+use crate::core::processor::*;
+use super::{events_of, value_at};
+
+const GATE: PortDescriptor = PortDescriptor::input("gate")
+    .kind(PortKind::Control)
+    .range(0.0, 1.0)
+    .description("Triggers when it rises past 0.5, and releases when it falls back");
+const VELOCITY: PortDescriptor = PortDescriptor::input("velocity")
+    .kind(PortKind::Control)
+    .default_value(1.0)
+    .range(0.0, 1.0)
+    .description("Velocity of the gate's triggers");
+const ENVELOPE_OUT: PortDescriptor = PortDescriptor::output("envelope");
+
+const fn seconds(name: &'static str, default: f32) -> PortDescriptor {
+    PortDescriptor::input(name)
+        .kind(PortKind::Control)
+        .default_value(default)
+        .range(0.0, 10.0)
+        .unit("s")
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Gate {
+    // velocity from 0 to 1
+    On { velocity: f32 },
+    Off,
+}
+
+// Maps a runtime's events to gates, for envelopes set up with events()
+pub trait GateEvent: Copy + 'static {
+    fn gate(&self) -> Option<Gate>;
+}
+
+impl GateEvent for Gate {
+    fn gate(&self) -> Option<Gate> {
+        Some(*self)
+    }
+}
+
+fn map_event<Ev: GateEvent>(event: &dyn Any) -> Option<Gate> {
+    event.downcast_ref::<Ev>().and_then(Ev::gate)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Curve {
+    #[default]
+    Linear,
+    // fast at first and slowing towards the level, like a capacitor
+    // charging, but still there on time
+    Exponential,
+}
+
+impl Curve {
+    fn at(self, from: f32, to: f32, progress: f32) -> f32 {
+        const STEEPNESS: f32 = 5.0;
+        let shaped = match self {
+            Curve::Linear => progress,
+            Curve::Exponential => (1.0 - (-STEEPNESS * progress).exp()) / (1.0 - (-STEEPNESS).exp()),
+        };
+        from + (to - from) * shaped
+    }
+}
+
+// Moves the output from wherever it is to level
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stage {
+    pub level: f32,
+    pub seconds: f32,
+    pub curve: Curve,
+}
+
+// Port handles every envelope has. envelope_out is always the last port
+pub trait Envelope: Processor {
+    fn gate_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 0, TypeId::of::<Input>(), TypeId::of::<Self>())
+    }
+
+    fn velocity_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 1, TypeId::of::<Input>(), TypeId::of::<Self>())
+    }
+
+    fn envelope_out(instance_name: &'static str) -> PortHandle<Output<'static>> {
+        PortHandle::new(instance_name, Self::ports().len() - 1, TypeId::of::<Output>(), TypeId::of::<Self>())
+    }
+}
+
+pub struct EnvelopeHandle;
+impl ProcessorHandle for EnvelopeHandle {}
+
+// How an envelope is triggered, the same for all of them
+#[derive(Clone, Copy)]
+struct Triggering {
+    map_event: fn(&dyn Any) -> Option<Gate>,
+    legato: bool,
+    velocity_depth: f32,
+}
+
+impl Default for Triggering {
+    fn default() -> Self {
+        Self {
+            map_event: map_event::<Gate>,
+            legato: false,
+            velocity_depth: 1.0,
+        }
+    }
+}
+
+struct EnvelopeState {
+    triggering: Triggering,
+    // where it rests before the first trigger
+    initial: f32,
+    // the stage running, None once past the last
+    stage: Option<usize>,
+    // reached the sustain stage's level, and is waiting for the gate to close
+    sustaining: bool,
+    open: bool,
+    // the level and sample the running stage started from
+    from: f32,
+    elapsed: f32,
+    level: f32,
+    velocity: f32,
+    // the gate port's last value, to find its edges
+    gate: f32,
+}
+
+impl EnvelopeState {
+    fn new(triggering: Triggering, initial: f32) -> Self {
+        Self {
+            triggering,
+            initial,
+            stage: None,
+            sustaining: false,
+            open: false,
+            from: initial,
+            elapsed: 0.0,
+            level: initial,
+            velocity: 1.0,
+            gate: 0.0,
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.triggering, self.initial);
+    }
+
+    // Stages always start from the current level, so nothing jumps
+    fn start(&mut self, stage: usize) {
+        self.stage = Some(stage);
+        self.sustaining = false;
+        self.from = self.level;
+        self.elapsed = 0.0;
+    }
+
+    fn apply(&mut self, gate: Gate, sustain: Option<usize>) {
+        match gate {
+            Gate::On { velocity } => {
+                // legato only retriggers once the last note was let go
+                if self.triggering.legato && self.open && self.stage.is_some() {
+                    return;
+                }
+                self.open = true;
+                self.velocity = velocity;
+                self.start(0);
+            },
+            Gate::Off => {
+                self.open = false;
+                // without a sustain stage the envelope runs to the end anyway
+                if let (Some(sustain), Some(stage)) = (sustain, self.stage) {
+                    if stage <= sustain {
+                        self.start(sustain + 1);
+                    }
+                }
+            },
+        }
+    }
+
+    fn set_gate(&mut self, gate: f32, velocity: f32, sustain: Option<usize>) {
+        let (was_high, high) = (self.gate >= 0.5, gate >= 0.5);
+        if high && !was_high {
+            self.apply(Gate::On { velocity }, sustain);
+        } else if was_high && !high {
+            self.apply(Gate::Off, sustain);
+        }
+        self.gate = gate;
+    }
+
+    // The next sample's level, with stage lengths in samples
+    fn next(&mut self, stages: &[Stage], sustain: Option<usize>, sample_rate: f32) -> f32 {
+        let Some(index) = self.stage else { return self.level };
+        let Some(stage) = stages.get(index) else {
+            self.stage = None;
+            return self.level;
+        };
+        if self.sustaining {
+            // follows the level, for sustain levels that are modulated
+            self.level = stage.level;
+            return self.level;
+        }
+
+        self.elapsed += 1.0;
+        let length = stage.seconds.max(0.0) * sample_rate;
+        let progress = if length > self.elapsed { self.elapsed / length } else { 1.0 };
+        self.level = stage.curve.at(self.from, stage.level, progress);
+        if progress >= 1.0 {
+            if sustain == Some(index) && self.open {
+                self.sustaining = true;
+            } else {
+                self.start(index + 1);
+            }
+        }
+        self.level
+    }
+}
+
+// Runs the triggering every envelope shares, over the stages it has at
+// each sample
+fn run<E: Clone + Copy, S: AsRef<[Stage]>>(
+    runtime: &Runtime<E>,
+    handle: ContextHandle,
+    out_idx: usize,
+    state: &mut EnvelopeState,
+    sustain: Option<usize>,
+    stages: impl Fn(usize) -> S,
+) {
+    let gate = get_input(runtime, handle.buffer_ids_start);
+    let velocity = get_input(runtime, handle.buffer_ids_start + 1);
+    let mut out = get_output(runtime, handle.buffer_ids_start + out_idx);
+    let sample_rate = runtime.sample_rate();
+
+    let map_event = state.triggering.map_event;
+    let mut gates = events_of(runtime)
+        .filter_map(|(offset, event)| Some((offset, map_event(event)?)))
+        .peekable();
+
+    for (i, out) in out.iter_mut().enumerate() {
+        while let Some((_, event)) = gates.next_if(|(offset, _)| *offset <= i) {
+            state.apply(event, sustain);
+        }
+        if let Some(gate) = *gate {
+            state.set_gate(gate[i], velocity.map_or(1.0, |velocity| velocity[i]), sustain);
+        }
+        let level = state.next(stages(i).as_ref(), sustain, sample_rate);
+        let depth = state.triggering.velocity_depth;
+        *out = level * (1.0 - depth + depth * state.velocity);
+    }
+}
+
+// Attack to 1, decay to the sustain level and hold it while the gate is
+// open, then release to 0
+#[derive(Default)]
+pub struct Adsr {
+    triggering: Triggering,
+    attack_curve: Curve,
+    curve: Curve,
+}
+
+impl Adsr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Reacts to the runtime's events through their GateEvent impl, instead
+    // of Gate events
+    pub fn events<Ev: GateEvent>(mut self) -> Self {
+        self.triggering.map_event = map_event::<Ev>;
+        self
+    }
+
+    // Triggers that come while the gate is still open don't restart it
+    pub fn legato(mut self, legato: bool) -> Self {
+        self.triggering.legato = legato;
+        self
+    }
+
+    // How much velocity scales the output, from 0 for not at all to 1, the
+    // default, for entirely
+    pub fn velocity_depth(mut self, depth: f32) -> Self {
+        self.triggering.velocity_depth = depth.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn attack_curve(mut self, curve: Curve) -> Self {
+        self.attack_curve = curve;
+        self
+    }
+
+    // The decay and release curve
+    pub fn curve(mut self, curve: Curve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    pub fn attack_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 2, TypeId::of::<Input>(), TypeId::of::<Adsr>())
+    }
+
+    pub fn decay_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 3, TypeId::of::<Input>(), TypeId::of::<Adsr>())
+    }
+
+    pub fn sustain_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 4, TypeId::of::<Input>(), TypeId::of::<Adsr>())
+    }
+
+    pub fn release_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 5, TypeId::of::<Input>(), TypeId::of::<Adsr>())
+    }
+}
+
+impl Envelope for Adsr {}
+
+struct AdsrState {
+    envelope: EnvelopeState,
+    attack_curve: Curve,
+    curve: Curve,
+}
+
+impl Processor for Adsr {
+    type Handle = EnvelopeHandle;
+    fn buffers_count() -> usize { 7 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            GATE,
+            VELOCITY,
+            seconds("attack", 0.01),
+            seconds("decay", 0.1),
+            PortDescriptor::input("sustain")
+                .kind(PortKind::Control)
+                .default_value(0.7)
+                .range(0.0, 1.0),
+            seconds("release", 0.3),
+            ENVELOPE_OUT,
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let attack = get_input(runtime, handle.buffer_ids_start + 2);
+        let decay = get_input(runtime, handle.buffer_ids_start + 3);
+        let sustain = get_input(runtime, handle.buffer_ids_start + 4);
+        let release = get_input(runtime, handle.buffer_ids_start + 5);
+        let mut state = get_state::<AdsrState, E>(runtime, handle.slot_ids_start);
+        let (attack_curve, curve) = (state.attack_curve, state.curve);
+
+        run(runtime, handle, 6, &mut state.envelope, Some(1), |i| [
+            Stage { level: 1.0, seconds: value_at(*attack, i, 0.01), curve: attack_curve },
+            Stage { level: value_at(*sustain, i, 0.7), seconds: value_at(*decay, i, 0.1), curve },
+            Stage { level: 0.0, seconds: value_at(*release, i, 0.3), curve },
+        ]);
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        get_state::<AdsrState, E>(runtime, handle.slot_ids_start).envelope.reset();
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        vec![Box::new(UnsafeCell::new(AdsrState {
+            envelope: EnvelopeState::new(self.triggering, 0.0),
+            attack_curve: self.attack_curve,
+            curve: self.curve,
+        }))]
+    }
+    fn get_handle() -> EnvelopeHandle { EnvelopeHandle }
+}
+
+// Attack to 1 then decay to 0, whether the gate closes or not. Made for
+// drums, where the attack is often 0 and lands on the trigger's sample
+#[derive(Default)]
+pub struct Ad {
+    triggering: Triggering,
+    curve: Curve,
+}
+
+impl Ad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Reacts to the runtime's events through their GateEvent impl, instead
+    // of Gate events
+    pub fn events<Ev: GateEvent>(mut self) -> Self {
+        self.triggering.map_event = map_event::<Ev>;
+        self
+    }
+
+    // How much velocity scales the output, from 0 for not at all to 1, the
+    // default, for entirely
+    pub fn velocity_depth(mut self, depth: f32) -> Self {
+        self.triggering.velocity_depth = depth.clamp(0.0, 1.0);
+        self
+    }
+
+    // The decay curve
+    pub fn curve(mut self, curve: Curve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    pub fn attack_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 2, TypeId::of::<Input>(), TypeId::of::<Ad>())
+    }
+
+    pub fn decay_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 3, TypeId::of::<Input>(), TypeId::of::<Ad>())
+    }
+}
+
+impl Envelope for Ad {}
+
+struct TwoStageState {
+    envelope: EnvelopeState,
+    curve: Curve,
+}
+
+impl Processor for Ad {
+    type Handle = EnvelopeHandle;
+    fn buffers_count() -> usize { 5 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            GATE,
+            VELOCITY,
+            seconds("attack", 0.0),
+            seconds("decay", 0.2),
+            ENVELOPE_OUT,
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let attack = get_input(runtime, handle.buffer_ids_start + 2);
+        let decay = get_input(runtime, handle.buffer_ids_start + 3);
+        let mut state = get_state::<TwoStageState, E>(runtime, handle.slot_ids_start);
+        let curve = state.curve;
+
+        run(runtime, handle, 4, &mut state.envelope, None, |i| [
+            Stage { level: 1.0, seconds: value_at(*attack, i, 0.0), curve: Curve::Linear },
+            Stage { level: 0.0, seconds: value_at(*decay, i, 0.2), curve },
+        ]);
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        get_state::<TwoStageState, E>(runtime, handle.slot_ids_start).envelope.reset();
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        vec![Box::new(UnsafeCell::new(TwoStageState {
+            envelope: EnvelopeState::new(self.triggering, 0.0),
+            curve: self.curve,
+        }))]
+    }
+    fn get_handle() -> EnvelopeHandle { EnvelopeHandle }
+}
+
+// Attack to 1 and hold it while the gate is open, then release to 0
+#[derive(Default)]
+pub struct Ar {
+    triggering: Triggering,
+    curve: Curve,
+}
+
+impl Ar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Reacts to the runtime's events through their GateEvent impl, instead
+    // of Gate events
+    pub fn events<Ev: GateEvent>(mut self) -> Self {
+        self.triggering.map_event = map_event::<Ev>;
+        self
+    }
+
+    // Triggers that come while the gate is still open don't restart it
+    pub fn legato(mut self, legato: bool) -> Self {
+        self.triggering.legato = legato;
+        self
+    }
+
+    // How much velocity scales the output, from 0 for not at all to 1, the
+    // default, for entirely
+    pub fn velocity_depth(mut self, depth: f32) -> Self {
+        self.triggering.velocity_depth = depth.clamp(0.0, 1.0);
+        self
+    }
+
+    // The release curve
+    pub fn curve(mut self, curve: Curve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    pub fn attack_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 2, TypeId::of::<Input>(), TypeId::of::<Ar>())
+    }
+
+    pub fn release_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 3, TypeId::of::<Input>(), TypeId::of::<Ar>())
+    }
+}
+
+impl Envelope for Ar {}
+
+impl Processor for Ar {
+    type Handle = EnvelopeHandle;
+    fn buffers_count() -> usize { 5 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            GATE,
+            VELOCITY,
+            seconds("attack", 0.01),
+            seconds("release", 0.3),
+            ENVELOPE_OUT,
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let attack = get_input(runtime, handle.buffer_ids_start + 2);
+        let release = get_input(runtime, handle.buffer_ids_start + 3);
+        let mut state = get_state::<TwoStageState, E>(runtime, handle.slot_ids_start);
+        let curve = state.curve;
+
+        run(runtime, handle, 4, &mut state.envelope, Some(0), |i| [
+            Stage { level: 1.0, seconds: value_at(*attack, i, 0.01), curve: Curve::Linear },
+            Stage { level: 0.0, seconds: value_at(*release, i, 0.3), curve },
+        ]);
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        get_state::<TwoStageState, E>(runtime, handle.slot_ids_start).envelope.reset();
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        vec![Box::new(UnsafeCell::new(TwoStageState {
+            envelope: EnvelopeState::new(self.triggering, 0.0),
+            curve: self.curve,
+        }))]
+    }
+    fn get_handle() -> EnvelopeHandle { EnvelopeHandle }
+}
+
+// Any number of stages, at any levels, so it can drive more than
+// amplitude: a kick's pitch envelope can be in Hz. Releasing before the
+// sustain stage skips to the one after it. Velocity depth starts at 0
+pub struct MultiStage {
+    triggering: Triggering,
+    initial: f32,
+    stages: Vec<Stage>,
+    sustain: Option<usize>,
+}
+
+impl MultiStage {
+    // Rests at initial until the first trigger
+    pub fn new(initial: f32) -> Self {
+        Self {
+            triggering: Triggering { velocity_depth: 0.0, ..Triggering::default() },
+            initial,
+            stages: Vec::new(),
+            sustain: None,
+        }
+    }
+
+    pub fn stage(mut self, level: f32, seconds: f32, curve: Curve) -> Self {
+        self.stages.push(Stage { level, seconds, curve });
+        self
+    }
+
+    // Holds the level of the last stage added while the gate is open
+    pub fn sustain(mut self) -> Self {
+        self.sustain = self.stages.len().checked_sub(1);
+        self
+    }
+
+    // Reacts to the runtime's events through their GateEvent impl, instead
+    // of Gate events
+    pub fn events<Ev: GateEvent>(mut self) -> Self {
+        self.triggering.map_event = map_event::<Ev>;
+        self
+    }
+
+    // Triggers that come while the gate is still open don't restart it
+    pub fn legato(mut self, legato: bool) -> Self {
+        self.triggering.legato = legato;
+        self
+    }
+
+    // How much velocity scales the output, from 0 for not at all to 1 for
+    // entirely
+    pub fn velocity_depth(mut self, depth: f32) -> Self {
+        self.triggering.velocity_depth = depth.clamp(0.0, 1.0);
+        self
+    }
+}
+
+impl Envelope for MultiStage {}
+
+struct MultiStageState {
+    envelope: EnvelopeState,
+    stages: Vec<Stage>,
+    sustain: Option<usize>,
+}
+
+impl Processor for MultiStage {
+    type Handle = EnvelopeHandle;
+    fn buffers_count() -> usize { 3 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[GATE, VELOCITY, ENVELOPE_OUT];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let mut state = get_state::<MultiStageState, E>(runtime, handle.slot_ids_start);
+        let MultiStageState { envelope, stages, sustain } = &mut *state;
+        run(runtime, handle, 2, envelope, *sustain, |_| stages.as_slice());
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        get_state::<MultiStageState, E>(runtime, handle.slot_ids_start).envelope.reset();
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        vec![Box::new(UnsafeCell::new(MultiStageState {
+            envelope: EnvelopeState::new(self.triggering, self.initial),
+            stages: self.stages.clone(),
+            sustain: self.sustain,
+        }))]
+    }
+    fn get_handle() -> EnvelopeHandle { EnvelopeHandle }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Builder;
+    use crate::testing::ProcessorTest;
+
+    // Runs an envelope at 1kHz, so seconds are thousands of samples, with
+    // events landing on the samples given
+    fn run_events<P: Envelope>(envelope: P, events: &[(usize, Gate)], length: usize) -> Vec<f32> {
+        let (mut runtime, router) = Builder::<Gate>::new()
+            .add_processor(envelope, "envelope")
+            .buffer_length(16)
+            .sample_rate(1000.0)
+            .build();
+        router.route(P::envelope_out("envelope"), output()).unwrap();
+
+        let mut out = Vec::new();
+        let mut block = [0.0; 16];
+        for start in (0..length).step_by(16) {
+            runtime.current_events.clear();
+            runtime.event_offsets.clear();
+            for (frame, gate) in events.iter().filter(|(frame, _)| (start..start + 16).contains(frame)) {
+                runtime.current_events.push(*gate);
+                runtime.event_offsets.push(frame - start);
+            }
+            runtime.run_tick();
+            runtime.write_to(&mut block);
+            out.extend_from_slice(&block);
+        }
+        out
+    }
+
+    fn assert_close(found: &[f32], expected: &[f32]) {
+        assert_eq!(found.len(), expected.len());
+        for (n, (found, expected)) in found.iter().zip(expected).enumerate() {
+            assert!((found - expected).abs() < 1e-5, "sample {}: {} != {}", n, found, expected);
+        }
+    }
+
+    #[test]
+    fn test_adsr_gate() {
+        let mut gate = vec![1.0; 16];
+        gate.extend_from_slice(&[0.0; 16]);
+        let capture = ProcessorTest::new(Adsr::new())
+            .sample_rate(1000.0)
+            .buffer_length(16)
+            .input("gate", &gate)
+            .parameter("attack", 0.004)
+            .parameter("decay", 0.004)
+            .parameter("sustain", 0.6)
+            .parameter("release", 0.006)
+            .run(2);
+        assert_close(capture.port("envelope").unwrap(), &[
+            0.25, 0.5, 0.75, 1.0, 0.9, 0.8, 0.7, 0.6, 0.6, 0.6, 0.6, 0.6, 0.6, 0.6, 0.6, 0.6,
+            0.5, 0.4, 0.3, 0.2, 0.1, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+        ]);
+    }
+
+    #[test]
+    fn test_release_before_sustain() {
+        // the attack's cut short, and the release starts from where it got to
+        let out = run_events(Adsr::new(), &[(0, Gate::On { velocity: 1.0 }), (5, Gate::Off)], 32);
+        assert!((out[4] - 0.5).abs() < 1e-5);
+        assert!(out[5] < out[4]);
+        assert!(out[6] < out[5]);
+    }
+
+    #[test]
+    fn test_sample_accurate_events() {
+        let ad = || Ad::new().curve(Curve::Exponential);
+        let out = run_events(ad(), &[(21, Gate::On { velocity: 1.0 })], 32);
+        assert!(out[..21].iter().all(|sample| *sample == 0.0));
+        assert_eq!(out[21], 1.0);
+        assert!(out[22] < 1.0);
+
+        // one-shot: letting go early doesn't cut the decay short
+        let held = run_events(ad(), &[(0, Gate::On { velocity: 1.0 })], 400);
+        let let_go = run_events(ad(), &[(0, Gate::On { velocity: 1.0 }), (1, Gate::Off)], 400);
+        assert_eq!(held, let_go);
+        assert!(held[150] > 0.0);
+        assert_eq!(held[200], 0.0);
+    }
+
+    #[test]
+    fn test_curves() {
+        let decay = |curve| run_events(Ad::new().curve(curve), &[(0, Gate::On { velocity: 1.0 })], 208);
+        let (linear, exponential) = (decay(Curve::Linear), decay(Curve::Exponential));
+        // both take the decay time, the exponential one falls faster at first
+        for n in [20, 50, 100, 150] {
+            assert!(exponential[n] < linear[n]);
+        }
+        assert!((linear[100] - 0.5).abs() < 1e-5);
+        assert_eq!(linear[200], 0.0);
+        assert_eq!(exponential[200], 0.0);
+        assert!(exponential[199] > 0.0);
+    }
+
+    #[test]
+    fn test_velocity() {
+        let peak = |ar: Ar, velocity| run_events(ar.legato(true), &[(0, Gate::On { velocity })], 32)[16];
+        assert!((peak(Ar::new(), 0.5) - 0.5).abs() < 1e-6);
+        assert!((peak(Ar::new().velocity_depth(0.5), 0.5) - 0.75).abs() < 1e-6);
+        assert!((peak(Ar::new().velocity_depth(0.0), 0.5) - 1.0).abs() < 1e-6);
+
+        // gates take theirs from the velocity port
+        let capture = ProcessorTest::new(Ar::new())
+            .parameter("gate", 1.0)
+            .parameter("velocity", 0.25)
+            .parameter("attack", 0.0)
+            .run(4);
+        assert!((capture.port("envelope").unwrap()[255] - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_retrigger_and_legato() {
+        let events = [(0, Gate::On { velocity: 1.0 }), (200, Gate::On { velocity: 1.0 })];
+        // at the sustain level by 200, retriggering attacks from there
+        let retriggered = run_events(Adsr::new(), &events, 224);
+        assert!((retriggered[199] - 0.7).abs() < 1e-5);
+        assert!((retriggered[204] - 0.85).abs() < 1e-5);
+        assert!((retriggered[209] - 1.0).abs() < 1e-5);
+
+        let legato = run_events(Adsr::new().legato(true), &events, 224);
+        assert!(legato[199..].iter().all(|sample| (sample - 0.7).abs() < 1e-5));
+
+        // once released, legato triggers again
+        let released = [(0, Gate::On { velocity: 1.0 }), (100, Gate::Off), (200, Gate::On { velocity: 1.0 })];
+        assert_eq!(run_events(Adsr::new().legato(true), &released, 224)[209], 1.0);
+    }
+
+    #[test]
+    fn test_multi_stage_pitch() {
+        let kick = MultiStage::new(50.0)
+            .stage(400.0, 0.0, Curve::Linear)
+            .stage(50.0, 0.1, Curve::Exponential);
+        let out = run_events(kick, &[(3, Gate::On { velocity: 0.2 }), (150, Gate::On { velocity: 1.0 })], 176);
+        assert_eq!(&out[..3], &[50.0; 3]);
+        // velocity doesn't scale it by default
+        assert_eq!(out[3], 400.0);
+        assert!(out[50] > 50.0 && out[50] < 400.0);
+        assert!((out[103] - 50.0).abs() < 1e-4);
+        assert_eq!(out[150], 400.0);
+
+        // with a sustain stage, releasing skips to the stage after it
+        let swell = MultiStage::new(0.0)
+            .stage(1.0, 0.01, Curve::Linear)
+            .stage(0.5, 0.01, Curve::Linear)
+            .sustain()
+            .stage(0.0, 0.01, Curve::Linear);
+        let out = run_events(swell, &[(0, Gate::On { velocity: 1.0 }), (40, Gate::Off)], 64);
+        assert_eq!(out[9], 1.0);
+        assert!((out[30] - 0.5).abs() < 1e-6);
+        assert!((out[44] - 0.25).abs() < 1e-6);
+        assert_eq!(out[49], 0.0);
+    }
+}
@@ -0,0 +1,539 @@
+// Filters. Biquads follow the RBJ Audio EQ Cookbook, the state-variable
+// filter and the ladder are topology-preserving (zero-delay feedback)
+// designs that stay stable while their cutoff moves at audio rate
+//
+// They all start with the same three inputs:
+//   audio_in   the signal to filter
+//   cutoff     in Hz, 1000 while unrouted. Can be modulated at audio rate
+//   resonance  Q for biquads and the SVF, 0 to 1 for the ladder
+//
+//     let (runtime, router) = Builder::<()>::new()
+//         .add_processor(SawOsc::new(), "osc")
+//         .add_processor(Ladder::new(), "ladder")
+//         .add_processor(SineOsc::new(), "lfo")
+//         .build();
+//     router.route(SawOsc::audio_out("osc"), Ladder::audio_in("ladder"))?;
+//     router.route(SineOsc::audio_out("lfo"), Ladder::cutoff_in("ladder"))?;
+
+use std::f64::consts::PI;
+use crate::core::processor::*;
+use super::value_at;
+
+const AUDIO_IN: PortDescriptor = PortDescriptor::input("audio_in");
+const CUTOFF: PortDescriptor = PortDescriptor::input("cutoff")
+    .kind(PortKind::Control)
+    .default_value(1000.0)
+    .range(20.0, 20_000.0)
+    .unit("Hz");
+const Q: PortDescriptor = PortDescriptor::input("resonance")
+    .kind(PortKind::Control)
+    .default_value(FRAC_1_SQRT_2)
+    .range(0.1, 20.0)
+    .description("Q");
+
+const FRAC_1_SQRT_2: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+// Port handles every filter has
+pub trait Filter: Processor {
+    fn audio_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 0, TypeId::of::<Input>(), TypeId::of::<Self>())
+    }
+
+    fn cutoff_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 1, TypeId::of::<Input>(), TypeId::of::<Self>())
+    }
+
+    fn resonance_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 2, TypeId::of::<Input>(), TypeId::of::<Self>())
+    }
+}
+
+pub struct FilterHandle;
+impl ProcessorHandle for FilterHandle {}
+
+// tan(pi * cutoff / sample_rate), the prewarped gain of a TPT integrator.
+// Cutoffs are kept just under Nyquist, where it blows up
+fn prewarp(cutoff: f32, sample_rate: f32) -> f64 {
+    let cutoff = (cutoff as f64).clamp(1.0, sample_rate as f64 * 0.49);
+    (PI * cutoff / sample_rate as f64).tan()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BiquadKind {
+    Lowpass,
+    Highpass,
+    // 0dB at the cutoff
+    Bandpass,
+    Notch,
+    // boosts or cuts around the cutoff by the gain input
+    Peak,
+    LowShelf,
+    HighShelf,
+}
+
+// b0, b1, b2, a1, a2, normalized by a0
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Coefficients([f64; 5]);
+
+impl BiquadKind {
+    fn coefficients(self, cutoff: f32, q: f32, gain_db: f32, sample_rate: f32) -> Coefficients {
+        let w0 = 2.0 * PI * (cutoff as f64).clamp(1.0, sample_rate as f64 * 0.49) / sample_rate as f64;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * (q as f64).max(0.01));
+        let a = 10f64.powf(gain_db as f64 / 40.0);
+        let shelf = 2.0 * a.sqrt() * alpha;
+
+        let [b0, b1, b2, a0, a1, a2] = match self {
+            BiquadKind::Lowpass => [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            BiquadKind::Highpass => [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            BiquadKind::Bandpass => [alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            BiquadKind::Notch => [1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            BiquadKind::Peak => [1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a, 1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a],
+            BiquadKind::LowShelf => [
+                a * ((a + 1.0) - (a - 1.0) * cos + shelf),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - shelf),
+                (a + 1.0) + (a - 1.0) * cos + shelf,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - shelf,
+            ],
+            BiquadKind::HighShelf => [
+                a * ((a + 1.0) + (a - 1.0) * cos + shelf),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - shelf),
+                (a + 1.0) - (a - 1.0) * cos + shelf,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - shelf,
+            ],
+        };
+        Coefficients([b0 / a0, b1 / a0, b2 / a0, a1 / a0, a2 / a0])
+    }
+}
+
+// An RBJ cookbook biquad in transposed direct form II
+pub struct Biquad {
+    kind: BiquadKind,
+}
+
+impl Biquad {
+    pub fn new(kind: BiquadKind) -> Self {
+        Self { kind }
+    }
+
+    pub fn gain_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 3, TypeId::of::<Input>(), TypeId::of::<Biquad>())
+    }
+
+    pub fn audio_out(instance_name: &'static str) -> PortHandle<Output<'static>> {
+        PortHandle::new(instance_name, 4, TypeId::of::<Output>(), TypeId::of::<Biquad>())
+    }
+}
+
+impl Filter for Biquad {}
+
+struct BiquadState {
+    kind: BiquadKind,
+    // cutoff, resonance, gain and sample rate the coefficients were made for
+    made_for: Option<[f32; 4]>,
+    coefficients: Coefficients,
+    z1: f64,
+    z2: f64,
+}
+
+impl Processor for Biquad {
+    type Handle = FilterHandle;
+    fn buffers_count() -> usize { 5 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            AUDIO_IN,
+            CUTOFF,
+            Q,
+            PortDescriptor::input("gain")
+                .kind(PortKind::Control)
+                .range(-24.0, 24.0)
+                .unit("dB")
+                .description("Boost or cut of peak and shelf filters"),
+            PortDescriptor::output("audio_out"),
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let audio_in = get_input(runtime, handle.buffer_ids_start);
+        let cutoff = get_input(runtime, handle.buffer_ids_start + 1);
+        let resonance = get_input(runtime, handle.buffer_ids_start + 2);
+        let gain = get_input(runtime, handle.buffer_ids_start + 3);
+        let mut audio_out = get_output(runtime, handle.buffer_ids_start + 4);
+        let mut state = get_state::<BiquadState, E>(runtime, handle.slot_ids_start);
+        let sample_rate = runtime.sample_rate();
+
+        for (i, out) in audio_out.iter_mut().enumerate() {
+            // coefficients are only worked out again when something moved
+            let params = [
+                value_at(*cutoff, i, 1000.0),
+                value_at(*resonance, i, FRAC_1_SQRT_2),
+                value_at(*gain, i, 0.0),
+                sample_rate,
+            ];
+            if state.made_for != Some(params) {
+                state.coefficients = state.kind.coefficients(params[0], params[1], params[2], sample_rate);
+                state.made_for = Some(params);
+            }
+
+            let Coefficients([b0, b1, b2, a1, a2]) = state.coefficients;
+            let x = value_at(*audio_in, i, 0.0) as f64;
+            let y = b0 * x + state.z1;
+            state.z1 = b1 * x - a1 * y + state.z2;
+            state.z2 = b2 * x - a2 * y;
+            *out = y as f32;
+        }
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let mut state = get_state::<BiquadState, E>(runtime, handle.slot_ids_start);
+        state.z1 = 0.0;
+        state.z2 = 0.0;
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        vec![Box::new(UnsafeCell::new(BiquadState {
+            kind: self.kind,
+            made_for: None,
+            coefficients: Coefficients::default(),
+            z1: 0.0,
+            z2: 0.0,
+        }))]
+    }
+    fn get_handle() -> FilterHandle { FilterHandle }
+}
+
+// A TPT state-variable filter with lowpass, bandpass, highpass and notch
+// outputs at once. The bandpass peaks at 0dB for a resonance of 1
+#[derive(Default)]
+pub struct Svf;
+
+impl Svf {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn lowpass_out(instance_name: &'static str) -> PortHandle<Output<'static>> {
+        PortHandle::new(instance_name, 3, TypeId::of::<Output>(), TypeId::of::<Svf>())
+    }
+
+    pub fn bandpass_out(instance_name: &'static str) -> PortHandle<Output<'static>> {
+        PortHandle::new(instance_name, 4, TypeId::of::<Output>(), TypeId::of::<Svf>())
+    }
+
+    pub fn highpass_out(instance_name: &'static str) -> PortHandle<Output<'static>> {
+        PortHandle::new(instance_name, 5, TypeId::of::<Output>(), TypeId::of::<Svf>())
+    }
+
+    pub fn notch_out(instance_name: &'static str) -> PortHandle<Output<'static>> {
+        PortHandle::new(instance_name, 6, TypeId::of::<Output>(), TypeId::of::<Svf>())
+    }
+}
+
+impl Filter for Svf {}
+
+// the two integrators' states
+#[derive(Default)]
+struct SvfState {
+    ic1: f64,
+    ic2: f64,
+}
+
+impl Processor for Svf {
+    type Handle = FilterHandle;
+    fn buffers_count() -> usize { 7 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            AUDIO_IN,
+            CUTOFF,
+            Q,
+            PortDescriptor::output("lowpass"),
+            PortDescriptor::output("bandpass"),
+            PortDescriptor::output("highpass"),
+            PortDescriptor::output("notch"),
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let audio_in = get_input(runtime, handle.buffer_ids_start);
+        let cutoff = get_input(runtime, handle.buffer_ids_start + 1);
+        let resonance = get_input(runtime, handle.buffer_ids_start + 2);
+        let mut lowpass = get_output(runtime, handle.buffer_ids_start + 3);
+        let mut bandpass = get_output(runtime, handle.buffer_ids_start + 4);
+        let mut highpass = get_output(runtime, handle.buffer_ids_start + 5);
+        let mut notch = get_output(runtime, handle.buffer_ids_start + 6);
+        let mut state = get_state::<SvfState, E>(runtime, handle.slot_ids_start);
+        let sample_rate = runtime.sample_rate();
+
+        for i in 0..lowpass.len() {
+            let g = prewarp(value_at(*cutoff, i, 1000.0), sample_rate);
+            let k = 1.0 / (value_at(*resonance, i, FRAC_1_SQRT_2) as f64).max(0.01);
+            let a1 = 1.0 / (1.0 + g * (g + k));
+            let a2 = g * a1;
+            let a3 = g * a2;
+
+            let x = value_at(*audio_in, i, 0.0) as f64;
+            let v3 = x - state.ic2;
+            let v1 = a1 * state.ic1 + a2 * v3;
+            let v2 = state.ic2 + a2 * state.ic1 + a3 * v3;
+            state.ic1 = 2.0 * v1 - state.ic1;
+            state.ic2 = 2.0 * v2 - state.ic2;
+
+            lowpass[i] = v2 as f32;
+            bandpass[i] = v1 as f32;
+            highpass[i] = (x - k * v1 - v2) as f32;
+            notch[i] = (x - k * v1) as f32;
+        }
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        *get_state::<SvfState, E>(runtime, handle.slot_ids_start) = SvfState::default();
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        vec![Box::new(UnsafeCell::new(SvfState::default()))]
+    }
+    fn get_handle() -> FilterHandle { FilterHandle }
+}
+
+// A 24dB/octave lowpass ladder. Four TPT one-poles with the feedback
+// around them solved without a delay, and a tanh where it comes back in,
+// so resonance saturates instead of running away. It self-oscillates
+// near a resonance of 1
+#[derive(Default)]
+pub struct Ladder;
+
+impl Ladder {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn drive_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 3, TypeId::of::<Input>(), TypeId::of::<Ladder>())
+    }
+
+    pub fn audio_out(instance_name: &'static str) -> PortHandle<Output<'static>> {
+        PortHandle::new(instance_name, 4, TypeId::of::<Output>(), TypeId::of::<Ladder>())
+    }
+}
+
+impl Filter for Ladder {}
+
+// each one-pole's state
+#[derive(Default)]
+struct LadderState {
+    stages: [f64; 4],
+}
+
+impl Processor for Ladder {
+    type Handle = FilterHandle;
+    fn buffers_count() -> usize { 5 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            AUDIO_IN,
+            CUTOFF,
+            PortDescriptor::input("resonance")
+                .kind(PortKind::Control)
+                .default_value(0.0)
+                .range(0.0, 1.0),
+            PortDescriptor::input("drive")
+                .kind(PortKind::Control)
+                .default_value(1.0)
+                .range(1.0, 10.0)
+                .description("Gain into the saturation"),
+            PortDescriptor::output("audio_out"),
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let audio_in = get_input(runtime, handle.buffer_ids_start);
+        let cutoff = get_input(runtime, handle.buffer_ids_start + 1);
+        let resonance = get_input(runtime, handle.buffer_ids_start + 2);
+        let drive = get_input(runtime, handle.buffer_ids_start + 3);
+        let mut audio_out = get_output(runtime, handle.buffer_ids_start + 4);
+        let mut state = get_state::<LadderState, E>(runtime, handle.slot_ids_start);
+        let sample_rate = runtime.sample_rate();
+
+        for (i, out) in audio_out.iter_mut().enumerate() {
+            let g = prewarp(value_at(*cutoff, i, 1000.0), sample_rate);
+            let gain = g / (1.0 + g);
+            let k = 4.0 * value_at(*resonance, i, 0.0).clamp(0.0, 1.0) as f64;
+            let x = value_at(*audio_in, i, 0.0) as f64 * value_at(*drive, i, 1.0) as f64;
+
+            // each stage gives gain * input + (1 - gain) * its state, so the
+            // last one's output is gain^4 * input plus what the states add
+            let from_states = state.stages.iter()
+                .fold(0.0, |sum, stage| sum * gain + (1.0 - gain) * stage);
+            let gain4 = gain * gain * gain * gain;
+            let mut signal = ((x - k * from_states) / (1.0 + k * gain4)).tanh();
+
+            for stage in &mut state.stages {
+                let v = (signal - *stage) * gain;
+                signal = v + *stage;
+                *stage = signal + v;
+            }
+            *out = signal as f32;
+        }
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        *get_state::<LadderState, E>(runtime, handle.slot_ids_start) = LadderState::default();
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        vec![Box::new(UnsafeCell::new(LadderState::default()))]
+    }
+    fn get_handle() -> FilterHandle { FilterHandle }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::ProcessorTest;
+    use std::f32::consts::TAU;
+
+    // 9600 samples, measured over the last 4800, which holds a whole number
+    // of cycles of any multiple of 10Hz
+    const LENGTH: usize = 9600;
+
+    // How much a filter passes a sine at frequency, from its RMS once settled.
+    // The sine's quiet enough to keep the ladder out of saturation
+    fn gain_db<P: Processor>(test: ProcessorTest<P>, output: &str, frequency: f32) -> f32 {
+        let sine: Vec<f32> = (0..LENGTH).map(|n| 0.1 * (TAU * frequency * n as f32 / 48_000.0).sin()).collect();
+        let capture = test.input("audio_in", &sine).run(LENGTH / 64);
+        let settled = &capture.port(output).unwrap()[LENGTH / 2..];
+        let power = settled.iter().map(|sample| sample * sample).sum::<f32>() / settled.len() as f32;
+        10.0 * (200.0 * power).log10()
+    }
+
+    // The response the coefficients should give
+    fn expected_db(coefficients: Coefficients, frequency: f32) -> f32 {
+        let Coefficients([b0, b1, b2, a1, a2]) = coefficients;
+        let w = 2.0 * PI * frequency as f64 / 48_000.0;
+        let magnitude = |c0: f64, c1: f64, c2: f64| {
+            let re = c0 + c1 * w.cos() + c2 * (2.0 * w).cos();
+            let im = c1 * w.sin() + c2 * (2.0 * w).sin();
+            (re * re + im * im).sqrt()
+        };
+        (20.0 * (magnitude(b0, b1, b2) / magnitude(1.0, a1, a2)).log10()) as f32
+    }
+
+    fn biquad(kind: BiquadKind) -> ProcessorTest<Biquad> {
+        ProcessorTest::new(Biquad::new(kind))
+            .parameter("cutoff", 1000.0)
+            .parameter("resonance", 2.0)
+            .parameter("gain", 6.0)
+    }
+
+    #[test]
+    fn test_biquad_matches_cookbook() {
+        let kinds = [
+            BiquadKind::Lowpass,
+            BiquadKind::Highpass,
+            BiquadKind::Bandpass,
+            BiquadKind::Notch,
+            BiquadKind::Peak,
+            BiquadKind::LowShelf,
+            BiquadKind::HighShelf,
+        ];
+        for kind in kinds {
+            let coefficients = kind.coefficients(1000.0, 2.0, 6.0, 48_000.0);
+            for frequency in [100.0, 500.0, 1000.0, 2000.0, 10_000.0] {
+                let (found, expected) = (gain_db(biquad(kind), "audio_out", frequency), expected_db(coefficients, frequency));
+                // the notch's cutoff is too deep to measure in f32
+                if expected > -60.0 {
+                    assert!((found - expected).abs() < 0.1, "{:?} at {}Hz: {}dB, expected {}dB", kind, frequency, found, expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_biquad_shapes() {
+        let at = |kind, frequency| gain_db(biquad(kind), "audio_out", frequency);
+        assert!(at(BiquadKind::Lowpass, 100.0).abs() < 0.1);
+        assert!(at(BiquadKind::Lowpass, 10_000.0) < -35.0);
+        assert!(at(BiquadKind::Highpass, 100.0) < -35.0);
+        assert!(at(BiquadKind::Highpass, 10_000.0).abs() < 0.1);
+        assert!(at(BiquadKind::Bandpass, 1000.0).abs() < 0.1);
+        assert!(at(BiquadKind::Notch, 1000.0) < -40.0);
+        assert!((at(BiquadKind::Peak, 1000.0) - 6.0).abs() < 0.1);
+        assert!((at(BiquadKind::LowShelf, 100.0) - 6.0).abs() < 0.2);
+        assert!((at(BiquadKind::HighShelf, 10_000.0) - 6.0).abs() < 0.2);
+
+        // a Butterworth lowpass is 3dB down at its cutoff
+        let butterworth = gain_db(ProcessorTest::new(Biquad::new(BiquadKind::Lowpass)), "audio_out", 1000.0);
+        assert!((butterworth + 3.01).abs() < 0.05, "{}", butterworth);
+    }
+
+    #[test]
+    fn test_svf_responses() {
+        let svf = || ProcessorTest::new(Svf::new()).parameter("cutoff", 1000.0);
+        assert!((gain_db(svf(), "lowpass", 1000.0) + 3.01).abs() < 0.05);
+        assert!((gain_db(svf(), "highpass", 1000.0) + 3.01).abs() < 0.05);
+        assert!(gain_db(svf(), "lowpass", 100.0).abs() < 0.1);
+        assert!(gain_db(svf(), "lowpass", 10_000.0) < -35.0);
+        assert!(gain_db(svf(), "highpass", 100.0) < -35.0);
+        assert!(gain_db(svf(), "notch", 1000.0) < -40.0);
+        assert!(gain_db(svf().parameter("resonance", 1.0), "bandpass", 1000.0).abs() < 0.05);
+
+        // the outputs add back up to the input
+        let noise: Vec<f32> = (0..512u32).map(|n| (n.wrapping_mul(2_654_435_761) >> 16) as f32 / 65_536.0 - 0.5).collect();
+        let capture = svf().parameter("resonance", 4.0).input("audio_in", &noise).run(8);
+        let k = 0.25;
+        for (n, input) in noise.iter().enumerate() {
+            let sum = capture.port("lowpass").unwrap()[n]
+                + k * capture.port("bandpass").unwrap()[n]
+                + capture.port("highpass").unwrap()[n];
+            assert!((sum - input).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_ladder() {
+        let ladder = |resonance| ProcessorTest::new(Ladder::new())
+            .parameter("cutoff", 1000.0)
+            .parameter("resonance", resonance);
+
+        // small signals pass below the cutoff and fall 24dB an octave above it
+        let response = |test, frequency| gain_db(test, "audio_out", frequency);
+        assert!(response(ladder(0.0), 100.0).abs() < 0.5);
+        let (octave, two_octaves) = (response(ladder(0.0), 4000.0), response(ladder(0.0), 8000.0));
+        assert!((two_octaves - octave + 24.0).abs() < 3.0, "{} {}", octave, two_octaves);
+
+        // resonance peaks at the cutoff
+        assert!(response(ladder(0.8), 1000.0) > response(ladder(0.0), 1000.0) + 6.0);
+
+        // and near 1 it rings on its own after an impulse, without blowing up
+        let capture = ladder(1.0).input("audio_in", &[1.0]).run(LENGTH / 64);
+        let tail = &capture.port("audio_out").unwrap()[LENGTH / 2..];
+        let peak = tail.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        assert!(peak > 0.01 && peak <= 1.0, "{}", peak);
+    }
+
+    #[test]
+    fn test_audio_rate_cutoff() {
+        // a cutoff fed in as audio matches the same value set as a parameter
+        let noise: Vec<f32> = (0..512u32).map(|n| (n.wrapping_mul(2_654_435_761) >> 16) as f32 / 65_536.0 - 0.5).collect();
+        let fed = ProcessorTest::new(Biquad::new(BiquadKind::Lowpass))
+            .input("audio_in", &noise)
+            .input("cutoff", &[500.0; 512])
+            .run(8);
+        let set = ProcessorTest::new(Biquad::new(BiquadKind::Lowpass))
+            .input("audio_in", &noise)
+            .parameter("cutoff", 500.0)
+            .run(8);
+        assert_eq!(fed.port("audio_out").unwrap(), set.port("audio_out").unwrap());
+
+        // and sweeping it every sample stays stable
+        let sweep: Vec<f32> = (0..4096).map(|n| 1000.0 + 900.0 * (n as f32 * 0.05).sin()).collect();
+        for output in ["lowpass", "bandpass"] {
+            let capture = ProcessorTest::new(Svf::new())
+                .parameter("resonance", 10.0)
+                .input("audio_in", &noise)
+                .input("cutoff", &sweep)
+                .run(64);
+            assert!(capture.port(output).unwrap().iter().all(|sample| sample.is_finite() && sample.abs() < 100.0));
+        }
+    }
+}
@@ -0,0 +1,380 @@
+// Low-frequency oscillators, for modulating cutoffs, pans and the like.
+// Unlike the oscillators in osc they aren't band-limited, so square and saw
+// corners stay sharp
+//
+// Inputs:
+//   rate    in Hz, or cycles a beat of the tempo input once tempo-synced
+//   tempo   in BPM, only read when tempo-synced
+//   phase   cycles added to the phase, to offset one LFO from another
+//   reset   restarts the cycle when it rises past 0.5
+//
+// The cycle can restart on the runtime's events too, at every Gate::On
+// their GateEvent impl maps them to
+//
+//     let (runtime, router) = Builder::<Gate>::new()
+//         .add_processor(Lfo::new(LfoShape::Triangle).tempo_sync(true).unipolar(true), "wobble")
+//         .build();
+//     router.set_parameter(Lfo::rate_in("wobble"), 0.5)?;
+//     router.route(Lfo::lfo_out("wobble"), Ladder::cutoff_in("bass"))?;
+
+use std::f32::consts::TAU;
+use crate::core::processor::*;
+use super::envelope::{Gate, GateEvent};
+use super::{events_of, value_at, Random};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LfoShape {
+    #[default]
+    Sine,
+    // rising from 0 through the first quarter
+    Triangle,
+    // rising from -1 to 1
+    Saw,
+    // 1 for the first half, -1 for the second
+    Square,
+    // a new random value every cycle, held until the next
+    SampleHold,
+}
+
+// How often the output moves
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Update {
+    #[default]
+    EverySample,
+    // at the start of each block, for modulation that's only read once a block
+    EveryBlock,
+    // every so many samples, counted across blocks
+    Every(usize),
+}
+
+fn restarts<Ev: GateEvent>(event: &dyn Any) -> bool {
+    matches!(event.downcast_ref::<Ev>().and_then(Ev::gate), Some(Gate::On { .. }))
+}
+
+#[derive(Clone, Copy)]
+struct Settings {
+    shape: LfoShape,
+    tempo_sync: bool,
+    unipolar: bool,
+    update: Update,
+    restarts: fn(&dyn Any) -> bool,
+}
+
+pub struct Lfo {
+    settings: Settings,
+}
+
+impl Lfo {
+    pub fn new(shape: LfoShape) -> Self {
+        Self {
+            settings: Settings {
+                shape,
+                tempo_sync: false,
+                unipolar: false,
+                update: Update::EverySample,
+                restarts: restarts::<Gate>,
+            },
+        }
+    }
+
+    // Reads the rate input in cycles a beat of the tempo input instead of Hz
+    pub fn tempo_sync(mut self, tempo_sync: bool) -> Self {
+        self.settings.tempo_sync = tempo_sync;
+        self
+    }
+
+    // Goes from 0 to 1 instead of -1 to 1
+    pub fn unipolar(mut self, unipolar: bool) -> Self {
+        self.settings.unipolar = unipolar;
+        self
+    }
+
+    pub fn update(mut self, update: Update) -> Self {
+        self.settings.update = update;
+        self
+    }
+
+    // Restarts on the runtime's events through their GateEvent impl, instead
+    // of Gate events
+    pub fn events<Ev: GateEvent>(mut self) -> Self {
+        self.settings.restarts = restarts::<Ev>;
+        self
+    }
+
+    pub fn rate_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 0, TypeId::of::<Input>(), TypeId::of::<Lfo>())
+    }
+
+    pub fn tempo_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 1, TypeId::of::<Input>(), TypeId::of::<Lfo>())
+    }
+
+    pub fn phase_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 2, TypeId::of::<Input>(), TypeId::of::<Lfo>())
+    }
+
+    pub fn reset_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 3, TypeId::of::<Input>(), TypeId::of::<Lfo>())
+    }
+
+    pub fn lfo_out(instance_name: &'static str) -> PortHandle<Output<'static>> {
+        PortHandle::new(instance_name, 4, TypeId::of::<Output>(), TypeId::of::<Lfo>())
+    }
+}
+
+pub struct LfoHandle;
+impl ProcessorHandle for LfoHandle {}
+
+struct LfoState {
+    settings: Settings,
+    // in cycles, where the next sample is read
+    phase: f64,
+    // the reset input's last sample, to find its edges
+    reset: f32,
+    // the sample-and-hold value, and where the random ones come from
+    held: f32,
+    random: Random,
+    // samples left until the output next moves, and what it shows until then
+    countdown: usize,
+    out: f32,
+}
+
+impl LfoState {
+    fn new(settings: Settings, mut random: Random) -> Self {
+        let held = random.bipolar();
+        Self { settings, phase: 0.0, reset: 0.0, held, random, countdown: 0, out: 0.0 }
+    }
+
+    fn restart(&mut self) {
+        self.phase = 0.0;
+        self.held = self.random.bipolar();
+    }
+
+    fn shape(&self, offset: f32) -> f32 {
+        let t = (self.phase as f32 + offset).rem_euclid(1.0);
+        let value = match self.settings.shape {
+            LfoShape::Sine => (TAU * t).sin(),
+            LfoShape::Triangle => 1.0 - 4.0 * ((t + 0.25).fract() - 0.5).abs(),
+            LfoShape::Saw => 2.0 * t - 1.0,
+            LfoShape::Square => if t < 0.5 { 1.0 } else { -1.0 },
+            LfoShape::SampleHold => self.held,
+        };
+        if self.settings.unipolar { 0.5 + 0.5 * value } else { value }
+    }
+}
+
+impl Processor for Lfo {
+    type Handle = LfoHandle;
+    fn buffers_count() -> usize { 5 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            PortDescriptor::input("rate")
+                .kind(PortKind::Control)
+                .default_value(1.0)
+                .range(0.0, 100.0)
+                .description("Hz, or cycles a beat of the tempo input once tempo-synced"),
+            PortDescriptor::input("tempo")
+                .kind(PortKind::Control)
+                .default_value(120.0)
+                .range(20.0, 300.0)
+                .unit("BPM"),
+            PortDescriptor::input("phase")
+                .kind(PortKind::Control)
+                .range(0.0, 1.0)
+                .description("Cycles added to the phase"),
+            PortDescriptor::input("reset")
+                .kind(PortKind::Control)
+                .range(0.0, 1.0)
+                .description("Restarts the cycle when it rises past 0.5"),
+            PortDescriptor::output("lfo").kind(PortKind::Control),
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let rate = get_input(runtime, handle.buffer_ids_start);
+        let tempo = get_input(runtime, handle.buffer_ids_start + 1);
+        let phase = get_input(runtime, handle.buffer_ids_start + 2);
+        let reset = get_input(runtime, handle.buffer_ids_start + 3);
+        let mut lfo_out = get_output(runtime, handle.buffer_ids_start + 4);
+        let mut state = get_state::<LfoState, E>(runtime, handle.slot_ids_start);
+        let settings = state.settings;
+        let sample_rate = runtime.sample_rate() as f64;
+
+        let mut restarts = events_of(runtime)
+            .filter(|(_, event)| (settings.restarts)(*event))
+            .map(|(offset, _)| offset)
+            .peekable();
+
+        for (i, out) in lfo_out.iter_mut().enumerate() {
+            let mut restart = false;
+            while restarts.next_if(|offset| *offset <= i).is_some() {
+                restart = true;
+            }
+            if let Some(reset) = *reset {
+                restart |= reset[i] >= 0.5 && state.reset < 0.5;
+                state.reset = reset[i];
+            }
+            if restart {
+                state.restart();
+            }
+
+            let moves = match settings.update {
+                Update::EverySample => true,
+                Update::EveryBlock => i == 0,
+                Update::Every(samples) => {
+                    let moves = state.countdown == 0;
+                    state.countdown = if moves { samples.max(1) - 1 } else { state.countdown - 1 };
+                    moves
+                },
+            };
+            if moves {
+                state.out = state.shape(value_at(*phase, i, 0.0));
+            }
+            *out = state.out;
+
+            let mut hz = value_at(*rate, i, 1.0).max(0.0);
+            if settings.tempo_sync {
+                hz *= value_at(*tempo, i, 120.0).max(1.0) / 60.0;
+            }
+            state.phase += hz as f64 / sample_rate;
+            if state.phase >= 1.0 {
+                state.phase = state.phase.fract();
+                state.held = state.random.bipolar();
+            }
+        }
+    }
+    fn prepare<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle, _config: AudioConfig) {
+        Self::reset(runtime, handle);
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let mut state = get_state::<LfoState, E>(runtime, handle.slot_ids_start);
+        *state = LfoState::new(state.settings, Random::new(runtime.instance_seed(handle)));
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        // seeded in prepare, once the instance is in a runtime
+        vec![Box::new(UnsafeCell::new(LfoState::new(self.settings, Random::new(0))))]
+    }
+    fn get_handle() -> LfoHandle { LfoHandle }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Builder;
+    use crate::testing::ProcessorTest;
+
+    // An LFO at 1kHz, so a 1Hz rate takes a thousand samples a cycle
+    fn lfo(lfo: Lfo) -> ProcessorTest<Lfo> {
+        ProcessorTest::new(lfo).sample_rate(1000.0)
+    }
+
+    fn assert_close(found: f32, expected: f32) {
+        assert!((found - expected).abs() < 1e-3, "{} != {}", found, expected);
+    }
+
+    #[test]
+    fn test_shapes() {
+        let expected = [
+            (LfoShape::Sine, [0.0, 1.0, 0.0, -1.0]),
+            (LfoShape::Triangle, [0.0, 1.0, 0.0, -1.0]),
+            (LfoShape::Saw, [-1.0, -0.5, 0.0, 0.5]),
+            (LfoShape::Square, [1.0, 1.0, -1.0, -1.0]),
+        ];
+        for (shape, quarters) in expected {
+            let capture = lfo(Lfo::new(shape)).run(20);
+            let out = capture.port("lfo").unwrap();
+            for (quarter, expected) in quarters.iter().enumerate() {
+                assert_close(out[quarter * 250], *expected);
+            }
+            // the second cycle plays the first again
+            assert_close(out[1000 + 250], out[250]);
+
+            let capture = lfo(Lfo::new(shape).unipolar(true)).run(20);
+            let unipolar = capture.port("lfo").unwrap();
+            for (unipolar, bipolar) in unipolar.iter().zip(out) {
+                assert_close(*unipolar, 0.5 + 0.5 * bipolar);
+            }
+        }
+
+        // a quarter cycle offset turns the sine into a cosine
+        let capture = lfo(Lfo::new(LfoShape::Sine)).parameter("phase", 0.25).run(1);
+        assert_close(capture.port("lfo").unwrap()[0], 1.0);
+    }
+
+    #[test]
+    fn test_sample_and_hold() {
+        let capture = lfo(Lfo::new(LfoShape::SampleHold)).parameter("rate", 10.0).run(16);
+        let out = capture.port("lfo").unwrap();
+        for step in out.chunks(100) {
+            assert!(step.iter().all(|sample| *sample == step[0]));
+            assert!((-1.0..=1.0).contains(&step[0]));
+        }
+        assert!(out.chunks(100).map(|step| step[0]).collect::<Vec<_>>().windows(2).all(|pair| pair[0] != pair[1]));
+
+        let again = lfo(Lfo::new(LfoShape::SampleHold)).parameter("rate", 10.0).run(16);
+        assert_eq!(again.port("lfo").unwrap(), out);
+        let reseeded = lfo(Lfo::new(LfoShape::SampleHold)).parameter("rate", 10.0).seed(1).run(16);
+        assert_ne!(reseeded.port("lfo").unwrap(), out);
+    }
+
+    #[test]
+    fn test_tempo_sync() {
+        // half a cycle a beat at 120BPM is 1Hz
+        let capture = lfo(Lfo::new(LfoShape::Saw).tempo_sync(true))
+            .parameter("rate", 0.5)
+            .parameter("tempo", 120.0)
+            .run(16);
+        let out = capture.port("lfo").unwrap();
+        assert_close(out[500], 0.0);
+        assert_close(out[999], 0.998);
+        assert_close(out[1000], -1.0);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut reset = vec![0.0; 64];
+        reset[40..].fill(1.0);
+        let capture = lfo(Lfo::new(LfoShape::Saw))
+            .parameter("rate", 10.0)
+            .input("reset", &reset)
+            .run(1);
+        let out = capture.port("lfo").unwrap();
+        assert_close(out[39], -1.0 + 2.0 * 0.39);
+        assert_close(out[40], -1.0);
+        assert_close(out[50], -0.8);
+
+        // and on Gate::On events, landing on the sample they're for
+        let (mut runtime, router) = Builder::<Gate>::new()
+            .add_processor(Lfo::new(LfoShape::Saw), "lfo")
+            .buffer_length(64)
+            .sample_rate(1000.0)
+            .build();
+        router.route(Lfo::lfo_out("lfo"), output()).unwrap();
+        router.set_parameter(Lfo::rate_in("lfo"), 10.0).unwrap();
+        let mut block = [0.0; 64];
+        runtime.current_events.extend([Gate::Off, Gate::On { velocity: 1.0 }]);
+        runtime.event_offsets.extend([10, 20]);
+        runtime.run_tick();
+        runtime.write_to(&mut block);
+        assert_close(block[19], -1.0 + 2.0 * 0.19);
+        assert_close(block[20], -1.0);
+    }
+
+    #[test]
+    fn test_update() {
+        let capture = lfo(Lfo::new(LfoShape::Saw).update(Update::EveryBlock)).run(2);
+        let out = capture.port("lfo").unwrap();
+        assert_eq!(&out[..64], &[-1.0; 64]);
+        assert!(out[64..].iter().all(|sample| *sample == out[64]));
+        assert_close(out[64], -1.0 + 2.0 * 0.064);
+
+        // every 48 samples, counting on over the block boundary
+        let capture = lfo(Lfo::new(LfoShape::Saw).update(Update::Every(48))).run(2);
+        let out = capture.port("lfo").unwrap();
+        for step in out.chunks(48) {
+            assert!(step.iter().all(|sample| *sample == step[0]));
+        }
+        assert_close(out[96], -1.0 + 2.0 * 0.096);
+    }
+}
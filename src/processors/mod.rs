@@ -0,0 +1,55 @@
+// Ready-made processors. They're plain Processor impls, added to a Builder
+// like any other
+
+pub mod delay;
+pub mod dynamics;
+pub mod envelope;
+pub mod filter;
+pub mod lfo;
+pub mod noise;
+pub mod osc;
+pub mod reverb;
+pub mod sampler;
+
+use std::any::Any;
+use crate::core::Runtime;
+
+// This tick's events with the sample each lands on. Processors can't bound
+// the runtime's event type, so ones that react to events are handed them as
+// Any and pick out the types they were set up for
+pub(crate) fn events_of<E: Clone + Copy + 'static>(runtime: &Runtime<E>) -> impl Iterator<Item = (usize, &dyn Any)> {
+    runtime.current_events.iter()
+        .zip(&runtime.event_offsets)
+        .map(|(event, offset)| (*offset, event as &dyn Any))
+}
+
+// An input's sample i, or default while the input isn't routed
+pub(crate) fn value_at(input: Option<&[f32]>, i: usize, default: f32) -> f32 {
+    input.map_or(default, |input| input[i])
+}
+
+// xorshift64* numbers for processors that need randomness. Seeded from
+// Runtime::instance_seed, they repeat exactly from one run to the next
+#[derive(Clone, Copy)]
+pub(crate) struct Random(u64);
+
+impl Random {
+    pub(crate) fn new(seed: u64) -> Self {
+        // xorshift never leaves 0
+        Self(seed.max(1))
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // Uniform from -1 up to 1
+    pub(crate) fn bipolar(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+    }
+}
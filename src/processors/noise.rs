@@ -0,0 +1,407 @@
+// Noise and random signals. Every instance draws from its own generator,
+// seeded from Runtime::instance_seed in prepare and again on reset, so a
+// graph built with the same Builder::seed plays the same samples every run
+//
+//     let (runtime, router) = Builder::<()>::new()
+//         .seed(7)
+//         .add_processor(Noise::new(NoiseColor::Pink), "hiss")
+//         .add_processor(SmoothRandom::new(), "drift")
+//         .build();
+//     router.set_parameter(SmoothRandom::rate_in("drift"), 0.5)?;
+
+use std::f32::consts::PI;
+use crate::core::processor::*;
+use super::{value_at, Random};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NoiseColor {
+    // equal power at every frequency
+    #[default]
+    White,
+    // equal power in every octave, -3dB an octave
+    Pink,
+    // -6dB an octave, integrated white noise
+    Brown,
+}
+
+pub struct NoiseHandle;
+impl ProcessorHandle for NoiseHandle {}
+
+fn seed<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) -> Random {
+    Random::new(runtime.instance_seed(handle))
+}
+
+// White, pink or brown noise, roughly within -1 and 1
+pub struct Noise {
+    color: NoiseColor,
+}
+
+impl Noise {
+    pub fn new(color: NoiseColor) -> Self {
+        Self { color }
+    }
+
+    pub fn audio_out(instance_name: &'static str) -> PortHandle<Output<'static>> {
+        PortHandle::new(instance_name, 0, TypeId::of::<Output>(), TypeId::of::<Noise>())
+    }
+}
+
+struct NoiseState {
+    color: NoiseColor,
+    random: Random,
+    // Paul Kellet's pink filter for pink, the integrator for brown
+    filter: [f32; 7],
+}
+
+impl NoiseState {
+    fn next(&mut self) -> f32 {
+        let white = self.random.bipolar();
+        let b = &mut self.filter;
+        match self.color {
+            NoiseColor::White => white,
+            NoiseColor::Pink => {
+                b[0] = 0.99886 * b[0] + white * 0.055_517_9;
+                b[1] = 0.99332 * b[1] + white * 0.075_075_9;
+                b[2] = 0.96900 * b[2] + white * 0.153_852;
+                b[3] = 0.86650 * b[3] + white * 0.310_485_6;
+                b[4] = 0.55000 * b[4] + white * 0.532_952_2;
+                b[5] = -0.7616 * b[5] - white * 0.016_898;
+                let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+                b[6] = white * 0.115_926;
+                pink * 0.2
+            },
+            NoiseColor::Brown => {
+                // leaks a little, so it wanders back to 0 instead of off
+                b[0] = (b[0] + 0.02 * white) / 1.02;
+                b[0] * 3.5
+            },
+        }
+    }
+}
+
+impl Processor for Noise {
+    type Handle = NoiseHandle;
+    fn buffers_count() -> usize { 1 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[PortDescriptor::output("audio_out")];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let mut audio_out = get_output(runtime, handle.buffer_ids_start);
+        let mut state = get_state::<NoiseState, E>(runtime, handle.slot_ids_start);
+        for out in audio_out.iter_mut() {
+            *out = state.next();
+        }
+    }
+    fn prepare<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle, _config: AudioConfig) {
+        Self::reset(runtime, handle);
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let mut state = get_state::<NoiseState, E>(runtime, handle.slot_ids_start);
+        state.random = seed(runtime, handle);
+        state.filter = [0.0; 7];
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        // seeded in prepare, once the instance is in a runtime
+        vec![Box::new(UnsafeCell::new(NoiseState {
+            color: self.color,
+            random: Random::new(0),
+            filter: [0.0; 7],
+        }))]
+    }
+    fn get_handle() -> NoiseHandle { NoiseHandle }
+}
+
+// Holds a sample of audio_in, or of white noise while it isn't routed,
+// until the next trigger. Without a trigger it's clocked at rate
+#[derive(Default)]
+pub struct SampleHold;
+
+impl SampleHold {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn audio_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 0, TypeId::of::<Input>(), TypeId::of::<SampleHold>())
+    }
+
+    pub fn trigger_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 1, TypeId::of::<Input>(), TypeId::of::<SampleHold>())
+    }
+
+    pub fn rate_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 2, TypeId::of::<Input>(), TypeId::of::<SampleHold>())
+    }
+
+    pub fn audio_out(instance_name: &'static str) -> PortHandle<Output<'static>> {
+        PortHandle::new(instance_name, 3, TypeId::of::<Output>(), TypeId::of::<SampleHold>())
+    }
+}
+
+struct SampleHoldState {
+    random: Random,
+    held: f32,
+    // the trigger's last value, to find its edges
+    trigger: f32,
+    // through the clock's cycle, a new sample is taken each time it wraps
+    phase: f32,
+}
+
+impl SampleHoldState {
+    fn new(random: Random) -> Self {
+        // the first sample is taken straight away
+        Self { random, held: 0.0, trigger: 0.0, phase: 1.0 }
+    }
+}
+
+impl Processor for SampleHold {
+    type Handle = NoiseHandle;
+    fn buffers_count() -> usize { 4 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            PortDescriptor::input("audio_in")
+                .description("What's sampled, white noise while it isn't routed"),
+            PortDescriptor::input("trigger")
+                .kind(PortKind::Event)
+                .description("Takes a sample when it rises past 0.5"),
+            PortDescriptor::input("rate")
+                .kind(PortKind::Control)
+                .default_value(10.0)
+                .range(0.0, 1000.0)
+                .unit("Hz")
+                .description("How often to sample while trigger isn't routed"),
+            PortDescriptor::output("audio_out"),
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let audio_in = get_input(runtime, handle.buffer_ids_start);
+        let trigger = get_input(runtime, handle.buffer_ids_start + 1);
+        let rate = get_input(runtime, handle.buffer_ids_start + 2);
+        let mut audio_out = get_output(runtime, handle.buffer_ids_start + 3);
+        let mut state = get_state::<SampleHoldState, E>(runtime, handle.slot_ids_start);
+        let sample_rate = runtime.sample_rate();
+
+        for (i, out) in audio_out.iter_mut().enumerate() {
+            let sample = match *trigger {
+                Some(trigger) => {
+                    let rising = trigger[i] >= 0.5 && state.trigger < 0.5;
+                    state.trigger = trigger[i];
+                    rising
+                },
+                None => {
+                    let wrapped = state.phase >= 1.0;
+                    state.phase = state.phase.fract() + value_at(*rate, i, 10.0).max(0.0) / sample_rate;
+                    wrapped
+                },
+            };
+            if sample {
+                let noise = state.random.bipolar();
+                state.held = audio_in.map_or(noise, |audio_in| audio_in[i]);
+            }
+            *out = state.held;
+        }
+    }
+    fn prepare<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle, _config: AudioConfig) {
+        Self::reset(runtime, handle);
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        *get_state::<SampleHoldState, E>(runtime, handle.slot_ids_start) = SampleHoldState::new(seed(runtime, handle));
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        vec![Box::new(UnsafeCell::new(SampleHoldState::new(Random::new(0))))]
+    }
+    fn get_handle() -> NoiseHandle { NoiseHandle }
+}
+
+// A random LFO that glides between random values from -1 to 1, reaching a
+// new one rate times a second, with no corners along the way
+#[derive(Default)]
+pub struct SmoothRandom;
+
+impl SmoothRandom {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn rate_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 0, TypeId::of::<Input>(), TypeId::of::<SmoothRandom>())
+    }
+
+    pub fn audio_out(instance_name: &'static str) -> PortHandle<Output<'static>> {
+        PortHandle::new(instance_name, 1, TypeId::of::<Output>(), TypeId::of::<SmoothRandom>())
+    }
+}
+
+struct SmoothRandomState {
+    random: Random,
+    from: f32,
+    to: f32,
+    // through the glide from one value to the next
+    phase: f32,
+}
+
+impl SmoothRandomState {
+    fn new(mut random: Random) -> Self {
+        let (from, to) = (random.bipolar(), random.bipolar());
+        Self { random, from, to, phase: 0.0 }
+    }
+}
+
+impl Processor for SmoothRandom {
+    type Handle = NoiseHandle;
+    fn buffers_count() -> usize { 2 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            PortDescriptor::input("rate")
+                .kind(PortKind::Control)
+                .default_value(1.0)
+                .range(0.0, 100.0)
+                .unit("Hz"),
+            PortDescriptor::output("audio_out"),
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let rate = get_input(runtime, handle.buffer_ids_start);
+        let mut audio_out = get_output(runtime, handle.buffer_ids_start + 1);
+        let mut state = get_state::<SmoothRandomState, E>(runtime, handle.slot_ids_start);
+        let sample_rate = runtime.sample_rate();
+
+        for (i, out) in audio_out.iter_mut().enumerate() {
+            // cosine easing, so each glide leaves and lands flat
+            let eased = 0.5 - 0.5 * (PI * state.phase).cos();
+            *out = state.from + (state.to - state.from) * eased;
+
+            state.phase += value_at(*rate, i, 1.0).max(0.0) / sample_rate;
+            while state.phase >= 1.0 {
+                state.phase -= 1.0;
+                state.from = state.to;
+                state.to = state.random.bipolar();
+            }
+        }
+    }
+    fn prepare<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle, _config: AudioConfig) {
+        Self::reset(runtime, handle);
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        *get_state::<SmoothRandomState, E>(runtime, handle.slot_ids_start) = SmoothRandomState::new(seed(runtime, handle));
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        vec![Box::new(UnsafeCell::new(SmoothRandomState::new(Random::new(0))))]
+    }
+    fn get_handle() -> NoiseHandle { NoiseHandle }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Builder;
+    use crate::testing::{Probe, ProcessorTest};
+
+    fn noise(color: NoiseColor, seed: u64) -> Vec<f32> {
+        let capture = ProcessorTest::new(Noise::new(color)).seed(seed).run(256);
+        capture.port("audio_out").unwrap().to_vec()
+    }
+
+    fn mean_square(samples: &[f32]) -> f32 {
+        samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32
+    }
+
+    // power of the differences over power, 2 for white noise and less the
+    // more a signal leans to low frequencies
+    fn brightness(samples: &[f32]) -> f32 {
+        let differences: Vec<f32> = samples.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        mean_square(&differences) / mean_square(samples)
+    }
+
+    #[test]
+    fn test_seeding() {
+        // the same seed plays the same samples, bit for bit
+        assert_eq!(noise(NoiseColor::Pink, 3), noise(NoiseColor::Pink, 3));
+        assert_ne!(noise(NoiseColor::Pink, 3), noise(NoiseColor::Pink, 4));
+
+        // instances in one graph get seeds of their own, and a reset starts
+        // them over
+        let (first, second) = (Probe::new(), Probe::new());
+        let (mut runtime, router) = Builder::<()>::new()
+            .seed(3)
+            .add_processor(Noise::new(NoiseColor::White), "first")
+            .add_processor(Noise::new(NoiseColor::White), "second")
+            .add_processor(first.clone(), "first_probe")
+            .add_processor(second.clone(), "second_probe")
+            .buffer_length(64)
+            .build();
+        router.route(Noise::audio_out("first"), Probe::input("first_probe")).unwrap();
+        router.route(Noise::audio_out("second"), Probe::input("second_probe")).unwrap();
+        let mut silence = [0.0; 64];
+        runtime.process(None, &mut silence);
+        assert_ne!(first.samples(), second.samples());
+
+        let played = first.samples();
+        runtime.reset();
+        first.clear();
+        runtime.process(None, &mut silence);
+        assert_eq!(first.samples(), played);
+    }
+
+    #[test]
+    fn test_colors() {
+        let (white, pink, brown) = (noise(NoiseColor::White, 1), noise(NoiseColor::Pink, 1), noise(NoiseColor::Brown, 1));
+        for samples in [&white, &pink, &brown] {
+            assert!(samples.iter().all(|sample| sample.abs() <= 1.5));
+            let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+            assert!(mean.abs() < 0.1, "{}", mean);
+        }
+        assert!((mean_square(&white) - 1.0 / 3.0).abs() < 0.01);
+        assert!((brightness(&white) - 2.0).abs() < 0.05);
+        assert!(brightness(&pink) < 1.0 && brightness(&pink) > 0.1, "{}", brightness(&pink));
+        assert!(brightness(&brown) < 0.05, "{}", brightness(&brown));
+    }
+
+    #[test]
+    fn test_sample_and_hold() {
+        let ramp: Vec<f32> = (0..64).map(|n| n as f32).collect();
+        let mut trigger = vec![0.0; 64];
+        trigger[10..20].fill(1.0);
+        trigger[40] = 1.0;
+        let capture = ProcessorTest::new(SampleHold::new())
+            .input("audio_in", &ramp)
+            .input("trigger", &trigger)
+            .run(1);
+        let out = capture.port("audio_out").unwrap();
+        assert_eq!(&out[..10], &[0.0; 10]);
+        assert_eq!(&out[10..40], &[10.0; 30]);
+        assert_eq!(&out[40..], &[40.0; 24]);
+
+        // clocked, it samples noise every sample_rate / rate samples
+        let capture = ProcessorTest::new(SampleHold::new())
+            .sample_rate(1000.0)
+            .parameter("rate", 100.0)
+            .run(1);
+        let out = capture.port("audio_out").unwrap();
+        for step in out.chunks(10) {
+            assert!(step.iter().all(|sample| *sample == step[0]));
+        }
+        assert_ne!(out[0], out[10]);
+    }
+
+    #[test]
+    fn test_smooth_random() {
+        let capture = ProcessorTest::new(SmoothRandom::new())
+            .sample_rate(1000.0)
+            .parameter("rate", 10.0)
+            .run(32);
+        let out = capture.port("audio_out").unwrap();
+        assert!(out.iter().all(|sample| (-1.0..=1.0).contains(sample)));
+        // a new value every 100 samples, reached without jumping
+        let steepest = out.windows(2).map(|pair| (pair[1] - pair[0]).abs()).fold(0.0, f32::max);
+        assert!(steepest < PI / 100.0 + 1e-4, "{}", steepest);
+        let values: Vec<f32> = out.iter().step_by(100).copied().collect();
+        assert!(values.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+}
@@ -0,0 +1,687 @@
+// Band-limited oscillators. Saw and pulse edges are smoothed with polyBLEP,
+// triangle corners with polyBLAMP, and wavetables play from mipmaps that
+// have no harmonics past Nyquist
+//
+// They all start with the same four inputs:
+//   frequency  in Hz, 440 while unrouted
+//   fm         Hz added to frequency, for audio-rate FM. When the sum goes
+//              negative the phase runs backwards, so FM can go through zero
+//   pm         cycles added to the phase, for phase modulation
+//   sync       restarts the cycle when it rises through zero, for hard sync
+//
+// Sync restarts are band-limited too. Each restart's step is smoothed over
+// the two samples after it, so nothing is held back and the oscillators
+// have no latency
+//
+//     let (runtime, router) = Builder::<()>::new()
+//         .add_processor(SawOsc::new(), "lead")
+//         .add_processor(SineOsc::new(), "modulator")
+//         .build();
+//     router.set_parameter(SawOsc::frequency_in("lead"), 220.0)?;
+//     router.route(SineOsc::audio_out("modulator"), SawOsc::fm_in("lead"))?;
+
+use std::f32::consts::TAU;
+use std::sync::Arc;
+use crate::core::processor::*;
+use super::value_at;
+
+const FREQUENCY: PortDescriptor = PortDescriptor::input("frequency")
+    .kind(PortKind::Control)
+    .default_value(440.0)
+    .range(0.0, 20_000.0)
+    .unit("Hz");
+const FM: PortDescriptor = PortDescriptor::input("fm")
+    .unit("Hz")
+    .description("Added to frequency, for audio-rate FM");
+const PM: PortDescriptor = PortDescriptor::input("pm")
+    .description("Cycles added to the phase");
+const SYNC: PortDescriptor = PortDescriptor::input("sync")
+    .description("Restarts the cycle when it rises through zero");
+const AUDIO_OUT: PortDescriptor = PortDescriptor::output("audio_out");
+
+// Port handles every oscillator has. audio_out is always the last port
+pub trait Oscillator: Processor {
+    fn frequency_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 0, TypeId::of::<Input>(), TypeId::of::<Self>())
+    }
+
+    fn fm_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 1, TypeId::of::<Input>(), TypeId::of::<Self>())
+    }
+
+    fn pm_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 2, TypeId::of::<Input>(), TypeId::of::<Self>())
+    }
+
+    fn sync_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 3, TypeId::of::<Input>(), TypeId::of::<Self>())
+    }
+
+    fn audio_out(instance_name: &'static str) -> PortHandle<Output<'static>> {
+        PortHandle::new(instance_name, Self::ports().len() - 1, TypeId::of::<Output>(), TypeId::of::<Self>())
+    }
+}
+
+pub struct OscHandle;
+impl ProcessorHandle for OscHandle {}
+
+#[derive(Default)]
+struct OscState {
+    // in cycles, where the next sample is read
+    phase: f64,
+    // the sync input's last sample, to find where it crosses zero
+    sync: f32,
+    // what's left of a sync restart's correction, for the next sample
+    pending: f32,
+}
+
+// What sets one oscillator apart from another
+trait Shape {
+    // The shape at phase t of sample i, band-limited for a phase increment of dt
+    fn band_limited(&self, i: usize, t: f32, dt: f32) -> f32;
+    // The shape without band-limiting, which sync steps are measured on
+    fn naive(&self, i: usize, t: f32) -> f32;
+}
+
+fn wrap(phase: f64) -> f64 {
+    phase.rem_euclid(1.0)
+}
+
+// A phase as read by shapes. Phases just under a whole cycle round up to
+// 1.0 in f32, which belongs to the next cycle
+fn cycle_position(phase: f64) -> f32 {
+    (wrap(phase) as f32).fract()
+}
+
+// Samples from a discontinuity at phase c to phase t, negative before it
+fn since(t: f32, c: f32, dt: f32) -> f32 {
+    let mut distance = t - c;
+    if distance >= 0.5 {
+        distance -= 1.0;
+    } else if distance < -0.5 {
+        distance += 1.0;
+    }
+    distance / dt.max(f32::EPSILON)
+}
+
+// polyBLEP residual of a unit step, s samples from it
+fn blep(s: f32) -> f32 {
+    if (0.0..1.0).contains(&s) {
+        -(1.0 - s) * (1.0 - s) / 2.0
+    } else if (-1.0..0.0).contains(&s) {
+        (1.0 + s) * (1.0 + s) / 2.0
+    } else {
+        0.0
+    }
+}
+
+// polyBLAMP residual of a unit change in slope per sample, s samples from it
+fn blamp(s: f32) -> f32 {
+    let r = 1.0 - s.abs();
+    if r > 0.0 { r * r * r / 6.0 } else { 0.0 }
+}
+
+// Runs the phase, modulation and sync every oscillator shares
+fn oscillate<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle, out_idx: usize, state: &mut OscState, shape: &impl Shape) {
+    let frequency = get_input(runtime, handle.buffer_ids_start);
+    let fm = get_input(runtime, handle.buffer_ids_start + 1);
+    let pm = get_input(runtime, handle.buffer_ids_start + 2);
+    let sync = get_input(runtime, handle.buffer_ids_start + 3);
+    let mut out = get_output(runtime, handle.buffer_ids_start + out_idx);
+    let sample_rate = runtime.sample_rate() as f64;
+
+    for i in 0..out.len() {
+        let hz = value_at(*frequency, i, 440.0) + value_at(*fm, i, 0.0);
+        let increment = hz as f64 / sample_rate;
+        let dt = (increment.abs() as f32).min(0.5);
+        let offset = value_at(*pm, i, 0.0) as f64;
+        let mut phase = state.phase;
+
+        // the step a restart makes, and how many samples ago it was
+        let mut restart = None;
+        if let Some(sync) = *sync {
+            let (last, now) = (state.sync, sync[i]);
+            state.sync = now;
+            if last <= 0.0 && now > 0.0 {
+                let ago = now / (now - last);
+                let crossed = cycle_position(phase - ago as f64 * increment + offset);
+                let step = shape.naive(i, cycle_position(offset)) - shape.naive(i, crossed);
+                phase = ago as f64 * increment;
+                restart = Some((step, ago));
+            }
+        }
+
+        // The restart's step gets the shape of a two-sample ramp starting
+        // where the sync crossed, ago² / 2 of the way there on this sample
+        // and 1 - (1 - ago)² / 2 on the next
+        let mut sample = shape.band_limited(i, cycle_position(phase + offset), dt) + std::mem::take(&mut state.pending);
+        if let Some((step, ago)) = restart {
+            sample += step * (ago * ago / 2.0 - 1.0);
+            state.pending = -step * (1.0 - ago) * (1.0 - ago) / 2.0;
+        }
+        out[i] = sample;
+        state.phase = wrap(phase + increment);
+    }
+}
+
+fn reset_state<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+    *get_state::<OscState, E>(runtime, handle.slot_ids_start) = OscState::default();
+}
+
+fn osc_states() -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+    vec![Box::new(UnsafeCell::new(OscState::default()))]
+}
+
+struct Saw;
+
+impl Shape for Saw {
+    fn band_limited(&self, i: usize, t: f32, dt: f32) -> f32 {
+        self.naive(i, t) - 2.0 * blep(since(t, 0.0, dt))
+    }
+    fn naive(&self, _: usize, t: f32) -> f32 {
+        2.0 * t - 1.0
+    }
+}
+
+struct Pulse<'a> {
+    width: Option<&'a [f32]>,
+}
+
+impl Pulse<'_> {
+    fn width(&self, i: usize) -> f32 {
+        value_at(self.width, i, 0.5).clamp(0.01, 0.99)
+    }
+}
+
+impl Shape for Pulse<'_> {
+    fn band_limited(&self, i: usize, t: f32, dt: f32) -> f32 {
+        let width = self.width(i);
+        self.naive(i, t) + 2.0 * blep(since(t, 0.0, dt)) - 2.0 * blep(since(t, width, dt))
+    }
+    fn naive(&self, i: usize, t: f32) -> f32 {
+        if t < self.width(i) { 1.0 } else { -1.0 }
+    }
+}
+
+struct Triangle;
+
+impl Shape for Triangle {
+    fn band_limited(&self, i: usize, t: f32, dt: f32) -> f32 {
+        // the slope turns by 8 cycles' worth at both corners
+        self.naive(i, t) + 8.0 * dt * (blamp(since(t, 0.0, dt)) - blamp(since(t, 0.5, dt)))
+    }
+    fn naive(&self, _: usize, t: f32) -> f32 {
+        if t < 0.5 { 4.0 * t - 1.0 } else { 3.0 - 4.0 * t }
+    }
+}
+
+struct Sine;
+
+impl Shape for Sine {
+    fn band_limited(&self, i: usize, t: f32, _: f32) -> f32 {
+        self.naive(i, t)
+    }
+    fn naive(&self, _: usize, t: f32) -> f32 {
+        (TAU * t).sin()
+    }
+}
+
+// polyBLEP sawtooth, rising from -1 to 1
+#[derive(Default)]
+pub struct SawOsc;
+
+impl SawOsc {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Oscillator for SawOsc {}
+
+impl Processor for SawOsc {
+    type Handle = OscHandle;
+    fn buffers_count() -> usize { 5 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[FREQUENCY, FM, PM, SYNC, AUDIO_OUT];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let mut state = get_state::<OscState, E>(runtime, handle.slot_ids_start);
+        oscillate(runtime, handle, 4, &mut state, &Saw);
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        reset_state(runtime, handle);
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> { osc_states() }
+    fn get_handle() -> OscHandle { OscHandle }
+}
+
+// polyBLEP pulse, high for the first width of each cycle. A width of 0.5
+// makes a square
+#[derive(Default)]
+pub struct PulseOsc;
+
+impl PulseOsc {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn width_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 4, TypeId::of::<Input>(), TypeId::of::<PulseOsc>())
+    }
+}
+
+impl Oscillator for PulseOsc {}
+
+impl Processor for PulseOsc {
+    type Handle = OscHandle;
+    fn buffers_count() -> usize { 6 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            FREQUENCY,
+            FM,
+            PM,
+            SYNC,
+            PortDescriptor::input("width")
+                .kind(PortKind::Control)
+                .default_value(0.5)
+                .range(0.01, 0.99)
+                .description("Part of each cycle spent high, for PWM"),
+            AUDIO_OUT,
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let width = get_input(runtime, handle.buffer_ids_start + 4);
+        let mut state = get_state::<OscState, E>(runtime, handle.slot_ids_start);
+        oscillate(runtime, handle, 5, &mut state, &Pulse { width: *width });
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        reset_state(runtime, handle);
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> { osc_states() }
+    fn get_handle() -> OscHandle { OscHandle }
+}
+
+// polyBLAMP triangle, rising from -1 over the first half of each cycle
+#[derive(Default)]
+pub struct TriangleOsc;
+
+impl TriangleOsc {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Oscillator for TriangleOsc {}
+
+impl Processor for TriangleOsc {
+    type Handle = OscHandle;
+    fn buffers_count() -> usize { 5 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[FREQUENCY, FM, PM, SYNC, AUDIO_OUT];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let mut state = get_state::<OscState, E>(runtime, handle.slot_ids_start);
+        oscillate(runtime, handle, 4, &mut state, &Triangle);
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        reset_state(runtime, handle);
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> { osc_states() }
+    fn get_handle() -> OscHandle { OscHandle }
+}
+
+#[derive(Default)]
+pub struct SineOsc;
+
+impl SineOsc {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Oscillator for SineOsc {}
+
+impl Processor for SineOsc {
+    type Handle = OscHandle;
+    fn buffers_count() -> usize { 5 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[FREQUENCY, FM, PM, SYNC, AUDIO_OUT];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let mut state = get_state::<OscState, E>(runtime, handle.slot_ids_start);
+        oscillate(runtime, handle, 4, &mut state, &Sine);
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        reset_state(runtime, handle);
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> { osc_states() }
+    fn get_handle() -> OscHandle { OscHandle }
+}
+
+// Every frame is resampled to this many samples per mipmap level
+const TABLE_SIZE: usize = 2048;
+// Each level keeps half the harmonics of the one before, down to just the
+// fundamental
+const LEVELS: usize = 11;
+
+// Single-cycle frames a WavetableOsc moves through with its position input.
+// Frames are turned into band-limited mipmaps when the table is built,
+// which takes a while, so build it off the audio thread and share it
+pub struct Wavetable {
+    // [frame][level], each with a copy of its first sample on the end
+    frames: Vec<Vec<Vec<f32>>>,
+}
+
+impl Wavetable {
+    // Frames can be any length, each holding one cycle. Their DC offset
+    // is dropped, as are harmonics a frame is too short to hold
+    pub fn new(frames: &[Vec<f32>]) -> Arc<Self> {
+        Arc::new(Self { frames: frames.iter().map(|frame| mipmaps(frame)).collect() })
+    }
+
+    // Splits a recording into frames of frame_length, the way wavetable
+    // files are usually laid out. A partial frame at the end is dropped
+    pub fn from_samples(samples: &[f32], frame_length: usize) -> Arc<Self> {
+        let frames: Vec<Vec<f32>> = samples.chunks_exact(frame_length.max(1)).map(<[f32]>::to_vec).collect();
+        Self::new(&frames)
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    // position runs from the first frame at 0.0 to the last at 1.0
+    fn read(&self, level: usize, position: f32, t: f32) -> f32 {
+        let Some(last) = self.frames.len().checked_sub(1) else {
+            return 0.0;
+        };
+        let frame = position.clamp(0.0, 1.0) * last as f32;
+        let below = (frame as usize).min(last);
+        let above = (below + 1).min(last);
+        let mix = frame - below as f32;
+        let a = read_table(&self.frames[below][level], t);
+        let b = read_table(&self.frames[above][level], t);
+        a + (b - a) * mix
+    }
+}
+
+// The highest harmonic a mipmap level keeps
+fn level_harmonics(level: usize) -> usize {
+    ((TABLE_SIZE / 2) >> level).min(TABLE_SIZE / 2 - 1)
+}
+
+// The fullest level with nothing at or past Nyquist for this increment
+fn level_for(dt: f32) -> usize {
+    (0..LEVELS)
+        .find(|&level| level_harmonics(level) as f32 * dt < 0.5)
+        .unwrap_or(LEVELS - 1)
+}
+
+fn read_table(table: &[f32], t: f32) -> f32 {
+    let position = t * TABLE_SIZE as f32;
+    let idx = (position as usize).min(TABLE_SIZE - 1);
+    let fraction = position - idx as f32;
+    table[idx] + (table[idx + 1] - table[idx]) * fraction
+}
+
+// Measures a frame's harmonics with a DFT, then adds them back up at
+// TABLE_SIZE once per level, keeping fewer each time
+fn mipmaps(frame: &[f32]) -> Vec<Vec<f32>> {
+    let len = frame.len();
+    let harmonics = (len.saturating_sub(1) / 2).min(TABLE_SIZE / 2 - 1);
+    let angle = |idx: usize, size: usize| TAU * (idx % size) as f32 / size as f32;
+
+    let coefficients: Vec<(f32, f32)> = (1..=harmonics)
+        .map(|k| frame.iter().enumerate().fold((0.0, 0.0), |(cos, sin), (n, sample)| {
+            let angle = angle(k * n, len);
+            (cos + sample * angle.cos(), sin + sample * angle.sin())
+        }))
+        .map(|(cos, sin)| (cos * 2.0 / len as f32, sin * 2.0 / len as f32))
+        .collect();
+
+    let cos: Vec<f32> = (0..TABLE_SIZE).map(|idx| angle(idx, TABLE_SIZE).cos()).collect();
+    let sin: Vec<f32> = (0..TABLE_SIZE).map(|idx| angle(idx, TABLE_SIZE).sin()).collect();
+    (0..LEVELS)
+        .map(|level| {
+            let kept = &coefficients[..level_harmonics(level).min(harmonics)];
+            let mut table: Vec<f32> = (0..TABLE_SIZE)
+                .map(|n| kept.iter().enumerate().map(|(k, (a, b))| {
+                    let idx = (k + 1) * n % TABLE_SIZE;
+                    a * cos[idx] + b * sin[idx]
+                }).sum())
+                .collect();
+            table.push(table[0]);
+            table
+        })
+        .collect()
+}
+
+struct TableShape<'a> {
+    table: &'a Wavetable,
+    position: Option<&'a [f32]>,
+}
+
+impl Shape for TableShape<'_> {
+    fn band_limited(&self, i: usize, t: f32, dt: f32) -> f32 {
+        self.table.read(level_for(dt), value_at(self.position, i, 0.0), t)
+    }
+    fn naive(&self, i: usize, t: f32) -> f32 {
+        self.table.read(0, value_at(self.position, i, 0.0), t)
+    }
+}
+
+// Plays a Wavetable, picking the mipmap level for its frequency so nothing
+// aliases
+pub struct WavetableOsc {
+    table: Arc<Wavetable>,
+}
+
+impl WavetableOsc {
+    pub fn new(table: Arc<Wavetable>) -> Self {
+        Self { table }
+    }
+
+    pub fn position_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 4, TypeId::of::<Input>(), TypeId::of::<WavetableOsc>())
+    }
+}
+
+impl Oscillator for WavetableOsc {}
+
+impl Processor for WavetableOsc {
+    type Handle = OscHandle;
+    fn buffers_count() -> usize { 6 }
+    fn slot_count() -> usize { 2 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            FREQUENCY,
+            FM,
+            PM,
+            SYNC,
+            PortDescriptor::input("position")
+                .kind(PortKind::Control)
+                .range(0.0, 1.0)
+                .description("Moves from the first frame to the last"),
+            AUDIO_OUT,
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let position = get_input(runtime, handle.buffer_ids_start + 4);
+        let mut state = get_state::<OscState, E>(runtime, handle.slot_ids_start);
+        let table = get_state::<Arc<Wavetable>, E>(runtime, handle.slot_ids_start + 1);
+        oscillate(runtime, handle, 5, &mut state, &TableShape { table: &table, position: *position });
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        reset_state(runtime, handle);
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        let mut states = osc_states();
+        states.push(Box::new(UnsafeCell::new(Arc::clone(&self.table))));
+        states
+    }
+    fn get_handle() -> OscHandle { OscHandle }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::ProcessorTest;
+
+    fn run<P: Processor>(test: ProcessorTest<P>, blocks: usize) -> Vec<f32> {
+        test.run(blocks).port("audio_out").unwrap().to_vec()
+    }
+
+    fn rising_crossings(signal: &[f32]) -> usize {
+        signal.windows(2).filter(|pair| pair[0] <= 0.0 && pair[1] > 0.0).count()
+    }
+
+    // Energy off the harmonics of fundamental_bin over energy on them, from
+    // a DFT over the whole signal
+    fn alias_ratio(signal: &[f32], fundamental_bin: usize) -> f64 {
+        let len = signal.len();
+        let (mut harmonic, mut inharmonic) = (0.0, 0.0);
+        for k in 1..len / 2 {
+            let (mut re, mut im) = (0.0f64, 0.0f64);
+            for (n, sample) in signal.iter().enumerate() {
+                let angle = std::f64::consts::TAU * (k * n % len) as f64 / len as f64;
+                re += *sample as f64 * angle.cos();
+                im += *sample as f64 * angle.sin();
+            }
+            if k % fundamental_bin == 0 {
+                harmonic += re * re + im * im;
+            } else {
+                inharmonic += re * re + im * im;
+            }
+        }
+        inharmonic / harmonic
+    }
+
+    #[test]
+    fn test_frequency_follows_sample_rate() {
+        for sample_rate in [44_100.0, 48_000.0, 96_000.0] {
+            let out = run(ProcessorTest::new(SineOsc::new())
+                .sample_rate(sample_rate)
+                .parameter("frequency", 1000.0), (sample_rate / 64.0).ceil() as usize);
+            // the last block can run a little past a second
+            assert!((1000..=1002).contains(&rising_crossings(&out)), "at {}Hz", sample_rate);
+        }
+    }
+
+    #[test]
+    fn test_fm_and_pm() {
+        let fm = run(ProcessorTest::new(SineOsc::new())
+            .parameter("frequency", 600.0)
+            .input("fm", &[400.0; 48_000]), 750);
+        assert!((1000..=1001).contains(&rising_crossings(&fm)));
+
+        // a quarter cycle ahead turns the sine into a cosine
+        let pm = run(ProcessorTest::new(SineOsc::new())
+            .parameter("frequency", 1000.0)
+            .input("pm", &[0.25; 64]), 1);
+        assert_eq!(SineOsc::new().latency(), 0);
+        for (n, sample) in pm.iter().enumerate() {
+            let expected = (TAU * 1000.0 * n as f32 / 48_000.0).cos();
+            assert!((sample - expected).abs() < 1e-4, "{}: {} != {}", n, sample, expected);
+        }
+    }
+
+    #[test]
+    fn test_band_limiting() {
+        // 1250Hz fits 25 cycles in 960 samples, so harmonics land on every
+        // 25th bin and aliases between them
+        let naive = |shape: &dyn Shape| -> Vec<f32> {
+            (0..960).map(|n| shape.naive(0, (n as f32 * 1250.0 / 48_000.0).fract())).collect()
+        };
+        let cases = [
+            ("saw", naive(&Saw), run(ProcessorTest::new(SawOsc::new()).parameter("frequency", 1250.0), 16)),
+            ("square", naive(&Pulse { width: None }), run(ProcessorTest::new(PulseOsc::new()).parameter("frequency", 1250.0), 16)),
+            ("triangle", naive(&Triangle), run(ProcessorTest::new(TriangleOsc::new()).parameter("frequency", 1250.0), 16)),
+        ];
+
+        for (name, naive, out) in cases {
+            let (naive, band_limited) = (alias_ratio(&naive, 25), alias_ratio(&out[..960], 25));
+            assert!(band_limited < naive / 10.0, "{}: {} vs naive {}", name, band_limited, naive);
+            assert!(out.iter().all(|sample| sample.abs() <= 1.1), "{} overshoots", name);
+        }
+    }
+
+    #[test]
+    fn test_pulse_width() {
+        let out = run(ProcessorTest::new(PulseOsc::new())
+            .parameter("frequency", 480.0)
+            .parameter("width", 0.25), 16);
+        // a quarter of each 100 sample cycle is high, away from the edges
+        let high = out[..1000].iter().filter(|sample| **sample > 0.5).count();
+        assert_eq!(high, 240);
+    }
+
+    #[test]
+    fn test_hard_sync() {
+        // restarted every 480 samples, the saw repeats every 480 samples
+        // even though 440Hz doesn't
+        let master: Vec<f32> = (0..4096).map(|n| if n % 480 < 240 { 1.0 } else { -1.0 }).collect();
+        let synced = run(ProcessorTest::new(SawOsc::new()).parameter("frequency", 440.0).input("sync", &master), 64);
+        let free = run(ProcessorTest::new(SawOsc::new()).parameter("frequency", 440.0), 64);
+
+        let repeats = |out: &[f32]| (0..480).all(|n| (out[500 + n] - out[980 + n]).abs() < 1e-3);
+        assert!(repeats(&synced));
+        assert!(!repeats(&free));
+        // the restarts are smoothed without overshooting
+        assert!(synced.iter().all(|sample| sample.abs() <= 1.0));
+
+        // 11 restarts in 960 samples, between samples, alias far less than
+        // restarting a naive saw
+        let period = 960.0 / 11.0;
+        let master: Vec<f32> = (0..1920).map(|n| (TAU * n as f32 / period).sin()).collect();
+        let synced = run(ProcessorTest::new(SawOsc::new()).parameter("frequency", 1370.0).input("sync", &master), 30);
+        let naive: Vec<f32> = (960..1920)
+            .map(|n| Saw.naive(0, ((n as f32 % period) * 1370.0 / 48_000.0).fract()))
+            .collect();
+        let (naive, band_limited) = (alias_ratio(&naive, 11), alias_ratio(&synced[960..1920], 11));
+        assert!(band_limited < naive / 5.0, "{} vs naive {}", band_limited, naive);
+    }
+
+    #[test]
+    fn test_wavetable() {
+        let sine: Vec<f32> = (0..TABLE_SIZE).map(|n| (TAU * n as f32 / TABLE_SIZE as f32).sin()).collect();
+        let table = Wavetable::new(&[sine]);
+        let out = run(ProcessorTest::new(WavetableOsc::new(table)).parameter("frequency", 1000.0), 4);
+        let reference = run(ProcessorTest::new(SineOsc::new()).parameter("frequency", 1000.0), 4);
+        for (found, expected) in out.iter().zip(&reference) {
+            assert!((found - expected).abs() < 1e-3, "{} != {}", found, expected);
+        }
+
+        // a naive saw frame is band-limited on the way in
+        let saw: Vec<f32> = (0..TABLE_SIZE).map(|n| 2.0 * n as f32 / TABLE_SIZE as f32 - 1.0).collect();
+        let table = Wavetable::from_samples(&saw, TABLE_SIZE);
+        assert_eq!(table.frame_count(), 1);
+        let out = run(ProcessorTest::new(WavetableOsc::new(table)).parameter("frequency", 1250.0), 16);
+        assert!(alias_ratio(&out[..960], 25) < 1e-4);
+    }
+
+    #[test]
+    fn test_wavetable_position() {
+        let frames = [vec![1.0, 1.0, -1.0, -1.0], vec![-1.0, -1.0, 1.0, 1.0]];
+        let table = Wavetable::new(&frames);
+        let first = run(ProcessorTest::new(WavetableOsc::new(table.clone())).parameter("frequency", 100.0), 1);
+        let middle = run(ProcessorTest::new(WavetableOsc::new(table.clone()))
+            .parameter("frequency", 100.0)
+            .parameter("position", 0.5), 1);
+        let last = run(ProcessorTest::new(WavetableOsc::new(table))
+            .parameter("frequency", 100.0)
+            .parameter("position", 1.0), 1);
+        for n in 0..64 {
+            assert!((first[n] + last[n]).abs() < 1e-5);
+            assert!(middle[n].abs() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,358 @@
+// Reverb. Plate is Dattorro's plate: the input is predelayed, band-limited
+// and smeared by four allpasses, then circulates around a tank of two
+// cross-coupled halves whose first allpasses are slowly modulated. Both
+// outputs are sums of taps across the tank, which decorrelates them
+//
+//     let (runtime, router) = Builder::<()>::new()
+//         .add_processor(SawOsc::new(), "lead")
+//         .add_processor(Plate::new(), "plate")
+//         .build();
+//     router.route(SawOsc::audio_out("lead"), Plate::left_in("plate"))?;
+//     router.route(Plate::left_out("plate"), output())?;
+
+use std::f32::consts::TAU;
+use crate::core::processor::*;
+use super::delay::{DelayLine, Interpolation};
+use super::value_at;
+
+// The delays in Dattorro's paper are in samples at this rate
+const PLATE_RATE: f32 = 29_761.0;
+
+// length and coefficient of each input diffuser
+const DIFFUSERS: [(f32, f32); 4] = [(142.0, 0.75), (107.0, 0.75), (379.0, 0.625), (277.0, 0.625)];
+
+// the lengths in each half of the tank: modulated allpass, delay, allpass,
+// delay
+const HALVES: [[f32; 4]; 2] = [[672.0, 4453.0, 1800.0, 3720.0], [908.0, 4217.0, 2656.0, 3163.0]];
+
+// how far and how fast the modulated allpasses move
+const EXCURSION: f32 = 16.0;
+const MODULATION_HZ: f32 = 1.0;
+
+// size scales the tank between these
+const SMALLEST: f32 = 0.5;
+const LARGEST: f32 = 1.5;
+
+const MAX_PREDELAY: f32 = 0.5;
+
+// one half of the tank
+struct Half {
+    modulated: DelayLine,
+    first: DelayLine,
+    damped: f32,
+    diffuser: DelayLine,
+    second: DelayLine,
+}
+
+impl Half {
+    fn new(lengths: [f32; 4], scale: f32) -> Self {
+        let line = |length: f32, margin: f32| DelayLine::new(((length * LARGEST + margin) * scale).ceil() as usize, Interpolation::Linear);
+        Self {
+            modulated: line(lengths[0], EXCURSION + 1.0),
+            first: line(lengths[1], 1.0),
+            damped: 0.0,
+            diffuser: line(lengths[2], 1.0),
+            second: line(lengths[3], 1.0),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.modulated.clear();
+        self.first.clear();
+        self.damped = 0.0;
+        self.diffuser.clear();
+        self.second.clear();
+    }
+}
+
+// Schroeder allpass around a delay line
+fn allpass(line: &mut DelayLine, input: f32, delay: f32, coefficient: f32) -> f32 {
+    let delayed = line.read(delay);
+    let written = input + coefficient * delayed;
+    line.write(written);
+    delayed - coefficient * written
+}
+
+struct PlateState {
+    predelay: DelayLine,
+    // the input's band-limiting one-pole
+    bandwidth: f32,
+    diffusers: [DelayLine; 4],
+    halves: [Half; 2],
+    // of the allpass modulation, in cycles
+    phase: f32,
+}
+
+impl PlateState {
+    fn new(sample_rate: f32) -> Self {
+        let scale = sample_rate / PLATE_RATE;
+        Self {
+            predelay: DelayLine::new((MAX_PREDELAY * sample_rate).ceil() as usize + 1, Interpolation::Linear),
+            bandwidth: 0.0,
+            diffusers: DIFFUSERS.map(|(length, _)| DelayLine::new((length * scale).ceil() as usize + 1, Interpolation::Linear)),
+            halves: HALVES.map(|lengths| Half::new(lengths, scale)),
+            phase: 0.0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.predelay.clear();
+        self.bandwidth = 0.0;
+        for diffuser in &mut self.diffusers {
+            diffuser.clear();
+        }
+        for half in &mut self.halves {
+            half.clear();
+        }
+        self.phase = 0.0;
+    }
+}
+
+// A stereo plate reverb. Mono input goes in on the left, or both inputs
+// are mixed when the right one's routed too
+#[derive(Default)]
+pub struct Plate;
+
+pub struct PlateHandle;
+impl ProcessorHandle for PlateHandle {}
+
+impl Plate {
+    pub fn new() -> Self {
+        Self
+    }
+
+    // The ports of the plate added under this instance name
+    pub fn left_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 0, TypeId::of::<Input>(), TypeId::of::<Plate>())
+    }
+
+    pub fn right_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 1, TypeId::of::<Input>(), TypeId::of::<Plate>())
+    }
+
+    pub fn size_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 2, TypeId::of::<Input>(), TypeId::of::<Plate>())
+    }
+
+    pub fn decay_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 3, TypeId::of::<Input>(), TypeId::of::<Plate>())
+    }
+
+    pub fn damping_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 4, TypeId::of::<Input>(), TypeId::of::<Plate>())
+    }
+
+    pub fn predelay_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 5, TypeId::of::<Input>(), TypeId::of::<Plate>())
+    }
+
+    pub fn mix_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 6, TypeId::of::<Input>(), TypeId::of::<Plate>())
+    }
+
+    pub fn left_out(instance_name: &'static str) -> PortHandle<Output<'static>> {
+        PortHandle::new(instance_name, 7, TypeId::of::<Output>(), TypeId::of::<Plate>())
+    }
+
+    pub fn right_out(instance_name: &'static str) -> PortHandle<Output<'static>> {
+        PortHandle::new(instance_name, 8, TypeId::of::<Output>(), TypeId::of::<Plate>())
+    }
+}
+
+impl Processor for Plate {
+    type Handle = PlateHandle;
+    fn buffers_count() -> usize { 9 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            PortDescriptor::input("left_in"),
+            PortDescriptor::input("right_in"),
+            PortDescriptor::input("size")
+                .kind(PortKind::Control)
+                .default_value(0.5)
+                .range(0.0, 1.0)
+                .description("Scales the tank's delays"),
+            PortDescriptor::input("decay")
+                .kind(PortKind::Control)
+                .default_value(0.5)
+                .range(0.0, 0.99)
+                .description("How much of the tank's signal survives each trip around it"),
+            PortDescriptor::input("damping")
+                .kind(PortKind::Control)
+                .default_value(0.3)
+                .range(0.0, 1.0)
+                .description("How quickly high frequencies die away"),
+            PortDescriptor::input("predelay")
+                .kind(PortKind::Control)
+                .range(0.0, MAX_PREDELAY)
+                .unit("s"),
+            PortDescriptor::input("mix")
+                .kind(PortKind::Control)
+                .default_value(0.3)
+                .range(0.0, 1.0)
+                .description("From only the dry signal at 0 to only the reverb at 1"),
+            PortDescriptor::output("left"),
+            PortDescriptor::output("right"),
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let left_in = get_input(runtime, handle.buffer_ids_start);
+        let right_in = get_input(runtime, handle.buffer_ids_start + 1);
+        let size = get_input(runtime, handle.buffer_ids_start + 2);
+        let decay = get_input(runtime, handle.buffer_ids_start + 3);
+        let damping = get_input(runtime, handle.buffer_ids_start + 4);
+        let predelay = get_input(runtime, handle.buffer_ids_start + 5);
+        let mix = get_input(runtime, handle.buffer_ids_start + 6);
+        let mut left_out = get_output(runtime, handle.buffer_ids_start + 7);
+        let mut right_out = get_output(runtime, handle.buffer_ids_start + 8);
+        let mut state = get_state::<PlateState, E>(runtime, handle.slot_ids_start);
+        let state = &mut *state;
+        let sample_rate = runtime.sample_rate();
+        let scale = sample_rate / PLATE_RATE;
+
+        for i in 0..left_out.len() {
+            let (left, right) = (value_at(*left_in, i, 0.0), value_at(*right_in, i, 0.0));
+            let input = if right_in.is_some() { 0.5 * (left + right) } else { left };
+            let tank = scale * (SMALLEST + (LARGEST - SMALLEST) * value_at(*size, i, 0.5).clamp(0.0, 1.0));
+            let decay = value_at(*decay, i, 0.5).clamp(0.0, 0.99);
+            let damping = value_at(*damping, i, 0.3).clamp(0.0, 1.0);
+            let mix = value_at(*mix, i, 0.3).clamp(0.0, 1.0);
+
+            // written first, so no predelay reads what was just written
+            state.predelay.write(input);
+            let predelay = value_at(*predelay, i, 0.0).clamp(0.0, MAX_PREDELAY) * sample_rate;
+            let delayed = state.predelay.read(predelay + 1.0);
+
+            state.bandwidth += 0.9995 * (delayed - state.bandwidth);
+            let mut diffused = state.bandwidth;
+            for (line, (length, coefficient)) in state.diffusers.iter_mut().zip(DIFFUSERS) {
+                diffused = allpass(line, diffused, length * scale, coefficient);
+            }
+
+            // each half is fed the other's output, from before this sample
+            let ends = [
+                decay * state.halves[0].second.read(HALVES[0][3] * tank),
+                decay * state.halves[1].second.read(HALVES[1][3] * tank),
+            ];
+            let decay_diffusion = (decay + 0.15).clamp(0.25, 0.5);
+            state.phase = (state.phase + MODULATION_HZ / sample_rate).fract();
+            for (n, half) in state.halves.iter_mut().enumerate() {
+                let lengths = HALVES[n];
+                // the halves' modulation is a quarter cycle apart
+                let wobble = EXCURSION * scale * (TAU * (state.phase + 0.25 * n as f32)).sin();
+                let smeared = allpass(&mut half.modulated, diffused + ends[1 - n], lengths[0] * tank + wobble, -0.7);
+                let delayed = half.first.read(lengths[1] * tank);
+                half.first.write(smeared);
+                half.damped += (1.0 - damping) * (delayed - half.damped);
+                let diffused = allpass(&mut half.diffuser, decay * half.damped, lengths[2] * tank, decay_diffusion);
+                half.second.write(diffused);
+            }
+
+            // the paper's output taps, scaled with the tank
+            let [l, r] = &mut state.halves;
+            let tap = |line: &mut DelayLine, at: f32| line.read(at * tank);
+            let wet_left = tap(&mut r.first, 266.0) + tap(&mut r.first, 2974.0) - tap(&mut r.diffuser, 1913.0)
+                + tap(&mut r.second, 1996.0) - tap(&mut l.first, 1990.0) - tap(&mut l.diffuser, 187.0)
+                - tap(&mut l.second, 1066.0);
+            let wet_right = tap(&mut l.first, 353.0) + tap(&mut l.first, 3627.0) - tap(&mut l.diffuser, 1228.0)
+                + tap(&mut l.second, 2673.0) - tap(&mut r.first, 2111.0) - tap(&mut r.diffuser, 335.0)
+                - tap(&mut r.second, 121.0);
+
+            let (dry_left, dry_right) = if right_in.is_some() { (left, right) } else { (left, left) };
+            left_out[i] = dry_left * (1.0 - mix) + 0.6 * wet_left * mix;
+            right_out[i] = dry_right * (1.0 - mix) + 0.6 * wet_right * mix;
+        }
+    }
+    fn prepare<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle, config: AudioConfig) {
+        *get_state::<PlateState, E>(runtime, handle.slot_ids_start) = PlateState::new(config.sample_rate);
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        get_state::<PlateState, E>(runtime, handle.slot_ids_start).clear();
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        // sized for real in prepare, once the sample rate is known
+        vec![Box::new(UnsafeCell::new(PlateState::new(1.0)))]
+    }
+    fn get_handle() -> PlateHandle { PlateHandle }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Capture, ProcessorTest};
+
+    fn impulse_response(plate: ProcessorTest<Plate>, seconds: usize) -> Capture {
+        plate.parameter("mix", 1.0).input("left_in", &[1.0]).run(seconds * 48_000 / 64)
+    }
+
+    fn energy(samples: &[f32]) -> f32 {
+        samples.iter().map(|sample| sample * sample).sum()
+    }
+
+    #[test]
+    fn test_impulse_response() {
+        let capture = impulse_response(ProcessorTest::new(Plate::new()), 2);
+        let (left, right) = (capture.port("left").unwrap(), capture.port("right").unwrap());
+        assert!(left.iter().chain(right).all(|sample| sample.is_finite()));
+
+        // a dense, decaying tail that's different on each side
+        let early = energy(&left[..24_000]);
+        assert!(early > 0.01);
+        assert!(energy(&left[48_000..72_000]) < early * 0.1);
+        let correlation = left.iter().zip(right).map(|(l, r)| l * r).sum::<f32>() / (early.sqrt() * energy(&right[..24_000]).sqrt());
+        assert!(correlation.abs() < 0.5, "{}", correlation);
+    }
+
+    #[test]
+    fn test_decay_and_damping() {
+        let tail = |plate: ProcessorTest<Plate>| {
+            let capture = impulse_response(plate, 2);
+            capture.port("left").unwrap()[48_000..].to_vec()
+        };
+        let plate = || ProcessorTest::new(Plate::new());
+        assert!(energy(&tail(plate().parameter("decay", 0.9))) > 10.0 * energy(&tail(plate().parameter("decay", 0.3))));
+
+        // damping takes the top end out first, which the differences show
+        let brightness = |tail: Vec<f32>| energy(&tail.windows(2).map(|pair| pair[1] - pair[0]).collect::<Vec<_>>()) / energy(&tail);
+        let (dark, bright) = (
+            brightness(tail(plate().parameter("decay", 0.9).parameter("damping", 0.8))),
+            brightness(tail(plate().parameter("decay", 0.9).parameter("damping", 0.0))),
+        );
+        assert!(dark < bright * 0.5, "{} {}", dark, bright);
+    }
+
+    #[test]
+    fn test_predelay() {
+        let capture = impulse_response(ProcessorTest::new(Plate::new()), 1);
+        let delayed = impulse_response(ProcessorTest::new(Plate::new()).parameter("predelay", 0.01), 1);
+        // the tank's modulation doesn't wait for the predelay, so only the
+        // onset lines up exactly
+        let onset = |capture: &Capture| capture.port("left").unwrap().iter().position(|sample| *sample != 0.0).unwrap();
+        assert_eq!(onset(&delayed), onset(&capture) + 480);
+    }
+
+    #[test]
+    fn test_mix_and_inputs() {
+        // fully dry passes the input through, mono on both sides
+        let signal: Vec<f32> = (0..64).map(|n| n as f32 / 64.0).collect();
+        let capture = ProcessorTest::new(Plate::new())
+            .parameter("mix", 0.0)
+            .input("left_in", &signal)
+            .run(1);
+        assert_eq!(capture.port("left").unwrap(), &signal[..]);
+        assert_eq!(capture.port("right").unwrap(), &signal[..]);
+
+        // stereo input mixed down is the same as its mono sum
+        let stereo = ProcessorTest::new(Plate::new())
+            .parameter("mix", 1.0)
+            .input("left_in", &[1.0])
+            .input("right_in", &[0.0])
+            .run(200);
+        let mono = ProcessorTest::new(Plate::new())
+            .parameter("mix", 1.0)
+            .input("left_in", &[0.5])
+            .run(200);
+        assert_eq!(stereo.port("left").unwrap(), mono.port("left").unwrap());
+    }
+}
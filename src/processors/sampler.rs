@@ -0,0 +1,575 @@
+// Plays audio from memory. A Sample is decoded once, off the audio thread,
+// and instances share it through an Arc instead of copying it into state
+//
+//     let kick = Sample::load("kick.flac")?;
+//     let (runtime, router) = Builder::<SamplerCommand>::new()
+//         .add_processor(Sampler::new(kick.clone()), "kick")
+//         .add_processor(Sampler::new(kick).looping(true), "kick_loop")
+//         .build();
+//     router.route(Sampler::left_out("kick"), output())?;
+//     router.send_event(SamplerCommand::Play);
+
+use std::sync::Arc;
+use crate::core::processor::*;
+use super::events_of;
+
+#[cfg(feature = "audio-files")]
+use std::fmt::{self, Display, Formatter};
+#[cfg(feature = "audio-files")]
+use std::path::Path;
+#[cfg(feature = "audio-files")]
+use std::thread::JoinHandle;
+
+// Decoded audio, one Vec per channel, all the same length
+#[derive(Debug)]
+pub struct Sample {
+    channels: Vec<Vec<f32>>,
+    sample_rate: f32,
+}
+
+impl Sample {
+    // Shorter channels are padded with silence
+    pub fn new(mut channels: Vec<Vec<f32>>, sample_rate: f32) -> Arc<Self> {
+        let frames = channels.iter().map(Vec::len).max().unwrap_or(0);
+        for channel in &mut channels {
+            channel.resize(frames, 0.0);
+        }
+        Arc::new(Self { channels, sample_rate })
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+
+    pub fn frames(&self) -> usize {
+        self.channels.first().map_or(0, Vec::len)
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    pub fn channel(&self, idx: usize) -> Option<&[f32]> {
+        self.channels.get(idx).map(Vec::as_slice)
+    }
+}
+
+#[cfg(feature = "audio-files")]
+impl Sample {
+    // Decodes a whole WAV, FLAC or AIFF file. It reads from disk and
+    // allocates, so call it from a loader thread, never the audio thread
+    pub fn load(path: impl AsRef<Path>) -> Result<Arc<Self>, SampleErr> {
+        use symphonia::core::audio::SampleBuffer;
+        use symphonia::core::codecs::DecoderOptions;
+        use symphonia::core::errors::Error;
+        use symphonia::core::formats::FormatOptions;
+        use symphonia::core::io::MediaSourceStream;
+        use symphonia::core::meta::MetadataOptions;
+        use symphonia::core::probe::Hint;
+
+        let path = path.as_ref();
+        let file = std::fs::File::open(path).map_err(SampleErr::Io)?;
+        let stream = MediaSourceStream::new(Box::new(file), Default::default());
+        let mut hint = Hint::new();
+        if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+            hint.with_extension(extension);
+        }
+        let probed = symphonia::default::get_probe()
+            .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())?;
+        let mut format = probed.format;
+        let track = format.default_track().ok_or(SampleErr::NoAudio)?;
+        let track_id = track.id;
+        let mut sample_rate = track.codec_params.sample_rate;
+        let mut decoder = symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+        let mut channels: Vec<Vec<f32>> = Vec::new();
+        loop {
+            let packet = match format.next_packet() {
+                Ok(packet) => packet,
+                Err(Error::IoError(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err.into()),
+            };
+            if packet.track_id() != track_id {
+                continue;
+            }
+            let decoded = match decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                // a corrupt packet is skipped, like players do
+                Err(Error::DecodeError(_)) => continue,
+                Err(err) => return Err(err.into()),
+            };
+
+            let spec = *decoded.spec();
+            sample_rate.get_or_insert(spec.rate);
+            if channels.is_empty() {
+                channels = vec![Vec::new(); spec.channels.count()];
+            }
+            let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+            buffer.copy_interleaved_ref(decoded);
+            let count = channels.len();
+            for (i, sample) in buffer.samples().iter().enumerate() {
+                channels[i % count].push(*sample);
+            }
+        }
+
+        match sample_rate {
+            Some(sample_rate) if !channels.is_empty() => Ok(Self::new(channels, sample_rate as f32)),
+            _ => Err(SampleErr::NoAudio),
+        }
+    }
+
+    // Loads on a new thread. Join the handle once the sample is needed
+    pub fn load_in_background(path: impl AsRef<Path>) -> JoinHandle<Result<Arc<Self>, SampleErr>> {
+        let path = path.as_ref().to_path_buf();
+        std::thread::spawn(move || Self::load(path))
+    }
+}
+
+#[cfg(feature = "audio-files")]
+#[derive(Debug)]
+pub enum SampleErr {
+    Io(std::io::Error),
+    Decode(symphonia::core::errors::Error),
+    // the file has no audio track, or its track has no samples
+    NoAudio,
+}
+
+#[cfg(feature = "audio-files")]
+impl From<symphonia::core::errors::Error> for SampleErr {
+    fn from(err: symphonia::core::errors::Error) -> Self {
+        SampleErr::Decode(err)
+    }
+}
+
+#[cfg(feature = "audio-files")]
+impl Display for SampleErr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SampleErr::Io(err) => write!(f, "Could not read the sample: {}", err),
+            SampleErr::Decode(err) => write!(f, "Could not decode the sample: {}", err),
+            SampleErr::NoAudio => write!(f, "The file has no audio to load"),
+        }
+    }
+}
+
+#[cfg(feature = "audio-files")]
+impl std::error::Error for SampleErr {}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SamplerCommand {
+    // Plays from the start, restarting if it's already playing
+    Play,
+    Stop,
+    Loop(bool),
+    // Semitones, added to the pitch port
+    Pitch(f32),
+}
+
+// Maps a runtime's events to sampler commands, for samplers set up with
+// Sampler::events
+pub trait SamplerEvent: Copy + 'static {
+    fn sampler_command(&self) -> Option<SamplerCommand>;
+}
+
+impl SamplerEvent for SamplerCommand {
+    fn sampler_command(&self) -> Option<SamplerCommand> {
+        Some(*self)
+    }
+}
+
+// Plays a Sample, resampled with cubic interpolation when its rate or the
+// pitch don't match the runtime's. Mono samples play on both outputs, and
+// channels past the second are ignored
+pub struct Sampler {
+    sample: Arc<Sample>,
+    looping: bool,
+    one_shot: bool,
+    map_event: fn(&dyn Any) -> Option<SamplerCommand>,
+}
+
+pub struct SamplerHandle;
+impl ProcessorHandle for SamplerHandle {}
+
+impl Sampler {
+    // Reacts to SamplerCommand events until told otherwise with events()
+    pub fn new(sample: Arc<Sample>) -> Self {
+        Self {
+            sample,
+            looping: false,
+            one_shot: false,
+            map_event: map_event::<SamplerCommand>,
+        }
+    }
+
+    // Reacts to the runtime's events through their SamplerEvent impl instead
+    pub fn events<Ev: SamplerEvent>(mut self) -> Self {
+        self.map_event = map_event::<Ev>;
+        self
+    }
+
+    // Loops until stopped instead of stopping at the end.
+    // SamplerCommand::Loop and the loop port override it
+    pub fn looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    // Plays to the end once the gate rises, whether or not it falls again
+    pub fn one_shot(mut self, one_shot: bool) -> Self {
+        self.one_shot = one_shot;
+        self
+    }
+
+    // The ports of the sampler added under this instance name
+    pub fn gate_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 0, TypeId::of::<Input>(), TypeId::of::<Sampler>())
+    }
+
+    pub fn pitch_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 1, TypeId::of::<Input>(), TypeId::of::<Sampler>())
+    }
+
+    pub fn loop_in(instance_name: &'static str) -> PortHandle<Input<'static>> {
+        PortHandle::new(instance_name, 2, TypeId::of::<Input>(), TypeId::of::<Sampler>())
+    }
+
+    pub fn left_out(instance_name: &'static str) -> PortHandle<Output<'static>> {
+        PortHandle::new(instance_name, 3, TypeId::of::<Output>(), TypeId::of::<Sampler>())
+    }
+
+    pub fn right_out(instance_name: &'static str) -> PortHandle<Output<'static>> {
+        PortHandle::new(instance_name, 4, TypeId::of::<Output>(), TypeId::of::<Sampler>())
+    }
+}
+
+fn map_event<Ev: SamplerEvent>(event: &dyn Any) -> Option<SamplerCommand> {
+    event.downcast_ref::<Ev>().and_then(Ev::sampler_command)
+}
+
+struct SamplerState {
+    sample: Arc<Sample>,
+    map_event: fn(&dyn Any) -> Option<SamplerCommand>,
+    one_shot: bool,
+    looping: bool,
+    playing: bool,
+    // in frames of the sample
+    position: f64,
+    // from SamplerCommand::Pitch
+    transpose: f32,
+    // the gate's last value, to find its edges
+    gate: f32,
+}
+
+impl SamplerState {
+    fn apply(&mut self, command: SamplerCommand) {
+        match command {
+            SamplerCommand::Play => {
+                self.playing = true;
+                self.position = 0.0;
+            },
+            SamplerCommand::Stop => self.playing = false,
+            SamplerCommand::Loop(looping) => self.looping = looping,
+            SamplerCommand::Pitch(semitones) => self.transpose = semitones,
+        }
+    }
+
+    fn set_gate(&mut self, gate: f32) {
+        let (was_high, high) = (self.gate >= 0.5, gate >= 0.5);
+        if high && !was_high {
+            self.apply(SamplerCommand::Play);
+        } else if was_high && !high && !self.one_shot {
+            self.apply(SamplerCommand::Stop);
+        }
+        self.gate = gate;
+    }
+
+    // Reads the current frame and moves on by step
+    fn next_frame(&mut self, looping: bool, step: f64) -> (f32, f32) {
+        let frames = self.sample.frames() as f64;
+        if !self.playing || frames == 0.0 {
+            return (0.0, 0.0);
+        }
+        if self.position >= frames {
+            if !looping {
+                self.playing = false;
+                return (0.0, 0.0);
+            }
+            self.position %= frames;
+        }
+
+        let left = self.read(0, looping);
+        let right = if self.sample.channel_count() > 1 { self.read(1, looping) } else { left };
+        self.position += step;
+        (left, right)
+    }
+
+    fn read(&self, channel: usize, looping: bool) -> f32 {
+        let samples = &self.sample.channels[channel];
+        let index = self.position.floor();
+        let fraction = (self.position - index) as f32;
+        let index = index as isize;
+        // a loop wraps around to its start, a one-off play is silent around it
+        let at = |offset: isize| {
+            let i = index + offset;
+            if looping {
+                samples[i.rem_euclid(samples.len() as isize) as usize]
+            } else {
+                usize::try_from(i).ok().and_then(|i| samples.get(i)).copied().unwrap_or(0.0)
+            }
+        };
+        hermite(fraction, at(-1), at(0), at(1), at(2))
+    }
+}
+
+// 4-point Catmull-Rom spline between y1 and y2
+fn hermite(t: f32, y0: f32, y1: f32, y2: f32, y3: f32) -> f32 {
+    let c1 = 0.5 * (y2 - y0);
+    let c2 = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+    let c3 = 0.5 * (y3 - y0) + 1.5 * (y1 - y2);
+    ((c3 * t + c2) * t + c1) * t + y1
+}
+
+impl Processor for Sampler {
+    type Handle = SamplerHandle;
+    fn buffers_count() -> usize { 5 }
+    fn slot_count() -> usize { 1 }
+    fn ports() -> &'static [PortDescriptor] {
+        const PORTS: &[PortDescriptor] = &[
+            PortDescriptor::input("gate")
+                .kind(PortKind::Control)
+                .range(0.0, 1.0)
+                .description("Plays from the start when it rises past 0.5, and stops when it falls back unless one-shot"),
+            PortDescriptor::input("pitch")
+                .kind(PortKind::Control)
+                .range(-48.0, 48.0)
+                .unit("st")
+                .description("Transposes playback"),
+            PortDescriptor::input("loop")
+                .kind(PortKind::Control)
+                .range(0.0, 1.0)
+                .description("Loops while above 0.5"),
+            PortDescriptor::output("left"),
+            PortDescriptor::output("right"),
+        ];
+        PORTS
+    }
+    fn call<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let gate = get_input(runtime, handle.buffer_ids_start);
+        let pitch = get_input(runtime, handle.buffer_ids_start + 1);
+        let loop_in = get_input(runtime, handle.buffer_ids_start + 2);
+        let mut left = get_output(runtime, handle.buffer_ids_start + 3);
+        let mut right = get_output(runtime, handle.buffer_ids_start + 4);
+        let mut state = get_state::<SamplerState, E>(runtime, handle.slot_ids_start);
+
+        let rate = state.sample.sample_rate as f64 / runtime.sample_rate() as f64;
+        let map_event = state.map_event;
+        let mut commands = events_of(runtime)
+            .filter_map(|(offset, event)| Some((offset, map_event(event)?)))
+            .peekable();
+
+        for i in 0..left.len() {
+            while let Some((_, command)) = commands.next_if(|(offset, _)| *offset <= i) {
+                state.apply(command);
+            }
+            if let Some(gate) = *gate {
+                state.set_gate(gate[i]);
+            }
+            let looping = loop_in.map_or(state.looping, |loop_in| loop_in[i] >= 0.5);
+            let semitones = pitch.map_or(0.0, |pitch| pitch[i]) + state.transpose;
+            let step = rate * (semitones as f64 / 12.0).exp2();
+            (left[i], right[i]) = state.next_frame(looping, step);
+        }
+    }
+    fn reset<E: Clone + Copy>(runtime: &Runtime<E>, handle: ContextHandle) {
+        let mut state = get_state::<SamplerState, E>(runtime, handle.slot_ids_start);
+        state.playing = false;
+        state.position = 0.0;
+        state.gate = 0.0;
+    }
+    fn create_states(&self) -> Vec<Box<UnsafeCell<dyn Any + Send + 'static>>> {
+        vec![Box::new(UnsafeCell::new(SamplerState {
+            sample: Arc::clone(&self.sample),
+            map_event: self.map_event,
+            one_shot: self.one_shot,
+            looping: self.looping,
+            playing: false,
+            position: 0.0,
+            transpose: 0.0,
+            gate: 0.0,
+        }))]
+    }
+    fn get_handle() -> SamplerHandle { SamplerHandle }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Builder;
+
+    fn build(sampler: Sampler, sample_rate: f32) -> (Runtime<SamplerCommand>, crate::Router<SamplerCommand>) {
+        let (runtime, router) = Builder::new()
+            .add_processor(sampler, "sampler")
+            .buffer_length(4)
+            .sample_rate(sample_rate)
+            .build();
+        router.route(Sampler::left_out("sampler"), output()).unwrap();
+        (runtime, router)
+    }
+
+    fn run<E: Clone + Copy>(runtime: &mut Runtime<E>, ticks: usize) -> Vec<f32> {
+        let mut out = Vec::new();
+        let mut block = [0.0; 4];
+        for _ in 0..ticks {
+            runtime.tick();
+            runtime.write_to(&mut block);
+            out.extend_from_slice(&block);
+        }
+        out
+    }
+
+    fn ramp() -> Arc<Sample> {
+        Sample::new(vec![vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]], 8.0)
+    }
+
+    #[test]
+    fn test_plays_once_and_stops() {
+        let (mut runtime, router) = build(Sampler::new(ramp()), 8.0);
+        assert_eq!(run(&mut runtime, 1), [0.0; 4]);
+
+        router.send_event(SamplerCommand::Play);
+        assert_eq!(run(&mut runtime, 2), [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 0.0, 0.0]);
+
+        router.send_event(SamplerCommand::Play);
+        run(&mut runtime, 1);
+        router.send_event(SamplerCommand::Stop);
+        assert_eq!(run(&mut runtime, 1), [0.0; 4]);
+    }
+
+    #[test]
+    fn test_pitch_and_looping() {
+        let (mut runtime, router) = build(Sampler::new(ramp()).looping(true), 8.0);
+        router.send_event(SamplerCommand::Pitch(12.0));
+        router.send_event(SamplerCommand::Play);
+        assert_eq!(run(&mut runtime, 2), [1.0, 3.0, 5.0, 1.0, 3.0, 5.0, 1.0, 3.0]);
+    }
+
+    #[test]
+    fn test_resamples_to_runtime_rate() {
+        // at twice the sample's rate every other frame is interpolated
+        let (mut runtime, router) = build(Sampler::new(ramp()), 16.0);
+        router.send_event(SamplerCommand::Play);
+        let out = run(&mut runtime, 2);
+        for (found, expected) in out[2..7].iter().zip([2.0, 2.5, 3.0, 3.5, 4.0]) {
+            assert!((found - expected).abs() < 1e-6, "{} != {}", found, expected);
+        }
+    }
+
+    #[test]
+    fn test_gate_and_one_shot() {
+        let ones = Sample::new(vec![vec![1.0; 16]], 8.0);
+        for (one_shot, expected) in [(false, [0.0; 4]), (true, [1.0; 4])] {
+            let (mut runtime, router) = build(Sampler::new(ones.clone()).one_shot(one_shot), 8.0);
+            router.set_parameter(Sampler::gate_in("sampler"), 1.0).unwrap();
+            assert_eq!(run(&mut runtime, 1), [1.0; 4]);
+
+            // the gate ramps down over the first block, and is low by the second
+            router.set_parameter(Sampler::gate_in("sampler"), 0.0).unwrap();
+            let out = run(&mut runtime, 2);
+            assert_eq!(out[4..], expected, "one_shot: {}", one_shot);
+        }
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    enum Note {
+        On,
+        Off,
+    }
+
+    impl SamplerEvent for Note {
+        fn sampler_command(&self) -> Option<SamplerCommand> {
+            match self {
+                Note::On => Some(SamplerCommand::Play),
+                Note::Off => None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_mapped_events() {
+        let (mut runtime, router) = Builder::<Note>::new()
+            .add_processor(Sampler::new(ramp()).events::<Note>(), "sampler")
+            .buffer_length(4)
+            .sample_rate(8.0)
+            .build();
+        router.route(Sampler::right_out("sampler"), output()).unwrap();
+        router.send_event(Note::Off);
+        assert_eq!(run(&mut runtime, 1), [0.0; 4]);
+        router.send_event(Note::On);
+        assert_eq!(run(&mut runtime, 1), [1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[cfg(feature = "audio-files")]
+    #[test]
+    fn test_load_aiff() {
+        // 16 bit stereo AIFF, two frames
+        let frames: [i16; 4] = [16384, -16384, 8192, 0];
+        let mut sound = Vec::new();
+        sound.extend_from_slice(&[0; 8]); // offset and block size
+        for sample in frames {
+            sound.extend_from_slice(&sample.to_be_bytes());
+        }
+        let mut comm = Vec::new();
+        comm.extend_from_slice(&2u16.to_be_bytes());
+        comm.extend_from_slice(&2u32.to_be_bytes());
+        comm.extend_from_slice(&16u16.to_be_bytes());
+        // 44100 as an 80 bit extended float
+        comm.extend_from_slice(&[0x40, 0x0E, 0xAC, 0x44, 0, 0, 0, 0, 0, 0]);
+
+        let mut file = b"FORM".to_vec();
+        file.extend_from_slice(&(4 + 8 + comm.len() as u32 + 8 + sound.len() as u32).to_be_bytes());
+        file.extend_from_slice(b"AIFF");
+        file.extend_from_slice(b"COMM");
+        file.extend_from_slice(&(comm.len() as u32).to_be_bytes());
+        file.extend_from_slice(&comm);
+        file.extend_from_slice(b"SSND");
+        file.extend_from_slice(&(sound.len() as u32).to_be_bytes());
+        file.extend_from_slice(&sound);
+
+        let dir = crate::core::test_processors::test_dir("sampler_load_aiff");
+        let path = dir.join("load.aiff");
+        std::fs::write(&path, file).unwrap();
+        let sample = Sample::load_in_background(&path).join().unwrap().unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(sample.sample_rate(), 44_100.0);
+        assert_eq!(sample.channel(0), Some(&[0.5, 0.25][..]));
+        assert_eq!(sample.channel(1), Some(&[-0.5, 0.0][..]));
+    }
+
+    #[cfg(all(feature = "audio-files", feature = "wav"))]
+    #[test]
+    fn test_load_wav() {
+        use crate::core::wav::{write_wav, WavFormat};
+        let dir = crate::core::test_processors::test_dir("sampler_load_wav");
+        let path = dir.join("load.wav");
+        write_wav(&path, &[&[0.5, -0.25, 1.0]], 22_050, WavFormat::Float32).unwrap();
+        let sample = Sample::load(&path).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(sample.sample_rate(), 22_050.0);
+        assert_eq!(sample.channel_count(), 1);
+        assert_eq!(sample.channel(0), Some(&[0.5, -0.25, 1.0][..]));
+    }
+
+    #[cfg(feature = "audio-files")]
+    #[test]
+    fn test_load_errors() {
+        let dir = crate::core::test_processors::test_dir("sampler_load_errors");
+        assert!(matches!(Sample::load(dir.join("missing.wav")), Err(SampleErr::Io(_))));
+
+        let path = dir.join("garbage.wav");
+        std::fs::write(&path, b"not audio at all").unwrap();
+        assert!(matches!(Sample::load(&path), Err(SampleErr::Decode(_))));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}